@@ -0,0 +1,202 @@
+// #[derive(RluObj)] for structs with an RluObjHdr<Self> field. The generated impl copies every
+// other field (which must be Clone) into write log copies and back into the original on
+// writeback, so objects no longer need a hand written, unsafe RluObj impl. Paths go through
+// ::rlu unless the struct says otherwise with #[rlu(crate = "path")], which the rlu crate
+// itself uses as #[rlu(crate = "crate")].
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Path, Type};
+
+#[proc_macro_derive(RluObj, attributes(rlu))]
+pub fn derive_rlu_obj(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn is_rlu_obj_hdr(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "RluObjHdr"),
+        _ => false,
+    }
+}
+
+// The path of the rlu crate, from an optional #[rlu(crate = "...")] attribute
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+    let mut path = syn::parse_quote!(::rlu);
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("rlu")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                path = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unknown rlu attribute"))
+            }
+        })?;
+    }
+    Ok(path)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let krate = crate_path(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "RluObj can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "RluObj can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut hdr = None;
+    let mut data_fields = Vec::new();
+    for field in fields.iter() {
+        if is_rlu_obj_hdr(&field.ty) {
+            if hdr.is_some() {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "RluObj needs exactly one RluObjHdr field",
+                ));
+            }
+            hdr = field.ident.clone();
+        } else {
+            data_fields.push(field);
+        }
+    }
+    let hdr = match hdr {
+        Some(hdr) => hdr,
+        None => {
+            return Err(syn::Error::new(
+                input.span(),
+                "RluObj needs a field of type RluObjHdr<Self>",
+            ))
+        }
+    };
+    let data_names: Vec<_> = data_fields.iter().map(|field| &field.ident).collect();
+    let data_types: Vec<_> = data_fields.iter().map(|field| &field.ty).collect();
+
+    let mut generics = input.generics.clone();
+    {
+        let where_clause = generics.make_where_clause();
+        for ty in data_types.iter() {
+            where_clause.predicates.push(syn::parse_quote!(#ty: ::std::clone::Clone));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::RluObj for #name #ty_generics #where_clause {
+            fn get_p_obj_copy(&self) -> *mut Self {
+                self.#hdr.p_obj_copy.load(::std::sync::atomic::Ordering::Acquire)
+            }
+
+            fn is_locked(&self) -> bool {
+                !self.get_p_obj_copy().is_null()
+            }
+
+            fn is_copy(&self) -> bool {
+                self.get_p_obj_copy() == #krate::PTR_ID_OBJ_COPY as *mut Self
+            }
+
+            fn has_ws_hdr(&self) -> bool {
+                self.#hdr.ws_hdr.is_some()
+            }
+
+            fn get_p_original(&self) -> *mut Self {
+                match &self.#hdr.ws_hdr {
+                    Some(ws_hdr) => ws_hdr.p_obj_actual,
+                    None => self as *const Self as *mut Self,
+                }
+            }
+
+            fn get_locking_thread_from_ws_obj(&self) -> usize {
+                match &self.#hdr.ws_hdr {
+                    Some(ws_hdr) => ws_hdr.thread_id,
+                    None => usize::MAX, //not a copy, rlu_dereference reports it as corrupted
+                }
+            }
+
+            fn get_ws_run_counter(&self) -> u64 {
+                match &self.#hdr.ws_hdr {
+                    Some(ws_hdr) => ws_hdr.run_counter,
+                    None => 0,
+                }
+            }
+
+            fn get_copy_with_ws_hdr(&self, run_counter: u64, thread_id: usize) -> Self {
+                #name {
+                    #hdr: #krate::RluObjHdr::new_copy(self.get_p_original(), run_counter, thread_id),
+                    #(#data_names: ::std::clone::Clone::clone(&self.#data_names),)*
+                }
+            }
+
+            fn cas(&self, new_obj: *mut Self) -> bool {
+                self.#hdr
+                    .p_obj_copy
+                    .compare_exchange(
+                        ::std::ptr::null_mut(),
+                        new_obj,
+                        ::std::sync::atomic::Ordering::AcqRel,
+                        ::std::sync::atomic::Ordering::Relaxed,
+                    )
+                    .is_ok()
+            }
+
+            fn store_copy(&self, new_obj: *mut Self) {
+                self.#hdr
+                    .p_obj_copy
+                    .store(new_obj, ::std::sync::atomic::Ordering::Release);
+            }
+
+            fn copy_back_to_original(&self) {
+                let p_original = self.get_p_original();
+                // Safety: only called on write log copies, whose original stays allocated
+                // (and locked by us) until writeback is done. clone_from lets fields reuse
+                // what the original already owns.
+                unsafe {
+                    #(::std::clone::Clone::clone_from(&mut (*p_original).#data_names, &self.#data_names);)*
+                }
+                self.unlock_original();
+            }
+
+            fn unlock_original(&self) {
+                let p_original = self.get_p_original();
+                unsafe {
+                    (*p_original)
+                        .#hdr
+                        .p_obj_copy
+                        .store(::std::ptr::null_mut(), ::std::sync::atomic::Ordering::Release);
+                }
+            }
+
+            fn unlock(&self) {
+                self.unlock_original();
+            }
+
+            fn get_version_chain(&self) -> &#krate::RluVersionChain<Self> {
+                &self.#hdr.p_version
+            }
+        }
+    })
+}
@@ -1,9 +1,8 @@
 use std::time::Instant;
 use std::thread;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
-use rlu::BPlusTree;  
+use rlu::{BPlusTree, RluInspectDumper};
 use rlu::BPTree as RegularBPlusTree;
-use prettytable::{Table, row};
 use std::collections::BTreeMap;
 
 
@@ -19,15 +18,11 @@ fn populate_tree<T: BPlusTreeTrait>(mut tree: T) -> T {
 // Common trait to handle both tree types
 trait BPlusTreeTrait {
     fn insert(&mut self, key: i32, value: i32);
-    fn search(&self, key: &i32) -> Option<i32>;
 }
 
 impl BPlusTreeTrait for BPlusTree<i32, i32> {
     fn insert(&mut self, key: i32, value: i32) {
-        self.insert(key, value);
-    }
-    fn search(&self, key: &i32) -> Option<i32> {
-        self.search(key)
+        BPlusTree::insert(self, key, value); //the tree's own insert, which takes &self
     }
 }
 
@@ -35,9 +30,6 @@ impl BPlusTreeTrait for RegularBPlusTree<i32, i32> {
     fn insert(&mut self, key: i32, value: i32) {
         self.insert(key, value);
     }
-    fn search(&self, key: &i32) -> Option<i32> {
-        self.search(key)
-    }
 }
 
 fn bench_rlu_bptree(num_threads: usize, num_searches: usize) -> u128 {
@@ -57,12 +49,19 @@ fn bench_rlu_bptree(num_threads: usize, num_searches: usize) -> u128 {
         })
     };
 
+    // RLU_INSPECT_INTERVAL=<ms> dumps the tree's RLU state to stderr while the run is going,
+    // RLU_INSPECT=text or RLU_INSPECT=json once after it
+    let inspected = tree.clone_ref();
+    let dumper = RluInspectDumper::new(format!("rlu,{}", num_threads), move || inspected.inspect());
     let readers: Vec<_> = (0..num_threads).map(|_| worker()).collect();
     for t in readers {
         t.join().unwrap();
     }
+    let elapsed = start.elapsed().as_millis();
+    drop(dumper);
 
-    start.elapsed().as_millis()
+    tree.inspect().dump_requested(&format!("rlu,{}", num_threads));
+    elapsed
 }
 
 fn bench_regular_bptree(num_threads: usize, num_searches: usize) -> u128 {
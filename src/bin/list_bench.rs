@@ -0,0 +1,71 @@
+// Coarse against fine grained writers on RluSet, the comparison the Java version makes between
+// RluCoarseList and RluFineList. Coarse writers take turns on the domain's writer lock and
+// never abort, fine ones lock only the nodes they change and retry when they lose a race.
+//
+//     cargo run --release --bin list_bench > list.csv
+//
+// benchmark-results/list.csv is one run, taken on a single core, where the threads never
+// run at the same time. Fine locking came out ahead everywhere but the 50% write mix at 4 and
+// 8 threads, and the run says nothing about contention between cores, which is where coarse
+// writers are expected to pay off; that needs a run on a machine with enough of them.
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rlu::{ConcurrentSet, GlobalRlu, RluLocking, RluSet};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+const OPS_PER_THREAD: usize = 100_000;
+const KEY_RANGE: u32 = 256;
+
+// Operations per millisecond over all threads, on a set kept about half full. Writes are
+// split evenly between inserts and deletes.
+fn bench(locking: RluLocking, num_threads: usize, write_frac: f64) -> f64 {
+    let set: Arc<RluSet<u32>> = Arc::new(RluSet::with_builder(
+        GlobalRlu::builder()
+            .max_threads(num_threads + 1)
+            .locking(locking),
+    ));
+    for key in (0..KEY_RANGE).step_by(2) {
+        set.insert(key);
+    }
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..num_threads)
+        .map(|t| {
+            let set = set.clone();
+            thread::spawn(move || {
+                let mut rng = SmallRng::from_seed([t as u8; 16]);
+                for _ in 0..OPS_PER_THREAD {
+                    let key = rng.gen_range(0, KEY_RANGE);
+                    if rng.gen::<f64>() >= write_frac {
+                        set.contains(key);
+                    } else if rng.gen() {
+                        set.insert(key);
+                    } else {
+                        set.delete(key);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    (num_threads * OPS_PER_THREAD) as f64 / elapsed
+}
+
+fn main() {
+    println!("locking,num_threads,write_frac,throughput");
+    for &locking in &[RluLocking::Coarse, RluLocking::Fine] {
+        for &write_frac in &[0.02, 0.2, 0.5] {
+            for &num_threads in &[1, 2, 4, 8] {
+                let runs: Vec<f64> = (0..3)
+                    .map(|_| bench(locking, num_threads, write_frac))
+                    .collect();
+                let avg = runs.iter().sum::<f64>() / runs.len() as f64;
+                println!("{:?},{},{},{:.1}", locking, num_threads, write_frac, avg);
+            }
+        }
+    }
+}
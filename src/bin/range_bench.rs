@@ -2,11 +2,11 @@ use std::time::Instant;
 use std::thread;
 use std::collections::BTreeMap;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
-use rlu::BPlusTree;  
+use rlu::{BPlusTree, RluInspectDumper};
 use rlu::BPTree as RegularBPlusTree;
 
 fn populate_trees() -> (BPlusTree<i32, i32>, RegularBPlusTree<i32, i32>, BTreeMap<i32, i32>) {
-    let mut rlu_tree = BPlusTree::new();
+    let rlu_tree = BPlusTree::new();
     let mut seq_tree = RegularBPlusTree::new();
     let mut btree_map = BTreeMap::new();
     
@@ -36,12 +36,19 @@ fn bench_rlu_range(num_threads: usize, num_queries: usize) -> u128 {
         })
     };
 
+    // RLU_INSPECT_INTERVAL=<ms> dumps the tree's RLU state to stderr while the run is going,
+    // RLU_INSPECT=text or RLU_INSPECT=json once after it
+    let inspected = tree.clone_ref();
+    let dumper = RluInspectDumper::new(format!("rlu,{}", num_threads), move || inspected.inspect());
     let readers: Vec<_> = (0..num_threads).map(|_| worker()).collect();
     for t in readers {
         t.join().unwrap();
     }
+    let elapsed = start.elapsed().as_millis();
+    drop(dumper);
 
-    start.elapsed().as_millis()
+    tree.inspect().dump_requested(&format!("rlu,{}", num_threads));
+    elapsed
 }
 
 fn bench_sequential_range(num_queries: usize) -> u128 {
@@ -0,0 +1,114 @@
+// Section throughput as threads are added, to see false sharing between RLU threads. Every
+// section writes its thread's run_counter and local_clock, and every commit reads those of
+// all other threads, so a thread's shared counters sitting on the same cache lines as another
+// thread's private state (or the global clock) costs more with every thread. Run it on two
+// builds and compare from 8 threads up, on a machine with at least that many cores:
+//
+//     cargo run --release --bin sharing_bench > after.csv
+//     python3 bench_plot.py before.csv before after.csv after
+//
+// benchmark-results/sharing_before.csv and sharing_after.csv are such a pair, from the layout
+// before and after the counters got cache lines of their own. They were taken on a single core,
+// where threads never run at the same time and no false sharing can show, so they only check
+// that the padding costs nothing there.
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rlu::{GlobalRlu, RluHandle, RluInspectDumper, RluObj, RluObjHdr};
+use std::thread;
+use std::time::Instant;
+
+// Each slot has cache lines of its own, so that a writer's slot does not share them with the
+// slots its neighbours write, and the RLU layout is all that is left to measure
+#[derive(RluObj)]
+#[repr(align(128))]
+pub struct Slot {
+    hdr: RluObjHdr<Slot>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Slots(*mut Slot, usize);
+
+unsafe impl Send for Slots {}
+
+// The domain, for the thread that inspects it during a run
+struct Domain(*mut GlobalRlu<Slot>);
+
+unsafe impl Send for Domain {}
+
+impl Slots {
+    fn get(&self, i: usize) -> *mut Slot {
+        unsafe { self.0.add(i) }
+    }
+}
+
+const SECTIONS_PER_THREAD: usize = 200_000;
+
+// Sections per millisecond over all threads. Each thread writes its own slot, so commits
+// only wait for readers and never conflict.
+fn bench(num_threads: usize, write_frac: f64) -> f64 {
+    let rlu_ptr: *mut GlobalRlu<Slot> = GlobalRlu::builder().max_threads(num_threads).init_rlu();
+    let slots: Vec<Slot> = (0..num_threads)
+        .map(|_| Slot {
+            hdr: RluObjHdr::new(),
+            value: 0,
+        })
+        .collect();
+    let slots = Slots(Box::into_raw(slots.into_boxed_slice()) as *mut Slot, num_threads);
+
+    let handles: Vec<_> = (0..num_threads).map(|_| unsafe { RluHandle::new(rlu_ptr) }).collect();
+
+    // RLU_INSPECT_INTERVAL=<ms> dumps the domain's state to stderr while the run is going,
+    // RLU_INSPECT=text or RLU_INSPECT=json once after it
+    let domain = Domain(rlu_ptr);
+    let dumper = RluInspectDumper::new(format!("{},{}", num_threads, write_frac), move || {
+        let domain = &domain;
+        unsafe { (*domain.0).inspect() }
+    });
+    let start = Instant::now();
+    let workers: Vec<_> = handles
+        .into_iter()
+        .enumerate()
+        .map(|(t, handle)| {
+            thread::spawn(move || {
+                let slots = slots;
+                let mut rng = SmallRng::from_seed([t as u8; 16]);
+                let mut sum = 0;
+                for _ in 0..SECTIONS_PER_THREAD {
+                    if rng.gen::<f64>() < write_frac {
+                        let guard = handle.write();
+                        let slot = guard.deref(slots.get(t)).unwrap();
+                        guard.try_lock(slot).unwrap().unwrap().value += 1;
+                    } else {
+                        let guard = handle.read();
+                        let i = rng.gen_range(0, slots.1);
+                        sum += guard.deref(slots.get(i)).unwrap().value;
+                    }
+                }
+                sum
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    drop(dumper);
+
+    unsafe { (*rlu_ptr).inspect() }.dump_requested(&format!("{},{}", num_threads, write_frac));
+    unsafe {
+        rlu::rlu_destroy(rlu_ptr).unwrap();
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(slots.0, slots.1)));
+    }
+    (num_threads * SECTIONS_PER_THREAD) as f64 / elapsed
+}
+
+fn main() {
+    println!("num_threads,write_frac,throughput");
+    for &write_frac in &[0.0, 0.01, 0.1] {
+        for &num_threads in &[1, 2, 4, 8, 16, 32] {
+            let runs: Vec<f64> = (0..3).map(|_| bench(num_threads, write_frac)).collect();
+            let avg = runs.iter().sum::<f64>() / runs.len() as f64;
+            println!("{},{},{:.1}", num_threads, write_frac, avg);
+        }
+    }
+}
@@ -1,5 +1,4 @@
-use core::{fmt, panic};
-use std::fmt::write;
+use core::fmt;
 
 
 const ORDER: usize = 4;
@@ -76,10 +75,10 @@ impl<K: Ord + Clone + fmt::Debug + Ord , V: Clone + fmt::Debug + Ord > BPTree<K,
                 })
             );
             // Then create new root with the old root and new node as children
-            self.root = Box::new(BPTreeNode::InternalNode {
+            *self.root = BPTreeNode::InternalNode {
                 keys: vec![promoted_key],
                 children: vec![old_root, new_node],
-            });
+            };
         }
     }
 
@@ -100,7 +99,7 @@ impl<K: Ord + Clone + fmt::Debug + Ord , V: Clone + fmt::Debug + Ord > BPTree<K,
 
             let middle_key = keys[mid -1 ].clone();
 
-            self.root = Box::new(
+            *self.root =
                 BPTreeNode::InternalNode { 
                     keys: vec![middle_key],
                     children: vec![
@@ -112,8 +111,7 @@ impl<K: Ord + Clone + fmt::Debug + Ord , V: Clone + fmt::Debug + Ord > BPTree<K,
                             }
                         )
                     ],
-                }
-            );
+                };
         }
     }
 
@@ -157,7 +155,7 @@ impl<K: Ord + Clone + fmt::Debug + Ord , V: Clone + fmt::Debug + Ord > BPTree<K,
                 }
             }
             if let Some(next_node) = next {
-                current_node = &*next_node;
+                current_node = next_node;
             } else {
                 break; // No more leaf nodes
             }
@@ -173,6 +171,12 @@ impl<K: Ord + Clone + fmt::Debug + Ord , V: Clone + fmt::Debug + Ord > BPTree<K,
 
 }
 
+impl<K: Ord + Clone + fmt::Debug, V: Clone + fmt::Debug + Ord> Default for BPTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl <K: fmt::Debug, V: fmt::Debug> fmt::Debug for BPTreeNode<K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
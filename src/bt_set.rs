@@ -15,6 +15,15 @@ where
     }
 }
 
+impl<T> Default for ConcurrentBTreeSet<T>
+where
+    T: Ord + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> ConcurrentSet<T> for ConcurrentBTreeSet<T>
 where
     T: Ord + Send + Sync,
@@ -4,6 +4,11 @@ pub trait ConcurrentSet<T>: Send + Sync {
     // Returns the number of elements in the set
     fn len(&self) -> usize;
 
+    // Returns true if the set has no elements
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     // Returns true if the value is contained in the set
     fn contains(&self, value: T) -> bool;
 
@@ -1,4 +1,15 @@
 mod rlu;
+mod rlu_error;
+mod rlu_guard;
+mod rlu_inspect;
+mod rlu_local;
+mod rlu_stats;
+mod rlu_debug;
+mod rlu_sync;
+mod rlu_txn;
+mod rlu_watchdog;
+mod rlu_group;
+mod rlu_cell;
 mod concurrent_set;
 mod bt_set;
 mod rlu_set;
@@ -7,7 +18,18 @@ mod rlu_bptree;
 
 pub use crate::concurrent_set::*;
 pub use crate::bt_set::*;
-pub use crate::rlu_set::*;
+pub use crate::rlu_set::{rlu_new_node, Node, RluSet};
 pub use crate::rlu::*;
+pub use crate::rlu_error::*;
+pub use crate::rlu_guard::*;
+pub use crate::rlu_inspect::*;
+pub use crate::rlu_local::*;
+pub use crate::rlu_txn::*;
+pub use crate::rlu_watchdog::*;
+pub use crate::rlu_group::*;
+#[cfg(feature = "stats")]
+pub use crate::rlu_stats::RluStats;
+pub use crate::rlu_cell::*;
 pub use crate::bptree::*;
-pub use crate::rlu_bptree::*;
+pub use crate::rlu_bptree::{BPlusTree, Node as BPlusTreeNode};
+pub use rlu_derive::RluObj;
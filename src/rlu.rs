@@ -1,13 +1,56 @@
 // Author: Hudson Ayers
 
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Deref, Range};
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
 
-const RLU_MAX_LOG_SIZE: usize = 128;
+#[cfg(feature = "debug-checks")]
+use crate::rlu_debug::{rlu_violation, RluViolation};
+use crate::rlu_error::RluError;
+use crate::rlu_inspect::{RluSnapshot, RluThreadSnapshot};
+#[cfg(feature = "stats")]
+use crate::rlu_stats::RluStats;
+use crate::rlu_stats::{RluCounter, RluThreadStats, RluTimer};
+use crate::rlu_sync::{
+    fence, hint, thread, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Condvar, Mutex,
+};
+use crate::rlu_txn::{Backoff, ContentionPolicy};
+use crate::rlu_watchdog::{RluBlockingThread, RluStallReport, RluWaitKind};
+
+// Defaults, GlobalRlu::builder() can change them per domain. The write log and free list
+// start out this big and grow when a section needs more.
+pub const RLU_MAX_LOG_SIZE: usize = 128;
 pub const RLU_MAX_THREADS: usize = 32;
-const RLU_MAX_FREE_NODES: usize = 100;
+pub const RLU_MAX_FREE_NODES: usize = 100;
 pub const PTR_ID_OBJ_COPY: usize = 0x12341234;
+// Waits between looks at the clock, for a writer with a sync timeout
+const RLU_STALL_CHECK_INTERVAL: u64 = 64;
+// Owner of a coarse domain's writer lock while no thread has it
+const RLU_NO_WRITER: usize = usize::MAX;
+
+// Aligns its value to 128 bytes, so nothing else shares its cache lines. Two lines rather
+// than one, since some CPUs prefetch lines in pairs.
+#[derive(Default)]
+#[repr(align(128))]
+pub(crate) struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub(crate) fn new(value: T) -> CachePadded<T> {
+        CachePadded(value)
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
 
 #[derive(Debug)]
 pub struct WsHdr<T: RluObj> {
@@ -16,10 +59,27 @@ pub struct WsHdr<T: RluObj> {
     pub thread_id: usize,
 }
 
+// A thread's write log. It has two halves: one for the section in progress (and, in deferral
+// mode, the sections before it that are not committed yet), and one holding the copies of the
+// last commit, which readers may still be inside (see end of 3.5 in paper).
+// Each half is a list of fixed size chunks, so it can grow without moving a copy that a
+// reader or a locked original points to.
 pub struct ObjList<T: RluObj> {
-    pub num_of_objs: usize,
-    pub cur_pos: usize,
-    pub buffer: [Option<T>; RLU_MAX_LOG_SIZE],
+    pub num_of_objs: usize, //copies locked by the current section, they end at cur_pos
+    pub cur_pos: usize,     //next free entry of the current half
+    cur_half: usize,
+    halves: [Vec<Box<[Option<T>]>>; 2],
+    chunk_size: usize,
+    max_half_size: usize,
+}
+
+impl<T> Default for ObjList<T>
+where
+    T: RluObj,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> ObjList<T>
@@ -27,22 +87,105 @@ where
     T: RluObj,
 {
     pub fn new() -> ObjList<T> {
+        ObjList::with_size(RLU_MAX_LOG_SIZE)
+    }
+
+    pub fn with_size(log_size: usize) -> ObjList<T> {
+        ObjList::with_sizes(log_size, usize::MAX)
+    }
+
+    pub fn with_sizes(log_size: usize, max_log_size: usize) -> ObjList<T> {
+        let chunk_size = std::cmp::max(log_size / 2, 1);
         ObjList {
             num_of_objs: 0,
             cur_pos: 0,
-            buffer: [
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None,
+            cur_half: 0,
+            halves: [
+                vec![ObjList::new_chunk(chunk_size)],
+                vec![ObjList::new_chunk(chunk_size)],
             ],
+            chunk_size,
+            max_half_size: max_log_size / 2,
+        }
+    }
+
+    fn new_chunk(chunk_size: usize) -> Box<[Option<T>]> {
+        (0..chunk_size).map(|_| None).collect()
+    }
+
+    // Entry pos of the current half
+    pub fn get(&self, pos: usize) -> Option<&T> {
+        self.halves[self.cur_half]
+            .get(pos / self.chunk_size)
+            .and_then(|chunk| chunk[pos % self.chunk_size].as_ref())
+    }
+
+    // Copies locked by the current section
+    pub fn section_objs(&self) -> impl Iterator<Item = &T> {
+        ((self.cur_pos - self.num_of_objs)..self.cur_pos).map(move |pos| self.get(pos).unwrap())
+    }
+
+    // Copies the next commit writes back: the current section's and those of deferred ones
+    pub fn pending_objs(&self) -> impl Iterator<Item = &T> {
+        (0..self.cur_pos).map(move |pos| self.get(pos).unwrap())
+    }
+
+    // Deferred sections have filled the space the current half started out with
+    fn is_under_pressure(&self) -> bool {
+        self.cur_pos >= self.chunk_size
+    }
+
+    pub fn has_copies(&self) -> bool {
+        self.halves
+            .iter()
+            .flat_map(|half| half.iter())
+            .any(|chunk| chunk.iter().any(|obj| obj.is_some()))
+    }
+
+    // Stores obj in the next free entry, growing the current half if needed. The entry only
+    // becomes part of the section once push_reserved() is called.
+    fn reserve(&mut self, obj: T) -> Result<*mut T, RluError> {
+        if self.cur_pos >= self.max_half_size {
+            return Err(RluError::WriteLogFull);
+        }
+        let half = &mut self.halves[self.cur_half];
+        if self.cur_pos == half.len() * self.chunk_size {
+            half.push(ObjList::new_chunk(self.chunk_size));
         }
+        let entry = &mut half[self.cur_pos / self.chunk_size][self.cur_pos % self.chunk_size];
+        *entry = Some(obj);
+        Ok(entry.as_mut().unwrap())
+    }
+
+    fn push_reserved(&mut self) {
+        self.cur_pos += 1;
+        self.num_of_objs += 1;
+    }
+
+    // Drops the current section's entries from the mark-th on, only valid while nobody can
+    // have stolen them
+    fn discard_section_since(&mut self, mark: usize) {
+        self.cur_pos -= self.num_of_objs - mark;
+        self.num_of_objs = mark;
+    }
+
+    // Clears the other half and makes it current. Its copies are from the commit before the
+    // last one, and no reader can remain inside them.
+    fn swap(&mut self) {
+        let other = 1 - self.cur_half;
+        for chunk in self.halves[other].iter_mut() {
+            for obj in chunk.iter_mut() {
+                *obj = None;
+            }
+        }
+        self.cur_half = other;
+        self.cur_pos = 0;
+    }
+
+    fn clear(&mut self) {
+        self.swap();
+        self.swap();
+        self.num_of_objs = 0;
     }
 }
 
@@ -52,15 +195,47 @@ pub struct WaitEntry {
     run_counter: u64,
 }
 
-pub struct RluThread<T: RluObj> {
-    is_writer: bool,
-    pub wlog: ObjList<T>,
+// The part of a thread that other threads read on every synchronize, dereference and lock
+// attempt. It has cache lines of its own, so the owner's writes to its private state below
+// do not keep invalidating them for everyone else.
+struct RluThreadShared {
     run_counter: AtomicU64, //odd = active, even = inactive
     local_clock: AtomicU64,
     write_clock: AtomicU64,
-    q_threads: [WaitEntry; RLU_MAX_THREADS], //pre-allocated storage for checking thread status
-    free_nodes: [*mut T; RLU_MAX_FREE_NODES],
-    free_nodes_size: usize,
+    sync_requested: AtomicBool, //set by threads that want one of our deferred locks
+    //copies of the owner's private counters, published for GlobalRlu::inspect
+    is_writer: AtomicBool,
+    cur_pos: AtomicUsize,
+    num_of_objs: AtomicUsize, //write log copies, or versions in MV mode
+    pending_frees: AtomicUsize,
+    pending_callbacks: AtomicUsize,
+}
+
+pub struct RluThread<T: RluObj> {
+    shared: CachePadded<RluThreadShared>,
+    is_writer: bool,
+    pub wlog: ObjList<T>,
+    q_threads: Vec<WaitEntry>, //pre-allocated storage for checking thread status
+    free_nodes: Vec<*mut T>,
+    max_free_nodes: usize,
+    deferred: Vec<Box<dyn FnOnce() + Send>>, //callbacks waiting for a grace period, see rlu_defer
+    deferred_mark: usize,                    //how many were queued before the current section
+    free_mark: usize,                        //free_nodes entries from before the current section
+    deferred_sections: usize, //writer sections whose commit is deferred, see rlu_defer_commit
+    nesting: usize,           //sections opened inside the outermost one and not yet closed
+    inner_abort: bool,        //an inner section aborted, so the outermost one cannot commit
+    mv_wset: Vec<*mut RluVersion<T>>, //versions locked by the current section (MV mode)
+    mv_spare: Vec<*mut RluVersion<T>>, //versions from aborted sections, reused by try_lock
+    stats: RluThreadStats,
+}
+
+impl<T> Default for RluThread<T>
+where
+    T: RluObj,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> RluThread<T>
@@ -68,23 +243,94 @@ where
     T: RluObj,
 {
     pub fn new() -> RluThread<T> {
+        RluThread::with_capacities(RLU_MAX_THREADS, RLU_MAX_LOG_SIZE, usize::MAX, usize::MAX)
+    }
+
+    pub fn with_capacities(
+        max_threads: usize,
+        log_size: usize,
+        max_log_size: usize,
+        max_free_nodes: usize,
+    ) -> RluThread<T> {
         RluThread {
+            shared: CachePadded::new(RluThreadShared {
+                run_counter: AtomicU64::new(0),
+                local_clock: AtomicU64::new(0),
+                write_clock: AtomicU64::new(u64::MAX),
+                sync_requested: AtomicBool::new(false),
+                is_writer: AtomicBool::new(false),
+                cur_pos: AtomicUsize::new(0),
+                num_of_objs: AtomicUsize::new(0),
+                pending_frees: AtomicUsize::new(0),
+                pending_callbacks: AtomicUsize::new(0),
+            }),
             is_writer: false,
-            wlog: ObjList::new(),
-            run_counter: AtomicU64::new(0),
-            local_clock: AtomicU64::new(0),
-            write_clock: AtomicU64::new(std::u64::MAX),
-            q_threads: [WaitEntry {
-                is_wait: false,
-                run_counter: 0,
-            }; RLU_MAX_THREADS],
-            free_nodes: [ptr::null_mut(); RLU_MAX_FREE_NODES],
-            free_nodes_size: 0,
+            wlog: ObjList::with_sizes(log_size, max_log_size),
+            q_threads: vec![
+                WaitEntry {
+                    is_wait: false,
+                    run_counter: 0,
+                };
+                max_threads
+            ],
+            free_nodes: Vec::with_capacity(std::cmp::min(max_free_nodes, RLU_MAX_FREE_NODES)),
+            max_free_nodes,
+            deferred: Vec::new(),
+            deferred_mark: 0,
+            free_mark: 0,
+            deferred_sections: 0,
+            nesting: 0,
+            inner_abort: false,
+            mv_wset: Vec::new(),
+            mv_spare: Vec::new(),
+            stats: RluThreadStats::new(),
         }
     }
+
+    // Stores the counters GlobalRlu::inspect reports where other threads can read them. Called
+    // once an operation has changed them, so a snapshot sees each thread between operations.
+    fn publish_counters(&self) {
+        let shared = &self.shared;
+        shared.is_writer.store(self.is_writer, Ordering::Relaxed);
+        shared.cur_pos.store(self.wlog.cur_pos, Ordering::Relaxed);
+        shared
+            .num_of_objs
+            .store(self.wlog.num_of_objs + self.mv_wset.len(), Ordering::Relaxed);
+        shared.pending_frees.store(self.free_nodes.len(), Ordering::Relaxed);
+        shared.pending_callbacks.store(self.deferred.len(), Ordering::Relaxed);
+    }
+
+    // Addresses of the counters other threads read, for checking that no other thread's
+    // counters or this thread's private state share their cache lines
+    pub fn shared_counters(&self) -> Range<usize> {
+        let start = &*self.shared as *const RluThreadShared as usize;
+        start..start + mem::size_of::<RluThreadShared>()
+    }
 }
 
-pub trait RluObj {
+// Runs when the domain is destroyed, after every thread has exited: the write log's copies
+// are dropped with it, objects still waiting to be freed and spare versions are freed here,
+// and callbacks still waiting are run, since no reader is left
+impl<T> Drop for RluThread<T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        unsafe {
+            for p_node in self.free_nodes.drain(..) {
+                drop(Box::from_raw(p_node));
+            }
+            for p_version in self.mv_wset.drain(..).chain(self.mv_spare.drain(..)) {
+                drop(Box::from_raw(p_version));
+            }
+        }
+        for f in self.deferred.drain(..) {
+            f();
+        }
+    }
+}
+
+pub trait RluObj: Sized {
     fn get_p_obj_copy(&self) -> *mut Self;
     fn is_locked(&self) -> bool;
     fn is_copy(&self) -> bool;
@@ -92,17 +338,145 @@ pub trait RluObj {
     fn get_p_original(&self) -> *mut Self;
     fn get_locking_thread_from_ws_obj(&self) -> usize;
     fn get_ws_run_counter(&self) -> u64;
+    // Must point the copy at the original object even when called on a copy, since in
+    // multi-version mode new copies are taken from the newest committed version
     fn get_copy_with_ws_hdr(&self, run_counter: u64, thread_id: usize) -> Self;
     fn cas(&self, new_obj: *mut Self) -> bool;
+    // Locks an object that no other thread can be locking, see RluLocking::Coarse
+    fn store_copy(&self, new_obj: *mut Self) {
+        let locked = self.cas(new_obj);
+        debug_assert!(locked, "object locked during a coarse writer section");
+    }
     fn copy_back_to_original(&self);
     fn unlock_original(&self);
     fn unlock(&self);
+    fn get_version_chain(&self) -> &RluVersionChain<Self>;
 }
 
 #[derive(Debug)]
 pub struct RluObjHdr<T: RluObj> {
     pub p_obj_copy: AtomicPtr<T>,
     pub ws_hdr: Option<WsHdr<T>>, //only Some() if we are a copy, None at start
+    pub p_version: RluVersionChain<T>, //newest committed version, only used in MV mode
+}
+
+impl<T> RluObjHdr<T>
+where
+    T: RluObj,
+{
+    pub fn new() -> RluObjHdr<T> {
+        RluObjHdr {
+            p_obj_copy: AtomicPtr::new(ptr::null_mut()),
+            ws_hdr: None,
+            p_version: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    // Header for a write log copy of p_obj_actual
+    pub fn new_copy(p_obj_actual: *mut T, run_counter: u64, thread_id: usize) -> RluObjHdr<T> {
+        RluObjHdr {
+            p_obj_copy: AtomicPtr::new(PTR_ID_OBJ_COPY as *mut T),
+            ws_hdr: Some(WsHdr {
+                p_obj_actual,
+                run_counter,
+                thread_id,
+            }),
+            p_version: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl<T> Default for RluObjHdr<T>
+where
+    T: RluObj,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Freeing an object frees the versions hanging off it, which no one else points to
+impl<T> Drop for RluObjHdr<T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        unsafe {
+            rlu_free_versions(self.p_version.load(Ordering::Relaxed));
+        }
+    }
+}
+
+// A committed copy of an object in multi-version mode. Versions hang off the original's
+// header newest first, and readers pick the newest one whose commit_clock is at or below
+// their local_clock. The copy is locked in place by the writer, so it keeps its ws_hdr.
+pub struct RluVersion<T: RluObj> {
+    pub obj: T,
+    commit_clock: AtomicU64,
+    older: AtomicPtr<RluVersion<T>>,
+}
+
+// The newest version of an object, derived RluObj impls name it through this since the
+// atomic is loom's in a loom build
+pub type RluVersionChain<T> = AtomicPtr<RluVersion<T>>;
+
+impl<T> RluVersion<T>
+where
+    T: RluObj,
+{
+    fn new(obj: T) -> RluVersion<T> {
+        RluVersion {
+            obj,
+            commit_clock: AtomicU64::new(u64::MAX),
+            older: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+// How a committing writer waits for the readers it has to wait for. Spinning reacts the
+// fastest but takes a whole core, which hurts when there are more threads than cores and the
+// reader it waits for is not even running.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WaitStrategy {
+    // Busy wait with a spin loop hint, the default
+    Spin,
+    // Spin up to spins times per reader, then yield to the scheduler between checks
+    SpinThenYield { spins: u32 },
+    // Spin up to spins times per reader, then sleep until a reader of the domain ends a
+    // section. Readers pay for a check of an atomic counter when they leave.
+    Park { spins: u32 },
+}
+
+// Gets the report of a writer that waited longer than the domain's sync_timeout
+pub type RluStallHandler = Box<dyn Fn(&RluStallReport) + Send + Sync>;
+
+// Waiting writers of a WaitStrategy::Park domain
+#[derive(Default)]
+struct RluParking {
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+}
+
+// How writer sections keep out of each other's way
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RluLocking {
+    // Per-object locks, the default: rlu_try_lock compare-and-swaps the object's copy pointer,
+    // and fails if another section has the object locked
+    Fine,
+    // One writer section at a time per domain, like RluCoarseList in the Java version. A
+    // section holds the domain's writer lock from its first rlu_try_lock (or from
+    // rlu_writer_lock) until it ends, and its locks always succeed, storing the copy pointer
+    // without a compare-and-swap. Single version mode without deferral only.
+    Coarse,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RluMode {
+    // Classic RLU: every writer commit waits for a grace period, then writes back
+    SingleVersion,
+    // MV-RLU: commits publish copies as versions and only wait when freeing objects
+    MultiVersion,
 }
 
 // Begin Internal functions
@@ -113,9 +487,33 @@ pub struct RluObjHdr<T: RluObj> {
 
 // This struct makes it possible to have multiple concurrent RLU data structures
 pub struct GlobalRlu<T: RluObj> {
-    pub threads: [Option<Box<RluThread<T>>>; RLU_MAX_THREADS],
-    global_clock: AtomicU64,
-    num_threads_created: AtomicUsize,
+    pub threads: Box<[Option<Box<RluThread<T>>>]>,
+    global_clock: Arc<CachePadded<AtomicU64>>, //shared by the domains of a group
+    slot_in_use: Box<[AtomicBool]>, //claimed by rlu_thread_init, released by rlu_thread_exit
+    slot_ready: Box<[AtomicBool]>,  //set once threads[i] exists, others check it before reading
+    slot_poisoned: Box<[AtomicBool]>, //owner unwound out of a section, see rlu_poison
+    max_threads: usize,
+    log_size: usize,
+    max_log_size: usize,
+    max_free_nodes: usize,
+    max_deferred: usize, //writer sections a thread commits at once, 1 unless in deferral mode
+    mode: RluMode,
+    locking: RluLocking,
+    writer_lock: CachePadded<AtomicUsize>, //id of the coarse writer, or RLU_NO_WRITER
+    wait_strategy: WaitStrategy,
+    parking: CachePadded<RluParking>, //written by parked writers
+    contention: Box<dyn ContentionPolicy>, //used by RluHandle::transaction between attempts
+    sync_timeout: Option<Duration>,
+    stall_handler: Option<RluStallHandler>, //None drops reports
+}
+
+impl<T> Default for GlobalRlu<T>
+where
+    T: RluObj,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> GlobalRlu<T>
@@ -123,19 +521,303 @@ where
     T: RluObj,
 {
     pub fn new() -> GlobalRlu<T> {
+        GlobalRlu::with_mode(RluMode::SingleVersion)
+    }
+    pub fn with_mode(mode: RluMode) -> GlobalRlu<T> {
+        GlobalRlu::builder().mode(mode).build()
+    }
+    pub fn builder() -> GlobalRluBuilder<T> {
+        GlobalRluBuilder::new()
+    }
+    pub fn init_rlu() -> *mut GlobalRlu<T> {
+        GlobalRlu::init_rlu_with_mode(RluMode::SingleVersion)
+    }
+    pub fn init_rlu_with_mode(mode: RluMode) -> *mut GlobalRlu<T> {
+        GlobalRlu::builder().mode(mode).init_rlu()
+    }
+    pub fn mode(&self) -> RluMode {
+        self.mode
+    }
+    pub fn locking(&self) -> RluLocking {
+        self.locking
+    }
+    pub fn max_threads(&self) -> usize {
+        self.max_threads
+    }
+    pub fn log_size(&self) -> usize {
+        self.log_size
+    }
+    pub fn max_log_size(&self) -> usize {
+        self.max_log_size
+    }
+    pub fn max_free_nodes(&self) -> usize {
+        self.max_free_nodes
+    }
+    pub fn max_deferred(&self) -> usize {
+        self.max_deferred
+    }
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.wait_strategy
+    }
+    pub fn contention_policy(&self) -> &dyn ContentionPolicy {
+        &*self.contention
+    }
+    pub fn sync_timeout(&self) -> Option<Duration> {
+        self.sync_timeout
+    }
+    // Registered threads whose owner unwound out of a section, see rlu_poison
+    pub fn poisoned_threads(&self) -> Vec<usize> {
+        (0..self.max_threads)
+            .filter(|&i| {
+                self.slot_in_use[i].load(Ordering::Acquire)
+                    && self.slot_poisoned[i].load(Ordering::Acquire)
+            })
+            .collect()
+    }
+    // A snapshot of the domain and its registered threads, see RluSnapshot. Every counter is
+    // read atomically, one at a time, and a thread publishes its write log, free list and
+    // writer flag at the end of each operation, so a snapshot of a busy domain shows each
+    // thread between two of its operations, though not all threads at the same moment.
+    pub fn inspect(&self) -> RluSnapshot {
+        let threads = (0..self.max_threads)
+            .filter(|&i| {
+                self.slot_in_use[i].load(Ordering::Acquire)
+                    && self.slot_ready[i].load(Ordering::Acquire)
+            })
+            .filter_map(|i| {
+                self.threads[i].as_ref().map(|box_thread| {
+                    let shared = &box_thread.shared;
+                    let write_clock = shared.write_clock.load(Ordering::Acquire);
+                    RluThreadSnapshot {
+                        id: i,
+                        run_counter: shared.run_counter.load(Ordering::Acquire),
+                        local_clock: shared.local_clock.load(Ordering::Acquire),
+                        write_clock: Some(write_clock).filter(|&clock| clock != u64::MAX),
+                        is_writer: shared.is_writer.load(Ordering::Relaxed),
+                        cur_pos: shared.cur_pos.load(Ordering::Relaxed),
+                        num_of_objs: shared.num_of_objs.load(Ordering::Relaxed),
+                        pending_frees: shared.pending_frees.load(Ordering::Relaxed),
+                        pending_callbacks: shared.pending_callbacks.load(Ordering::Relaxed),
+                        poisoned: self.slot_poisoned[i].load(Ordering::Acquire),
+                    }
+                })
+            })
+            .collect();
+        RluSnapshot {
+            global_clock: self.global_clock.load(Ordering::Acquire),
+            mode: self.mode,
+            max_threads: self.max_threads,
+            threads,
+        }
+    }
+    // Counters of every thread that ever registered, see RluStats. A slot being registered
+    // right now is skipped until rlu_thread_init has published its thread, like in inspect.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> RluStats {
+        let mut stats = RluStats::default();
+        let ready = (0..self.max_threads).filter(|&i| self.slot_ready[i].load(Ordering::Acquire));
+        for box_thread in ready.filter_map(|i| self.threads[i].as_ref()) {
+            box_thread.stats.add_to(&mut stats);
+        }
+        stats
+    }
+    // Counters of the thread in slot id, including earlier threads that used the slot
+    #[cfg(feature = "stats")]
+    pub fn thread_stats(&self, id: usize) -> Option<RluStats> {
+        self.threads.get(id)?.as_ref().map(|box_thread| {
+            let mut stats = RluStats::default();
+            box_thread.stats.add_to(&mut stats);
+            stats
+        })
+    }
+}
+
+// Sets the capacities of a domain. Every registered thread gets its own write log of
+// log_size entries (half of it per section), which grows up to max_log_size. A section can
+// free up to max_free_nodes objects, callbacks from rlu_defer included. Past either limit,
+// rlu_try_lock, rlu_free and rlu_defer return an RluError instead of growing further.
+pub struct GlobalRluBuilder<T: RluObj> {
+    mode: RluMode,
+    locking: RluLocking,
+    max_threads: usize,
+    log_size: usize,
+    max_log_size: usize,
+    max_free_nodes: usize,
+    max_deferred: usize,
+    wait_strategy: WaitStrategy,
+    contention: Box<dyn ContentionPolicy>,
+    sync_timeout: Option<Duration>,
+    stall_handler: Option<RluStallHandler>,
+    clock: Option<Arc<CachePadded<AtomicU64>>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> GlobalRluBuilder<T>
+where
+    T: RluObj,
+{
+    pub fn new() -> GlobalRluBuilder<T> {
+        GlobalRluBuilder {
+            mode: RluMode::SingleVersion,
+            locking: RluLocking::Fine,
+            max_threads: RLU_MAX_THREADS,
+            log_size: RLU_MAX_LOG_SIZE,
+            max_log_size: usize::MAX,
+            max_free_nodes: usize::MAX,
+            max_deferred: 1,
+            wait_strategy: WaitStrategy::Spin,
+            contention: Box::new(Backoff::default()),
+            sync_timeout: None,
+            stall_handler: None,
+            clock: None,
+            _marker: PhantomData,
+        }
+    }
+    pub fn mode(mut self, mode: RluMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    // RluLocking::Fine unless set
+    pub fn locking(mut self, locking: RluLocking) -> Self {
+        self.locking = locking;
+        self
+    }
+    pub fn max_threads(mut self, max_threads: usize) -> Self {
+        assert!(max_threads > 0, "an RLU domain needs at least one thread");
+        self.max_threads = max_threads;
+        self
+    }
+    // Initial size, must be even. Sections that lock more than half of it grow the log.
+    pub fn log_size(mut self, log_size: usize) -> Self {
+        assert!(
+            log_size >= 2 && log_size.is_multiple_of(2),
+            "write log size must be even and nonzero"
+        );
+        self.log_size = log_size;
+        self
+    }
+    // Limits a section to max_log_size / 2 locked objects
+    pub fn max_log_size(mut self, max_log_size: usize) -> Self {
+        assert!(max_log_size >= 2, "write log must hold at least one object per half");
+        self.max_log_size = max_log_size;
+        self
+    }
+    pub fn max_free_nodes(mut self, max_free_nodes: usize) -> Self {
+        self.max_free_nodes = max_free_nodes;
+        self
+    }
+    // Deferral mode (section 4 of the paper): a writer keeps its locks and copies when a
+    // section ends, and commits up to max_sections of them with a single synchronize. It
+    // commits early when its write log or free list fills up, when another thread (or one of
+    // its own sections) fails to lock one of its objects, and on rlu_flush. Other threads only
+    // see the changes once they are committed, and its locks stay taken, so a thread that
+    // goes idle must call rlu_flush. Structures on RluShared do that at the end of every
+    // operation, since they cannot tell whether the thread will be back. 1, the default,
+    // commits every section. Single version mode only.
+    pub fn defer_commits(mut self, max_sections: usize) -> Self {
+        assert!(max_sections > 0, "a writer must be able to commit at least one section");
+        self.max_deferred = max_sections;
+        self
+    }
+    // How commits wait for readers, WaitStrategy::Spin unless set
+    pub fn wait_strategy(mut self, wait_strategy: WaitStrategy) -> Self {
+        self.wait_strategy = wait_strategy;
+        self
+    }
+    // What transactions do after a conflict, Backoff::default() unless set
+    pub fn contention_policy<P: ContentionPolicy + 'static>(mut self, policy: P) -> Self {
+        self.contention = Box::new(policy);
+        self
+    }
+    // Watchdog for readers that stay in a section too long: a commit or synchronize that has
+    // waited on readers for timeout reports the threads it waits on, and again each time
+    // another timeout passes. It keeps waiting, since it cannot be safe without them.
+    pub fn sync_timeout(mut self, timeout: Duration) -> Self {
+        self.sync_timeout = Some(timeout);
+        self
+    }
+    // Where stall reports go, nowhere unless set
+    pub fn on_stall<F: Fn(&RluStallReport) + Send + Sync + 'static>(mut self, handler: F) -> Self {
+        self.stall_handler = Some(Box::new(handler));
+        self
+    }
+    // Makes the domain part of a group, see RluGroup::add
+    pub(crate) fn shared_clock(mut self, clock: Arc<CachePadded<AtomicU64>>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+    pub fn build(self) -> GlobalRlu<T> {
+        assert!(
+            self.max_deferred == 1 || self.mode == RluMode::SingleVersion,
+            "deferral mode needs RluMode::SingleVersion"
+        );
+        assert!(
+            self.locking == RluLocking::Fine
+                || (self.mode == RluMode::SingleVersion && self.max_deferred == 1),
+            "coarse locking needs RluMode::SingleVersion without deferral"
+        );
+        assert!(
+            self.clock.is_none() || (self.max_deferred == 1 && self.locking == RluLocking::Fine),
+            "a group domain cannot defer commits or use coarse locking"
+        );
+        let log_size = std::cmp::min(self.log_size, self.max_log_size);
         GlobalRlu {
-            threads: [
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None,
-            ],
-            global_clock: AtomicU64::new(0),
-            num_threads_created: AtomicUsize::new(0),
+            threads: (0..self.max_threads).map(|_| None).collect(),
+            global_clock: self.clock.unwrap_or_default(),
+            slot_in_use: (0..self.max_threads).map(|_| AtomicBool::new(false)).collect(),
+            slot_ready: (0..self.max_threads).map(|_| AtomicBool::new(false)).collect(),
+            slot_poisoned: (0..self.max_threads).map(|_| AtomicBool::new(false)).collect(),
+            max_threads: self.max_threads,
+            log_size,
+            max_log_size: self.max_log_size,
+            max_free_nodes: self.max_free_nodes,
+            max_deferred: self.max_deferred,
+            mode: self.mode,
+            locking: self.locking,
+            writer_lock: CachePadded::new(AtomicUsize::new(RLU_NO_WRITER)),
+            wait_strategy: self.wait_strategy,
+            parking: CachePadded::default(),
+            contention: self.contention,
+            sync_timeout: self.sync_timeout,
+            stall_handler: self.stall_handler,
         }
     }
-    pub fn init_rlu() -> *mut GlobalRlu<T> {
-        let boxed = Box::new(GlobalRlu::new());
-        Box::into_raw(boxed)
+    pub fn init_rlu(self) -> *mut GlobalRlu<T> {
+        Box::into_raw(Box::new(self.build()))
+    }
+}
+
+impl<T> Default for GlobalRluBuilder<T>
+where
+    T: RluObj,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Frees a domain made with init_rlu, along with its threads' write logs and pending frees.
+// The objects belong to the data structure, which can free them with the help of rlu_latest.
+/// # Safety
+/// rlu must come from init_rlu, and must not be used again once this returns Ok.
+pub unsafe fn rlu_destroy<T: RluObj>(rlu: *mut GlobalRlu<T>) -> Result<(), RluError> {
+    if (*rlu).slot_in_use.iter().any(|in_use| in_use.load(Ordering::Acquire)) {
+        return Err(RluError::DomainInUse);
+    }
+    drop(Box::from_raw(rlu));
+    Ok(())
+}
+
+// The current contents of p_obj while no section is open, for walking a structure to tear it
+// down. In MV mode that is the newest version, since originals are never written back.
+/// # Safety
+/// p_obj must be a live original, and no thread may be inside a section of its domain.
+pub unsafe fn rlu_latest<T: RluObj>(p_obj: *mut T) -> *mut T {
+    let p_version = (*p_obj).get_version_chain().load(Ordering::Acquire);
+    if p_version.is_null() {
+        p_obj
+    } else {
+        &mut (*p_version).obj
     }
 }
 
@@ -146,301 +828,1137 @@ fn rlu_process_free<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
     unsafe {
         (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                for i in 0..box_thread.free_nodes_size {
-                    let box_node = Box::from_raw(box_thread.free_nodes[i]);
-                    drop(box_node);
-                    box_thread.free_nodes[i] = ptr::null_mut();
-                }
-                box_thread.free_nodes_size = 0;
-            },
+            |box_thread| {
+                box_thread
+                    .stats
+                    .add(RluCounter::ObjectsFreed, box_thread.free_nodes.len() as u64);
+                for p_node in box_thread.free_nodes.drain(..) {
+                    let box_node = Box::from_raw(p_node);
+                    rlu_free_versions(box_node.get_version_chain().swap(ptr::null_mut(), Ordering::Acquire));
+                    drop(box_node);
+                }
+            },
+        );
+        //taken out first, a callback may queue more for the next grace period
+        let deferred = (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                box_thread.deferred_mark = 0;
+                box_thread.free_mark = 0;
+                mem::take(&mut box_thread.deferred)
+            },
+        );
+        for f in deferred {
+            f();
+        }
+    }
+    rlu_publish_counters(rlu, id);
+}
+
+fn rlu_publish_counters<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| box_thread.publish_counters(),
+        );
+    }
+}
+
+fn rlu_wait_for_readers<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    let timeout = unsafe { (*rlu).sync_timeout };
+    let _ = rlu_await_readers(rlu, id, timeout, false); //only gives up with give_up
+}
+
+// Waits for readers, reporting a stall each time timeout passes. With give_up it stops at
+// the first report instead, and returns it.
+fn rlu_await_readers<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    timeout: Option<Duration>,
+    give_up: bool,
+) -> Result<(), RluStallReport> {
+    //basing mostly off paper pseudocode for now
+    unsafe {
+        //pairs with the fence in rlu_section_enter: a reader we see outside of a section
+        //sees our write clock once it enters one
+        fence(Ordering::SeqCst);
+        let timer = RluTimer::start();
+        let start = timeout.map(|_| Instant::now());
+        let mut deadline = start.zip(timeout).map(|(start, timeout)| start + timeout);
+        let mut spins = 0;
+        let mut stall = None;
+        for i in 0..(*rlu).max_threads {
+            if i == id {
+                continue; //dont wait for myself
+            }
+            if !(*rlu).slot_ready[i].load(Ordering::Acquire)
+                || !(*rlu).slot_in_use[i].load(Ordering::Acquire)
+            {
+                //dont wait for uninitialized/finished threads
+                if let Some(box_thread) = (*rlu).threads[id].as_mut() {
+                    box_thread.q_threads[i].is_wait = false;
+                }
+                continue;
+            }
+            if let Some(box_thread) = (*rlu).threads[id].as_mut() {
+                box_thread.q_threads[i].run_counter = (*rlu).threads[i]
+                    .as_ref()
+                    .unwrap()
+                    .shared.run_counter
+                    .load(Ordering::Acquire);
+                //if run_counter odd, wait on it
+                box_thread.q_threads[i].is_wait = box_thread.q_threads[i].run_counter & 0x1 == 0x1;
+            }
+        }
+        'readers: for i in 0..(*rlu).max_threads {
+            let is_done = || rlu_reader_done(rlu, id, i);
+            let mut waited: u64 = 0;
+            while !is_done() {
+                waited += 1;
+                let mut check_clock = waited.is_multiple_of(RLU_STALL_CHECK_INTERVAL);
+                match (*rlu).wait_strategy {
+                    WaitStrategy::SpinThenYield { spins: max_spins }
+                        if waited > u64::from(max_spins) =>
+                    {
+                        thread::yield_now()
+                    }
+                    WaitStrategy::Park { spins: max_spins } if waited > u64::from(max_spins) => {
+                        rlu_park_until(rlu, is_done, deadline);
+                        check_clock = true;
+                    }
+                    _ => hint::spin_loop(),
+                }
+                if let (Some(at), Some(start), true) = (deadline, start, check_clock) {
+                    if Instant::now() >= at && !is_done() {
+                        let report = rlu_report_stall(rlu, id, start.elapsed());
+                        if give_up {
+                            spins += waited;
+                            stall = Some(report);
+                            break 'readers;
+                        }
+                        deadline = timeout.map(|timeout| at + timeout);
+                    }
+                }
+            }
+            spins += waited;
+        }
+        (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                box_thread.stats.add(RluCounter::SyncSpins, spins);
+                box_thread.stats.add_time(RluCounter::SyncNanos, timer);
+            },
+        );
+        stall.map_or(Ok(()), Err)
+    }
+}
+
+// Whether thread id no longer has to wait for reader i, as of the last rlu_await_readers
+fn rlu_reader_done<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, i: usize) -> bool {
+    unsafe {
+        (*rlu).threads[id]
+            .as_ref()
+            .map(|box_thread| {
+                if !box_thread.q_threads[i].is_wait {
+                    return true; //already confirmed I dont need to wait
+                }
+                (*rlu).threads[i]
+                    .as_ref()
+                    .map(|other_thread| {
+                        if box_thread.q_threads[i].run_counter
+                            != other_thread.shared.run_counter.load(Ordering::Acquire)
+                        {
+                            return true; //other thread has progressed
+                        }
+                        if box_thread.shared.write_clock.load(Ordering::Relaxed)
+                            <= other_thread.shared.local_clock.load(Ordering::Relaxed)
+                        {
+                            return true; //other thread started after me so dont wait on it
+                        }
+                        false
+                    })
+                    .unwrap()
+            })
+            .unwrap()
+    }
+}
+
+// Hands the readers thread id still waits on to the domain's stall handler
+fn rlu_report_stall<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    waited: Duration,
+) -> RluStallReport {
+    let blocking = unsafe {
+        (0..(*rlu).max_threads)
+            .filter(|&i| i != id && !rlu_reader_done(rlu, id, i))
+            .map(|i| rlu_blocking_thread(rlu, i))
+            .collect()
+    };
+    rlu_hand_stall(rlu, id, RluWaitKind::Readers, waited, blocking)
+}
+
+fn rlu_blocking_thread<T: RluObj>(rlu: *mut GlobalRlu<T>, i: usize) -> RluBlockingThread {
+    unsafe {
+        (*rlu).threads[i].as_ref().map_or_else(
+            || unreachable!(),
+            |other_thread| RluBlockingThread {
+                id: i,
+                run_counter: other_thread.shared.run_counter.load(Ordering::Relaxed),
+                local_clock: other_thread.shared.local_clock.load(Ordering::Relaxed),
+            },
+        )
+    }
+}
+
+fn rlu_hand_stall<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    waiting_for: RluWaitKind,
+    waited: Duration,
+    blocking: Vec<RluBlockingThread>,
+) -> RluStallReport {
+    unsafe {
+        let report = RluStallReport {
+            waiter: id,
+            waiting_for,
+            waited,
+            write_clock: (*rlu).threads[id].as_ref().map_or_else(
+                || unreachable!(),
+                |box_thread| box_thread.shared.write_clock.load(Ordering::Relaxed),
+            ),
+            blocking,
+        };
+        if let Some(handler) = &(*rlu).stall_handler {
+            handler(&report);
+        }
+        report
+    }
+}
+
+// Sleeps until is_done or the deadline, woken up by rlu_wake_writers. A reader that ends its
+// section after we counted ourselves in either sees the count and wakes us, or changed its
+// run_counter before we check is_done under the lock.
+fn rlu_park_until<T: RluObj, F: Fn() -> bool>(
+    rlu: *mut GlobalRlu<T>,
+    is_done: F,
+    deadline: Option<Instant>,
+) {
+    unsafe {
+        let parking = &(*rlu).parking;
+        parking.waiters.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let mut guard = parking.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while !is_done() {
+            guard = match deadline {
+                None => parking.wakeup.wait(guard).unwrap_or_else(PoisonError::into_inner),
+                Some(at) => {
+                    let now = Instant::now();
+                    if now >= at {
+                        break;
+                    }
+                    parking
+                        .wakeup
+                        .wait_timeout(guard, at - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+        drop(guard);
+        parking.waiters.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Called after a section ends, wakes writers parked in rlu_wait_for_readers
+fn rlu_wake_writers<T: RluObj>(rlu: *mut GlobalRlu<T>) {
+    unsafe {
+        let parking = &(*rlu).parking;
+        if !matches!((*rlu).wait_strategy, WaitStrategy::Park { .. }) {
+            return;
+        }
+        fence(Ordering::SeqCst); //pairs with the one in rlu_park_until
+        if parking.waiters.load(Ordering::Relaxed) > 0 {
+            let _guard = parking.lock.lock().unwrap_or_else(PoisonError::into_inner);
+            parking.wakeup.notify_all();
+        }
+    }
+}
+
+fn rlu_commit_write_log<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        let write_clock = (*rlu).global_clock.load(Ordering::Relaxed) + 1;
+        rlu_commit_start(rlu, id, write_clock);
+        //readers that see the new clock also see our write clock and what we wrote to our copies
+        (*rlu).global_clock.fetch_add(1, Ordering::Release);
+        rlu_commit_finish(rlu, id, write_clock);
+    }
+}
+
+// From here on readers whose local_clock reaches write_clock steal our copies. A group sets
+// this in all of its domains before moving their shared clock, see rlu_group.
+pub(crate) fn rlu_commit_start<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, write_clock: u64) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| box_thread.shared.write_clock.store(write_clock, Ordering::Relaxed),
+        );
+    }
+}
+
+// Called once the global clock has moved past write_clock
+pub(crate) fn rlu_commit_finish<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, write_clock: u64) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                //requests from here on are for locks taken after this commit
+                box_thread.shared.sync_requested.store(false, Ordering::Relaxed);
+                box_thread.deferred_sections = 0;
+                box_thread.stats.add(RluCounter::WriterCommits, 1);
+            },
+        );
+        if (*rlu).mode == RluMode::MultiVersion {
+            rlu_mv_commit_write_log(rlu, id, write_clock);
+            return;
+        }
+    }
+    rlu_wait_for_readers(rlu, id); //spin loop while readers finish up
+    rlu_writeback_write_log(rlu, id);
+    // now set write clock back to inf. A reader that still finds one of our objects locked
+    // but reads this goes to the original, so the writeback must be visible with it.
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                box_thread
+                    .shared.write_clock
+                    .store(u64::MAX, Ordering::Release);
+            },
+        );
+    }
+    rlu_swap_write_logs(rlu, id);
+    rlu_release_writer_lock(rlu, id); //our objects are unlocked again
+    rlu_process_free(rlu, id);
+}
+
+fn rlu_writeback_write_log<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                for obj_copy in box_thread.wlog.pending_objs() {
+                    rlu_check_log_copy(id, obj_copy);
+                    obj_copy.copy_back_to_original();
+                }
+                box_thread.wlog.num_of_objs = 0; //these objects still exist but only until next sync()
+            },
+        );
+    }
+}
+
+fn rlu_swap_write_logs<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                //Now, clear other half of write log bc this is second synchronize() since (see end
+                //of 3.5 in paper)
+                box_thread.wlog.swap();
+            },
+        );
+    }
+}
+
+fn rlu_unlock_objs<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    rlu_unlock_objs_since(rlu, id, 0);
+}
+
+// How many objects the current section has locked, for rlu_unlock_objs_since
+fn rlu_lock_mark<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> usize {
+    unsafe {
+        (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| match (*rlu).mode {
+                RluMode::SingleVersion => box_thread.wlog.num_of_objs,
+                RluMode::MultiVersion => box_thread.mv_wset.len(),
+            },
+        )
+    }
+}
+
+// Unlocks the objects the current section locked after it had locked mark of them
+fn rlu_unlock_objs_since<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, mark: usize) {
+    unsafe {
+        if (*rlu).mode == RluMode::MultiVersion {
+            rlu_mv_unlock_objs(rlu, id, mark);
+            return;
+        }
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                for ws_copy in box_thread.wlog.section_objs().skip(mark) {
+                    ws_copy.unlock_original();
+                }
+                box_thread.wlog.discard_section_since(mark);
+            },
+        )
+    }
+}
+
+// Begin internal MV-RLU functions
+
+// Frees a chain of versions that no reader can reach anymore
+unsafe fn rlu_free_versions<T: RluObj>(mut p_version: *mut RluVersion<T>) {
+    while !p_version.is_null() {
+        let older = (*p_version).older.load(Ordering::Relaxed);
+        drop(Box::from_raw(p_version));
+        p_version = older;
+    }
+}
+
+// Returns a clock value that every active and future reader section is at or above
+fn rlu_mv_horizon<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> u64 {
+    unsafe {
+        fence(Ordering::SeqCst); //as in rlu_wait_for_readers
+        //load the global clock first: a thread that starts after this will see at least this
+        let mut horizon = (*rlu).global_clock.load(Ordering::Relaxed);
+        for i in 0..(*rlu).max_threads {
+            if i == id {
+                continue;
+            }
+            if !(*rlu).slot_ready[i].load(Ordering::Acquire) {
+                continue;
+            }
+            if let Some(other_thread) = (*rlu).threads[i].as_ref() {
+                if other_thread.shared.run_counter.load(Ordering::Acquire) & 0x1 == 0x1 {
+                    horizon = std::cmp::min(
+                        horizon,
+                        other_thread.shared.local_clock.load(Ordering::Relaxed),
+                    );
+                }
+            }
+        }
+        horizon
+    }
+}
+
+// Drops every version older than the newest one at or below the horizon. Readers stop
+// walking the chain at the first version their local_clock can see, so nothing past that
+// point can be reached. Only called while holding the lock on the object.
+fn rlu_mv_trim_versions<T: RluObj>(p_version: *mut RluVersion<T>, horizon: u64) {
+    unsafe {
+        let mut p_version = p_version;
+        while !p_version.is_null() {
+            if (*p_version).commit_clock.load(Ordering::Relaxed) <= horizon {
+                rlu_free_versions((*p_version).older.swap(ptr::null_mut(), Ordering::Relaxed));
+                return;
+            }
+            p_version = (*p_version).older.load(Ordering::Relaxed);
+        }
+    }
+}
+
+// Newest version of p_obj (an unlocked or foreign-locked original) visible to this thread
+fn rlu_mv_dereference<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, p_obj: *mut T) -> *mut T {
+    unsafe {
+        let my_local_clock = (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| box_thread.shared.local_clock.load(Ordering::Relaxed),
+        );
+        let mut p_version = (*p_obj).get_version_chain().load(Ordering::Acquire);
+        while !p_version.is_null() {
+            if (*p_version).commit_clock.load(Ordering::Relaxed) <= my_local_clock {
+                return &mut (*p_version).obj;
+            }
+            p_version = (*p_version).older.load(Ordering::Relaxed);
+        }
+        p_obj //no committed version old enough, the original is what we saw
+    }
+}
+
+// try_lock for an unlocked original in MV mode. The copy is taken from the newest version,
+// which must be part of our snapshot, and lives in its own allocation so that committing it
+// is just publishing the pointer.
+fn rlu_mv_try_lock<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    p_p_obj: *mut *mut T,
+    p_obj: *mut T,
+) -> Result<bool, RluError> {
+    unsafe {
+        let p_newest = (*p_obj).get_version_chain().load(Ordering::Acquire);
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                if !p_newest.is_null()
+                    && (*p_newest).commit_clock.load(Ordering::Relaxed)
+                        > box_thread.shared.local_clock.load(Ordering::Relaxed)
+                {
+                    //committed after our snapshot, our reads of this object are outdated
+                    return Ok(false);
+                }
+                if box_thread.mv_wset.len() >= box_thread.wlog.max_half_size {
+                    return Err(RluError::WriteLogFull);
+                }
+                let run_counter = box_thread.shared.run_counter.load(Ordering::Relaxed);
+                let obj_copy = if p_newest.is_null() {
+                    (*p_obj).get_copy_with_ws_hdr(run_counter, id)
+                } else {
+                    (*p_newest).obj.get_copy_with_ws_hdr(run_counter, id)
+                };
+                let p_version = match box_thread.mv_spare.pop() {
+                    Some(p_version) => {
+                        (*p_version).obj = obj_copy;
+                        p_version
+                    }
+                    None => Box::into_raw(Box::new(RluVersion::new(obj_copy))),
+                };
+                if !(*p_obj).cas(&mut (*p_version).obj) {
+                    box_thread.mv_spare.push(p_version);
+                    return Ok(false);
+                }
+                if (*p_obj).get_version_chain().load(Ordering::Relaxed) != p_newest {
+                    //someone committed between our copy and our lock, copy is stale
+                    (*p_version).obj.unlock_original();
+                    box_thread.mv_spare.push(p_version);
+                    return Ok(false);
+                }
+                box_thread.mv_wset.push(p_version);
+                *p_p_obj = &mut (*p_version).obj;
+                Ok(true)
+            },
+        )
+    }
+}
+
+fn rlu_mv_commit_write_log<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, write_clock: u64) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                //readers with a new enough clock steal our copies until we publish them
+                let horizon = rlu_mv_horizon(rlu, id);
+                for p_version in box_thread.mv_wset.drain(..) {
+                    let p_original = (*p_version).obj.get_p_original();
+                    let chain = (*p_original).get_version_chain();
+                    (*p_version).commit_clock.store(write_clock, Ordering::Relaxed);
+                    (*p_version)
+                        .older
+                        .store(chain.load(Ordering::Relaxed), Ordering::Relaxed);
+                    chain.store(p_version, Ordering::Release); //with its clock and older
+                    rlu_mv_trim_versions(p_version, horizon);
+                    (*p_version).obj.unlock_original();
+                }
+            },
+        );
+        let has_frees = (*rlu).threads[id]
+            .as_ref()
+            .map(|box_thread| {
+                !box_thread.free_nodes.is_empty() || !box_thread.deferred.is_empty()
+            })
+            .unwrap();
+        if has_frees {
+            //only unlinked objects need a grace period, readers may still be inside them
+            rlu_wait_for_readers(rlu, id);
+            rlu_process_free(rlu, id);
+        }
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                box_thread
+                    .shared.write_clock
+                    .store(u64::MAX, Ordering::Release);
+                box_thread.publish_counters();
+            },
+        );
+    }
+}
+
+fn rlu_mv_unlock_objs<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, mark: usize) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                while box_thread.mv_wset.len() > mark {
+                    let p_version = box_thread.mv_wset.pop().unwrap();
+                    (*p_version).obj.unlock_original();
+                    box_thread.mv_spare.push(p_version);
+                }
+            },
+        )
+    }
+}
+
+// End internal MV-RLU functions
+
+// Ok if id is a thread registered with rlu_thread_init and not yet exited. Every public
+// function checks this first, so the internal ones can treat a missing thread as a bug. id
+// may be another thread's slot that is being registered right now, so threads[id] is only
+// known to be written once slot_ready is.
+fn rlu_check_handle<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    unsafe {
+        if id >= (*rlu).max_threads
+            || !(*rlu).slot_in_use[id].load(Ordering::Acquire)
+            || !(*rlu).slot_ready[id].load(Ordering::Acquire)
+        {
+            return Err(RluError::InvalidHandle);
+        }
+    }
+    Ok(())
+}
+
+// A section is entered (run_counter made odd) before its clock is read, and left before its
+// writes are committed. These are separate steps so that a group can enter all of its domains
+// before reading their shared clock once, see rlu_group.
+//
+// Sections nest: one opened inside another is part of it, keeps its snapshot and commits
+// with it. Ok(true) if this opened the outermost section, whose clock must then be set.
+pub(crate) fn rlu_section_enter<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+) -> Result<bool, RluError> {
+    rlu_check_handle(rlu, id)?;
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                if (box_thread.shared.run_counter.load(Ordering::Relaxed) & 0x1) != 0 {
+                    box_thread.nesting += 1;
+                    return Ok(false);
+                }
+                box_thread.shared.run_counter.fetch_add(1, Ordering::Relaxed);
+                //either a committing writer sees us inside, or we see the clock it moved
+                fence(Ordering::SeqCst);
+                box_thread.stats.add(RluCounter::ReaderSections, 1);
+                box_thread.is_writer = false;
+                box_thread.deferred_mark = box_thread.deferred.len();
+                box_thread.free_mark = box_thread.free_nodes.len();
+                box_thread.inner_abort = false;
+                box_thread.publish_counters();
+                Ok(true)
+            },
+        )
+    }
+}
+
+pub(crate) fn rlu_section_set_clock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, clock: u64) {
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| box_thread.shared.local_clock.store(clock, Ordering::Relaxed),
         );
     }
 }
 
-fn rlu_synchronize<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
-    //basing mostly off paper pseudocode for now
-    unsafe {
-        for i in 0..RLU_MAX_THREADS {
-            if i == id {
-                continue; //dont wait for myself
-            }
-            if (*rlu).threads[i].is_none() {
-                continue; //dont wait for uninitialized/finished threads
-            }
-            (*rlu).threads[id].as_mut().map(|mut box_thread| {
-                box_thread.q_threads[i].run_counter = (*rlu).threads[i]
-                    .as_ref()
-                    .unwrap()
-                    .run_counter
-                    .load(Ordering::SeqCst);
-                if box_thread.q_threads[i].run_counter & 0x1 == 0x1 {
-                    //if run_counter odd, wait on it
-                    box_thread.q_threads[i].is_wait = true;
-                } else {
-                    box_thread.q_threads[i].is_wait = false;
+// Ok(true) if the section locked something, which the caller must then commit. Leaving an
+// inner section is always Ok(false), and the thread stays in the outermost one.
+pub(crate) fn rlu_section_exit<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+) -> Result<bool, RluError> {
+    rlu_check_handle(rlu, id)?;
+    let mut nested = false;
+    let is_writer = unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                if (box_thread.shared.run_counter.load(Ordering::Relaxed) & 0x1) == 0 {
+                    return Err(RluError::UnbalancedSection); //not inside a section
                 }
-            });
-        }
-        for i in 0..RLU_MAX_THREADS {
-            loop {
-                let done = (*rlu).threads[id]
-                    .as_mut()
-                    .map(|box_thread| {
-                        if !box_thread.q_threads[i].is_wait {
-                            return true; //already confirmed I dont need to wait
-                        }
-                        return (*rlu).threads[i]
-                            .as_ref()
-                            .map(|other_thread| {
-                                if box_thread.q_threads[i].run_counter
-                                    != other_thread.run_counter.load(Ordering::SeqCst)
-                                {
-                                    return true; //other thread has progressed
-                                }
-                                if box_thread.write_clock.load(Ordering::SeqCst)
-                                    <= other_thread.local_clock.load(Ordering::SeqCst)
-                                {
-                                    return true; //other thread started after me so dont wait on it
-                                }
-                                false
-                            })
-                            .unwrap();
-                    })
-                    .unwrap();
-                if done {
-                    break;
+                if box_thread.nesting > 0 {
+                    box_thread.nesting -= 1;
+                    nested = true;
+                    return Ok(false);
                 }
-                std::sync::atomic::spin_loop_hint();
-            }
+                box_thread.shared.run_counter.fetch_add(1, Ordering::Release);
+                if box_thread.is_writer {
+                    box_thread.is_writer = false;
+                    return Ok(true);
+                }
+                Ok(false)
+            },
+        )?
+    };
+    if !nested {
+        if !is_writer {
+            rlu_release_writer_lock(rlu, id); //a writer releases it once committed
         }
+        rlu_wake_writers(rlu);
+        rlu_publish_counters(rlu, id);
     }
+    Ok(is_writer)
 }
 
-fn rlu_commit_write_log<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+// Called when a section ends, true if its commit can wait (deferral mode). Deferred sections
+// keep their copies in the write log and their objects locked, and the next commit writes all
+// of them back. A thread with deferred sections commits once the batch is full, its write log
+// or free list is filling up, or another thread is waiting on one of its locks.
+fn rlu_defer_commit<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, is_writer: bool) -> bool {
     unsafe {
+        let max_deferred = (*rlu).max_deferred;
+        let max_free_nodes = (*rlu).max_free_nodes;
         (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
             |box_thread| {
-                box_thread.write_clock.store(
-                    (*rlu).global_clock.load(Ordering::SeqCst) + 1,
-                    Ordering::SeqCst,
-                );
-                (*rlu).global_clock.fetch_add(1, Ordering::SeqCst);
+                if is_writer {
+                    box_thread.deferred_sections += 1;
+                    box_thread.wlog.num_of_objs = 0; //the copies stay, as part of the batch
+                }
+                if box_thread.deferred_sections == 0 {
+                    return true; //a reader section, with nothing waiting
+                }
+                box_thread.deferred_sections < max_deferred
+                    && !box_thread.wlog.is_under_pressure()
+                    && box_thread.free_nodes.len() + box_thread.deferred.len() < max_free_nodes / 2
+                    && !box_thread.shared.sync_requested.load(Ordering::Relaxed)
             },
-        );
+        )
     }
-    rlu_synchronize(rlu, id); //spin loop while readers finish up
-    rlu_writeback_write_log(rlu, id);
-    // now set write clock back to inf
+}
+
+pub(crate) fn rlu_in_section<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> bool {
     unsafe {
-        (*rlu).threads[id].as_mut().map_or_else(
+        (*rlu).threads[id].as_ref().map_or_else(
             || unreachable!(),
-            |box_thread| {
-                box_thread
-                    .write_clock
-                    .store(std::u64::MAX, Ordering::SeqCst);
-            },
-        );
+            |box_thread| (box_thread.shared.run_counter.load(Ordering::Relaxed) & 0x1) != 0,
+        )
     }
-    rlu_swap_write_logs(rlu, id);
-    rlu_process_free(rlu, id);
 }
 
-fn rlu_writeback_write_log<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+// True inside a section opened within another one
+pub(crate) fn rlu_is_nested<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> bool {
     unsafe {
-        (*rlu).threads[id].as_mut().map_or_else(
+        (*rlu).threads[id].as_ref().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                let cur_pos = box_thread.wlog.cur_pos;
-                for i in (cur_pos - box_thread.wlog.num_of_objs)..cur_pos {
-                    assert!(box_thread.wlog.buffer[i].is_some());
-                    box_thread.wlog.buffer[i]
-                        .as_ref()
-                        .map(|obj_copy| obj_copy.copy_back_to_original());
-                }
-                box_thread.wlog.num_of_objs = 0; //these objects still exist but only until next sync()
-            },
-        );
+            |box_thread| box_thread.nesting > 0,
+        )
     }
 }
 
-fn rlu_swap_write_logs<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+// True once an inner section of the current one has aborted, see rlu_abort
+pub(crate) fn rlu_inner_aborted<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> bool {
     unsafe {
-        (*rlu).threads[id].as_mut().map_or_else(
+        (*rlu).threads[id].as_ref().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                let cur_pos = box_thread.wlog.cur_pos;
-                //Now, clear other half of write log bc this is second synchronize() since (see end
-                //of 3.5 in paper)
-                if cur_pos < (RLU_MAX_LOG_SIZE / 2) {
-                    for i in (RLU_MAX_LOG_SIZE / 2)..RLU_MAX_LOG_SIZE {
-                        if box_thread.wlog.buffer[i].is_some() {
-                            box_thread.wlog.buffer[i] = None; //no readers can remain for these entries
-                        }
-                    }
-                    box_thread.wlog.cur_pos = RLU_MAX_LOG_SIZE / 2; //swaps write logs
-                } else {
-                    for i in 0..(RLU_MAX_LOG_SIZE / 2) {
-                        if box_thread.wlog.buffer[i].is_some() {
-                            box_thread.wlog.buffer[i] = None; //no readers can remain for these entries
-                        }
-                    }
-                    box_thread.wlog.cur_pos = 0; //swaps write logs
-                }
-            },
-        );
+            |box_thread| box_thread.inner_abort,
+        )
     }
 }
 
-fn rlu_unlock_objs<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+fn rlu_has_deferred_sections<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> bool {
     unsafe {
-        (*rlu).threads[id].as_mut().map_or_else(
+        (*rlu).threads[id].as_ref().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                for i in
-                    (box_thread.wlog.cur_pos - box_thread.wlog.num_of_objs)..box_thread.wlog.cur_pos
+            |box_thread| box_thread.deferred_sections > 0,
+        )
+    }
+}
+
+// Asks the thread holding a lock we want to commit, which matters in deferral mode only
+fn rlu_request_sync<T: RluObj>(rlu: *mut GlobalRlu<T>, th_id: usize) {
+    unsafe {
+        if (*rlu).max_deferred > 1 {
+            (*rlu).threads[th_id].as_ref().map_or_else(
+                || unreachable!(),
+                |other_thread| other_thread.shared.sync_requested.store(true, Ordering::Relaxed),
+            );
+        }
+    }
+}
+
+// Takes a coarse domain's writer lock, true if this thread has it. Only its owner sets it to
+// its own id, and releasing it makes the last writer's unlocks visible to the next one.
+fn rlu_try_writer_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> bool {
+    unsafe {
+        let writer_lock = &(*rlu).writer_lock;
+        writer_lock.load(Ordering::Relaxed) == id
+            || writer_lock
+                .compare_exchange(RLU_NO_WRITER, id, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+}
+
+// Called when a section has ended and its objects are unlocked
+fn rlu_release_writer_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        if (*rlu).locking == RluLocking::Coarse && (*rlu).writer_lock.load(Ordering::Relaxed) == id {
+            (*rlu).writer_lock.store(RLU_NO_WRITER, Ordering::Release);
+            rlu_wake_writers(rlu); //writers parked in rlu_wait_writer_lock
+        }
+    }
+}
+
+// Takes a coarse domain's writer lock, waiting for it like rlu_await_readers waits for
+// readers: with the domain's wait strategy, and handing a report naming the thread that holds
+// the lock to the stall handler each time sync_timeout passes.
+fn rlu_wait_writer_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    unsafe {
+        let timeout = (*rlu).sync_timeout;
+        let start = timeout.map(|_| Instant::now());
+        let mut deadline = start.zip(timeout).map(|(start, timeout)| start + timeout);
+        let is_free = || (*rlu).writer_lock.load(Ordering::Relaxed) == RLU_NO_WRITER;
+        let mut waited: u64 = 0;
+        while !rlu_try_writer_lock(rlu, id) {
+            waited += 1;
+            let mut check_clock = waited.is_multiple_of(RLU_STALL_CHECK_INTERVAL);
+            match (*rlu).wait_strategy {
+                WaitStrategy::SpinThenYield { spins: max_spins }
+                    if waited > u64::from(max_spins) =>
                 {
-                    assert!(box_thread.wlog.buffer[i].is_some());
-                    box_thread.wlog.buffer[i]
-                        .as_ref()
-                        .map(|ws_copy| (*ws_copy).unlock_original());
+                    thread::yield_now()
                 }
-                box_thread.wlog.cur_pos -= box_thread.wlog.num_of_objs;
-                box_thread.wlog.num_of_objs = 0;
-            },
-        )
+                WaitStrategy::Park { spins: max_spins } if waited > u64::from(max_spins) => {
+                    rlu_park_until(rlu, is_free, deadline);
+                    check_clock = true;
+                }
+                _ => hint::spin_loop(),
+            }
+            if let (Some(at), Some(start), true) = (deadline, start, check_clock) {
+                if Instant::now() >= at {
+                    let holder = (*rlu).writer_lock.load(Ordering::Relaxed);
+                    if holder != RLU_NO_WRITER && holder != id {
+                        let blocking = vec![rlu_blocking_thread(rlu, holder)];
+                        let waited = start.elapsed();
+                        rlu_hand_stall(rlu, id, RluWaitKind::WriterLock, waited, blocking);
+                    }
+                    deadline = timeout.map(|timeout| at + timeout);
+                }
+            }
+        }
     }
 }
+
 // End internal RLU functions
 
 // Begin main externally exposed RLU functions
 
-pub fn rlu_thread_init<T: RluObj>(rlu: *mut GlobalRlu<T>) -> usize {
+/// # Safety
+/// rlu must point to a live domain.
+pub unsafe fn rlu_thread_init<T: RluObj>(rlu: *mut GlobalRlu<T>) -> Result<usize, RluError> {
     unsafe {
-        let id = (*rlu).num_threads_created.fetch_add(1, Ordering::SeqCst);
-        if id >= RLU_MAX_THREADS {
-            panic!("invalid thread id");
+        let id = (0..(*rlu).max_threads)
+            .find(|&i| {
+                (*rlu).slot_in_use[i]
+                    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or(RluError::ThreadSlotsExhausted)?;
+        //this is safe because no 2 threads will ever own the same index. A recycled slot keeps
+        //its RluThread, since other threads may still be reading its counters
+        if (*rlu).threads[id].is_none() {
+            (*rlu).threads[id] = Some(Box::new(RluThread::with_capacities(
+                (*rlu).max_threads,
+                (*rlu).log_size,
+                (*rlu).max_log_size,
+                (*rlu).max_free_nodes,
+            )));
+            (*rlu).slot_ready[id].store(true, Ordering::Release);
         }
-        //this is safe because no 2 threads will ever access the same index
-        assert!((*rlu).threads[id].is_none());
-        (*rlu).threads[id] = Some(Box::new(RluThread::new()));
-        id
+        rlu_publish_counters(rlu, id);
+        Ok(id)
     }
 }
 
-pub fn rlu_reader_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+// Gives the slot back for a later rlu_thread_init. Must be called outside of a section, and
+// id must not be used again afterwards.
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and neither it nor
+/// anything dereferenced through it may be used again once this returns Ok.
+pub unsafe fn rlu_thread_exit<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
     unsafe {
+        if rlu_in_section(rlu, id) {
+            return Err(RluError::UnbalancedSection);
+        }
+        if rlu_has_deferred_sections(rlu, id) {
+            rlu_commit_write_log(rlu, id);
+        }
+        let has_copies = (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| box_thread.wlog.has_copies() || !box_thread.deferred.is_empty(),
+        );
+        if has_copies {
+            //readers that stole our last commits may still be inside the old copies, or in
+            //whatever the callbacks still waiting retire
+            rlu_wait_for_readers(rlu, id);
+        }
         (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                assert!((box_thread.run_counter.load(Ordering::SeqCst) & 0x1) == 0);
-                box_thread.run_counter.fetch_add(1, Ordering::SeqCst);
-                box_thread.is_writer = false;
-                box_thread
-                    .local_clock
-                    .store((*rlu).global_clock.load(Ordering::SeqCst), Ordering::SeqCst);
+            |box_thread| {
+                box_thread.wlog.clear();
+                while let Some(p_version) = box_thread.mv_spare.pop() {
+                    drop(Box::from_raw(p_version));
+                }
             },
-        )
+        );
+        rlu_process_free(rlu, id);
+        (*rlu).slot_poisoned[id].store(false, Ordering::Relaxed);
+        (*rlu).slot_in_use[id].store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+// Inside a section this opens an inner one, which only ends with its own rlu_reader_unlock
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_reader_lock<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+) -> Result<(), RluError> {
+    if rlu_section_enter(rlu, id)? {
+        unsafe {
+            rlu_section_set_clock(rlu, id, (*rlu).global_clock.load(Ordering::Acquire));
+        }
     }
+    Ok(())
 }
 
-pub fn rlu_reader_unlock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+// rlu_reader_lock for a section that is going to write. In coarse mode it first takes the
+// domain's writer lock, waiting for the writer section holding it to end (see
+// rlu_wait_writer_lock), so that no rlu_try_lock of the section can fail. Inside a section it
+// opens an inner one without waiting, since the writer may be waiting for this very section,
+// and rlu_try_lock takes the writer lock if it is free. The section ends with rlu_reader_unlock
+// or rlu_abort as usual.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_writer_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
     unsafe {
-        (*rlu).threads[id].as_mut().map_or_else(
-            || unreachable!(),
-            |mut box_thread| {
-                assert!((box_thread.run_counter.load(Ordering::SeqCst) & 0x1) != 0);
-                box_thread.run_counter.fetch_add(1, Ordering::SeqCst);
-                if box_thread.is_writer {
-                    box_thread.is_writer = false;
-                    rlu_commit_write_log(rlu, id);
-                }
-            },
-        )
+        if (*rlu).locking == RluLocking::Coarse && !rlu_in_section(rlu, id) {
+            rlu_wait_writer_lock(rlu, id);
+        }
+    }
+    rlu_reader_lock(rlu, id)
+}
+
+// Only the outermost unlock commits. Err(Conflict) means an inner section aborted, which
+// undid the writes of the whole section, and the section has ended without them.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_reader_unlock<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+) -> Result<(), RluError> {
+    let is_writer = rlu_section_exit(rlu, id)?;
+    if rlu_in_section(rlu, id) {
+        return Ok(()); //an inner section
+    }
+    if !rlu_defer_commit(rlu, id, is_writer) {
+        rlu_commit_write_log(rlu, id);
     }
+    rlu_publish_counters(rlu, id);
+    if rlu_inner_aborted(rlu, id) {
+        return Err(RluError::Conflict);
+    }
+    Ok(())
 }
 
-pub fn rlu_dereference<T: RluObj>(
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and p_obj must be
+/// null or point to an object of the domain that the current section can still reach.
+pub unsafe fn rlu_dereference<T: RluObj>(
     rlu: *mut GlobalRlu<T>,
     id: usize,
     p_obj: *mut T, /* ptr to any object */
-) -> *mut T {
+) -> Result<*mut T, RluError> {
+    rlu_check_handle(rlu, id)?;
+    rlu_check_in_section(rlu, id, "rlu_dereference");
     //check if object is unlocked:
     unsafe {
         if p_obj.is_null() {
-            return p_obj;
+            return Ok(p_obj);
         }
         let p_obj_copy = (*p_obj).get_p_obj_copy();
 
         if p_obj_copy.is_null() {
             //unlocked!
-            return p_obj;
+            if (*rlu).mode == RluMode::MultiVersion {
+                return Ok(rlu_mv_dereference(rlu, id, p_obj));
+            }
+            return Ok(p_obj);
         }
         if p_obj_copy == mem::transmute(PTR_ID_OBJ_COPY) {
             // this is already a copy, it has already been referenced
-            return p_obj;
+            return Ok(p_obj);
         }
 
+        // a locked object must point to a copy made by a registered thread
         let locking_thread = (*p_obj_copy).get_locking_thread_from_ws_obj();
-        if locking_thread >=RLU_MAX_THREADS {
-            // Invalid thread id - return the original object
-            dbg!("Invalid thread id: {}", locking_thread);
-            return p_obj;
-        }
-
-        // Verify thread exists before accessing
-        if (*rlu).threads[locking_thread].is_none() {
-            dbg!("Thread entry is None for ID", locking_thread);
-            return p_obj;
+        if locking_thread >= (*rlu).max_threads || (*rlu).threads[locking_thread].is_none() {
+            return Err(RluError::CorruptedHeader);
         }
 
         if locking_thread == id {
             //locked by us!
-            return p_obj_copy;
+            return Ok(p_obj_copy);
         }
         let other_write_clock = (*rlu).threads[locking_thread].as_ref().map_or_else(
             || unreachable!(),
-            |other_thread| other_thread.write_clock.load(Ordering::SeqCst),
+            |other_thread| other_thread.shared.write_clock.load(Ordering::Acquire),
         );
         let my_local_clock = (*rlu).threads[id].as_ref().map_or_else(
             || unreachable!(),
-            |box_thread| box_thread.local_clock.load(Ordering::SeqCst),
+            |box_thread| box_thread.shared.local_clock.load(Ordering::Relaxed),
         );
         if other_write_clock <= my_local_clock {
-            p_obj_copy //steal!
+            (*rlu).threads[id].as_ref().map_or_else(
+                || unreachable!(),
+                |box_thread| box_thread.stats.add(RluCounter::Steals, 1),
+            );
+            Ok(p_obj_copy) //steal!
+        } else if (*rlu).mode == RluMode::MultiVersion {
+            Ok(rlu_mv_dereference(rlu, id, p_obj)) //no stealing, but there may be older versions
         } else {
-            p_obj //no stealing
+            Ok(p_obj) //no stealing
         }
     }
 }
 
-pub fn rlu_try_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, p_p_obj: *mut *mut T) -> bool {
-    unsafe {
-        
-        assert!(id < RLU_MAX_THREADS, "Invalid thread ID in try_lock");
-        let mut p_obj = *p_p_obj;
+// Ok(false) means the object is locked by someone else (or, in MV mode, changed since the
+// section started), and the section should be aborted and retried
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and p_p_obj must
+/// be valid for reads and writes and hold a pointer from rlu_dereference in its current
+/// section.
+pub unsafe fn rlu_try_lock<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    p_p_obj: *mut *mut T,
+) -> Result<bool, RluError> {
+    rlu_check_handle(rlu, id)?;
+    let locked = rlu_lock_obj(rlu, id, p_p_obj)?;
+    rlu_publish_counters(rlu, id);
+    if !locked {
+        unsafe {
+            (*rlu).threads[id].as_ref().map_or_else(
+                || unreachable!(),
+                |box_thread| box_thread.stats.add(RluCounter::TryLockFailures, 1),
+            );
+        }
+    }
+    Ok(locked)
+}
 
-        assert!(!p_obj.is_null()); // cant lock null pointer!
+// What rlu_try_lock_all did
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RluLockAll {
+    // Every object is locked, and every pointer points to its copy
+    Locked,
+    // Object index is held by someone else, see rlu_try_lock
+    Conflict { index: usize },
+}
 
-        // Verify thread exists
-        if (*rlu).threads[id].is_none() {
-            dbg!("Thread entry is None in try_lock", id);
-            return false;
+// Locks all of the objects, or none of them. They are locked in the address order of their
+// originals, so sections locking overlapping sets meet on the same object first instead of
+// each holding part of what the other needs. On Conflict, and on an error, the objects this
+// call locked are unlocked again, the pointers are left as they were, and a section that was
+// not a writer before the call is not one after it. Objects the section had locked before
+// stay locked, and the section should be aborted and retried as after a failed rlu_try_lock.
+// An object given twice, as an original or as a copy, fails with AlreadyLocked before
+// anything is locked.
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and every non-null
+/// pointer must come from rlu_dereference in its current section.
+pub unsafe fn rlu_try_lock_all<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    p_p_objs: &mut [&mut *mut T],
+) -> Result<RluLockAll, RluError> {
+    rlu_check_handle(rlu, id)?;
+    if p_p_objs.iter().any(|p_p_obj| p_p_obj.is_null()) {
+        return Err(RluError::NullObject);
+    }
+    let mut originals = Vec::with_capacity(p_p_objs.len());
+    for p_p_obj in p_p_objs.iter() {
+        originals.push(rlu_original_of(**p_p_obj)? as usize);
+    }
+    let mut order: Vec<usize> = (0..p_p_objs.len()).collect();
+    order.sort_by_key(|&i| originals[i]);
+    if order.windows(2).any(|pair| originals[pair[0]] == originals[pair[1]]) {
+        return Err(RluError::AlreadyLocked);
+    }
+    let mark = rlu_lock_mark(rlu, id);
+    let was_writer = (*rlu).threads[id]
+        .as_ref()
+        .map_or_else(|| unreachable!(), |box_thread| box_thread.is_writer);
+    let mut copies: Vec<*mut T> = p_p_objs.iter().map(|p_p_obj| **p_p_obj).collect();
+    for &i in &order {
+        match rlu_try_lock(rlu, id, &mut copies[i]) {
+            Ok(true) => {}
+            locked => {
+                rlu_unlock_objs_since(rlu, id, mark);
+                (*rlu).threads[id].as_mut().map_or_else(
+                    || unreachable!(),
+                    |box_thread| box_thread.is_writer = was_writer,
+                );
+                rlu_publish_counters(rlu, id);
+                return locked.map(|_| RluLockAll::Conflict { index: i });
+            }
         }
+    }
+    for (p_p_obj, copy) in p_p_objs.iter_mut().zip(copies) {
+        **p_p_obj = copy;
+    }
+    Ok(RluLockAll::Locked)
+}
 
-        (*rlu).threads[id].as_mut().map_or_else(
+// The object p_obj is, or is a copy of. Only copies are asked for it, so the order of
+// rlu_try_lock_all does not depend on what an RluObj impl returns for an original.
+fn rlu_original_of<T: RluObj>(p_obj: *mut T) -> Result<*mut T, RluError> {
+    unsafe {
+        if (*p_obj).get_p_obj_copy() != mem::transmute(PTR_ID_OBJ_COPY) {
+            return Ok(p_obj);
+        }
+        if !(*p_obj).has_ws_hdr() {
+            return Err(RluError::CorruptedHeader);
+        }
+        Ok((*p_obj).get_p_original())
+    }
+}
+
+fn rlu_lock_obj<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    p_p_obj: *mut *mut T,
+) -> Result<bool, RluError> {
+    unsafe {
+        let mut p_obj = *p_p_obj;
+        if p_obj.is_null() {
+            return Err(RluError::NullObject); // cant lock null pointer!
+        }
+
+        let can_lock = (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
+            |box_thread| {
+                if (box_thread.shared.run_counter.load(Ordering::Relaxed) & 0x1) == 0 {
+                    return Err(RluError::UnbalancedSection); //locks only live inside a section
+                }
+                if box_thread.inner_abort {
+                    return Ok(false); //the outermost section has to start over
+                }
                 box_thread.is_writer = true;
+                Ok(true)
             },
-        );
+        )?;
+        if !can_lock {
+            return Ok(false);
+        }
+        let coarse = (*rlu).locking == RluLocking::Coarse;
+        if coarse && !rlu_try_writer_lock(rlu, id) {
+            return Ok(false); //another writer section is running
+        }
         let mut p_obj_copy = (*p_obj).get_p_obj_copy();
         // dbg!("the p_obj_copy: {:?}", p_obj_copy);
         if p_obj_copy == mem::transmute(PTR_ID_OBJ_COPY) {
             //tried to lock a copy!
             //get original
-            assert!((*p_obj).has_ws_hdr());
+            if !(*p_obj).has_ws_hdr() {
+                return Err(RluError::CorruptedHeader);
+            }
             p_obj = (*p_obj).get_p_original();
             p_obj_copy = (*p_obj).get_p_obj_copy();
         }
@@ -453,98 +1971,411 @@ pub fn rlu_try_lock<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, p_p_obj: *mut
                 if (*p_obj_copy).get_ws_run_counter()
                     == (*rlu).threads[id]
                         .as_ref()
-                        .map(|thread| thread.run_counter.load(Ordering::SeqCst))
+                        .map(|thread| thread.shared.run_counter.load(Ordering::Relaxed))
                         .unwrap()
                 {
                     // dbg!("same thread");
                     // already locked by current execution of this thread
                     *p_p_obj = p_obj_copy;
-                    return true;
+                    return Ok(true);
                 }
-                //locked by other execution of this thread
-                return false;
+                //locked by a deferred section of this thread, which must commit before the
+                //object can be locked again
+                rlu_request_sync(rlu, id);
+                return Ok(false);
             }
             // locked by another thread
-            return false;
+            rlu_request_sync(rlu, th_id);
+            return Ok(false);
         }
         //unlocked!
-        let obj_copy = (*rlu).threads[id].as_mut().map_or_else(
+        if (*rlu).mode == RluMode::MultiVersion {
+            return rlu_mv_try_lock(rlu, id, p_p_obj, p_obj);
+        }
+        let reserved = (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                box_thread.wlog.buffer[box_thread.wlog.cur_pos] = Some(
-                    (*p_obj)
-                        .get_copy_with_ws_hdr(box_thread.run_counter.load(Ordering::SeqCst), id),
-                );
-                box_thread.wlog.buffer[box_thread.wlog.cur_pos]
-                    .as_mut()
-                    .unwrap()
+            |box_thread| {
+                let run_counter = box_thread.shared.run_counter.load(Ordering::Relaxed);
+                box_thread
+                    .wlog
+                    .reserve((*p_obj).get_copy_with_ws_hdr(run_counter, id))
             },
         );
+        let obj_copy = match reserved {
+            //deferred sections use up the log, committing them makes room
+            Err(RluError::WriteLogFull) if rlu_has_deferred_sections(rlu, id) => {
+                rlu_request_sync(rlu, id);
+                return Ok(false);
+            }
+            reserved => reserved?,
+        };
         // dbg!("the obj_copy:", obj_copy.get_p_obj_copy());
         // My design here differs slightly from the C implementation, in that it puts the entire
         // copy in the write log before trying to compare-and-swap the pointer in the original.
 
-        if !(*p_obj).cas(obj_copy) {
-            return false;
+        if coarse {
+            (*p_obj).store_copy(obj_copy); //the writer lock keeps other writers out
+        } else if !(*p_obj).cas(obj_copy) {
+            return Ok(false);
         }
 
         //now, update ws_hdr state
         (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                box_thread.wlog.cur_pos += 1;
-                box_thread.wlog.num_of_objs += 1;
-            },
+            |box_thread| box_thread.wlog.push_reserved(),
         );
 
         *p_p_obj = obj_copy; //new
 
-        return true;
+        Ok(true)
     }
 }
 
-pub fn rlu_abort<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
-    unsafe {
+// Aborting an inner section undoes the writes of the whole section, since a conflict cannot
+// be waited out inside it: the lock holder's commit waits for this very section to end. The
+// thread stays in the outermost section, whose locks fail from here on and whose unlock
+// returns Err(Conflict), so that it gets aborted and retried as a whole.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_abort<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    let ended = unsafe {
         (*rlu).threads[id].as_mut().map_or_else(
             || unreachable!(),
-            |mut box_thread| {
-                let prev = box_thread.run_counter.fetch_add(1, Ordering::SeqCst);
-                assert!((prev & 0x1) != 0);
+            |box_thread| {
+                if (box_thread.shared.run_counter.load(Ordering::Relaxed) & 0x1) == 0 {
+                    return Err(RluError::UnbalancedSection);
+                }
+                box_thread.stats.add(RluCounter::Aborts, 1);
+                //the section's frees and callbacks were for changes that are now undone
+                box_thread.free_nodes.truncate(box_thread.free_mark);
+                box_thread.deferred.truncate(box_thread.deferred_mark);
                 if box_thread.is_writer {
                     box_thread.is_writer = false;
                     rlu_unlock_objs(rlu, id);
                 }
+                if box_thread.nesting > 0 {
+                    box_thread.nesting -= 1;
+                    box_thread.inner_abort = true;
+                    return Ok(false);
+                }
+                box_thread.shared.run_counter.fetch_add(1, Ordering::Release);
+                Ok(true)
             },
-        )
+        )?
+    };
+    rlu_publish_counters(rlu, id);
+    if !ended {
+        return Ok(());
+    }
+    rlu_release_writer_lock(rlu, id);
+    rlu_wake_writers(rlu);
+    //the retry may need a lock that a deferred section holds
+    if !rlu_defer_commit(rlu, id, false) {
+        rlu_commit_write_log(rlu, id);
+    }
+    Ok(())
+}
+
+// Aborts every section the thread has open, nested ones included, for a thread unwinding out
+// of them. Its locks are released and its run_counter is even again, so writers neither wait
+// for it nor find its objects locked.
+pub(crate) fn rlu_unwind_sections<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) {
+    while rlu_check_handle(rlu, id).is_ok() && rlu_in_section(rlu, id) {
+        if unsafe { rlu_abort(rlu, id) }.is_err() {
+            break;
+        }
+    }
+}
+
+// Marks the slot of a thread that unwound out of a section without a handle to give the slot
+// back, see RluUnwindGuard. Other threads find it with GlobalRlu::poisoned_threads and give it
+// back with rlu_reclaim, or the owner, if it caught the panic, keeps it with rlu_clear_poison.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_poison<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    if rlu_in_section(rlu, id) {
+        return Err(RluError::UnbalancedSection); //abort it first
     }
+    unsafe { (*rlu).slot_poisoned[id].store(true, Ordering::Release) };
+    Ok(())
 }
 
-pub unsafe fn rlu_free<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, p_obj: *mut T) {
-    assert!((*p_obj).is_copy()); //cant free node you havent locked!
+/// # Safety
+/// rlu must point to a live domain. id may be any slot of it.
+pub unsafe fn rlu_is_poisoned<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<bool, RluError> {
+    rlu_check_handle(rlu, id)?;
+    Ok(unsafe { (*rlu).slot_poisoned[id].load(Ordering::Acquire) })
+}
+
+// Keeps a poisoned slot for its owner. It races with rlu_reclaim for the slot, and only one
+// of them wins: Err(NotPoisoned) (or InvalidHandle, once the slot is given back) means another
+// thread has reclaimed it, and the owner must treat id as exited and not use it again.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_clear_poison<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    let cleared = unsafe {
+        (*rlu).slot_poisoned[id]
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    };
+    if !cleared {
+        return Err(RluError::NotPoisoned); //reclaimed by another thread
+    }
+    Ok(())
+}
+
+// Exits a poisoned thread on behalf of its owner, committing its deferred sections and giving
+// its slot back. Only one caller gets to reclaim a slot, the others get Err(NotPoisoned).
+// That commit waits for every section open on the domain, so the calling thread must not be
+// in one of its own, or it waits for itself forever. RluHandle::reclaim checks this.
+/// # Safety
+/// rlu must point to a live domain. id may be another thread's slot, and its owner must not
+/// use it again once this returns Ok.
+pub unsafe fn rlu_reclaim<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    let claimed = unsafe {
+        (*rlu).slot_poisoned[id]
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    };
+    if !claimed {
+        return Err(RluError::NotPoisoned);
+    }
+    rlu_thread_exit(rlu, id)
+}
+
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and p_obj must be
+/// null or a pointer from rlu_dereference in its current section. The object must be unlinked
+/// from everything readers can reach before the section commits.
+pub unsafe fn rlu_free<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    p_obj: *mut T,
+) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    if p_obj.is_null() {
+        return Err(RluError::NullObject);
+    }
+    if !(*p_obj).is_copy() {
+        return Err(RluError::NotLocked); //cant free node you havent locked!
+    }
 
     (*rlu).threads[id].as_mut().map_or_else(
         || unreachable!(),
-        |mut box_thread| {
-            assert!(box_thread.free_nodes_size < RLU_MAX_FREE_NODES);
-            box_thread.free_nodes[box_thread.free_nodes_size] = (*p_obj).get_p_original();
-            box_thread.free_nodes_size += 1;
+        |box_thread| {
+            //the list only holds objects unlinked by this section, which readers can still
+            //reach until we commit, so it cannot be reclaimed early
+            if box_thread.free_nodes.len() + box_thread.deferred.len()
+                >= box_thread.max_free_nodes
+            {
+                return Err(RluError::FreeListFull);
+            }
+            box_thread.free_nodes.push((*p_obj).get_p_original());
+            box_thread.publish_counters();
+            Ok(())
         },
-    );
+    )
+}
+
+// Queues f to run once every section open right now has ended, for cleanup that readers
+// could still be racing with, like closing a file an unlinked object refers to. Inside a
+// section f belongs to it: it runs after the section commits, and is dropped if it aborts.
+// Outside of one it runs after this thread's next grace period, which is its next commit,
+// rlu_synchronize or rlu_thread_exit. Callbacks count against max_free_nodes like rlu_free.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_defer<T, F>(rlu: *mut GlobalRlu<T>, id: usize, f: F) -> Result<(), RluError>
+where
+    T: RluObj,
+    F: FnOnce() + Send + 'static,
+{
+    rlu_check_handle(rlu, id)?;
+    unsafe {
+        (*rlu).threads[id].as_mut().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                if box_thread.free_nodes.len() + box_thread.deferred.len()
+                    >= box_thread.max_free_nodes
+                {
+                    return Err(RluError::FreeListFull);
+                }
+                box_thread.deferred.push(Box::new(f));
+                box_thread.publish_counters();
+                Ok(())
+            },
+        )
+    }
+}
+
+// Blocks until every section that was open when it was called has ended, then runs the
+// callbacks this thread queued with rlu_defer. Like a commit it must be called outside of
+// a section, and it commits deferred sections too.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_synchronize<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    if rlu_in_section(rlu, id) {
+        return Err(RluError::UnbalancedSection);
+    }
+    if rlu_has_deferred_sections(rlu, id) {
+        rlu_commit_write_log(rlu, id); //synchronizes, and runs the callbacks after
+    } else {
+        rlu_wait_for_readers(rlu, id);
+        rlu_process_free(rlu, id);
+    }
+    Ok(())
+}
+
+// rlu_synchronize that waits at most timeout for the sections open when it was called. If
+// one is still open by then, it reports the readers to the domain's stall handler and
+// returns SyncTimeout with the report, leaving the callbacks for a later synchronize.
+// Deferred sections are always left pending, along with the callbacks, since their commit
+// would wait for readers again with no bound: rlu_flush commits them.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_try_synchronize<T: RluObj>(
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    timeout: Duration,
+) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    if rlu_in_section(rlu, id) {
+        return Err(RluError::UnbalancedSection);
+    }
+    rlu_await_readers(rlu, id, Some(timeout), true).map_err(RluError::SyncTimeout)?;
+    if !rlu_has_deferred_sections(rlu, id) {
+        rlu_process_free(rlu, id);
+    }
+    Ok(())
+}
+
+// Commits the sections this thread deferred in deferral mode, see
+// GlobalRluBuilder::defer_commits. Must be called outside of a section.
+/// # Safety
+/// rlu must point to a live domain, and id must be the slot the calling thread got from
+/// rlu_thread_init.
+pub unsafe fn rlu_flush<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize) -> Result<(), RluError> {
+    rlu_check_handle(rlu, id)?;
+    if rlu_in_section(rlu, id) {
+        return Err(RluError::UnbalancedSection);
+    }
+    if rlu_has_deferred_sections(rlu, id) {
+        rlu_commit_write_log(rlu, id);
+    }
+    Ok(())
 }
 
-pub fn rlu_assign_ptr<T: RluObj>(p_ptr: *mut *mut T, p_obj: *mut T) {
+/// # Safety
+/// p_ptr must be valid for writes, and p_obj must be null or point to an object of the domain
+/// (or a copy of one) that the current section can still reach.
+pub unsafe fn rlu_assign_ptr<T: RluObj>(p_ptr: *mut *mut T, p_obj: *mut T) {
     unsafe {
         if p_obj.is_null() {
             (*p_ptr) = p_obj; //assign null
             return;
         }
-        if (*p_obj).is_copy() {
-            (*p_ptr) = (*p_obj).get_p_original();
+        let p_original = if (*p_obj).is_copy() {
+            (*p_obj).get_p_original()
         } else {
-            //already original
-            (*p_ptr) = p_obj;
+            p_obj //already original
+        };
+        rlu_check_assigned(p_original);
+        (*p_ptr) = p_original;
+    }
+}
+
+// With the "debug-checks" feature, panics unless p_obj is a copy that the current section of
+// thread id locked, which is the only kind of object a section may write to. RluLocked checks
+// every mutable access with it, code on the raw functions can call it before its writes.
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and p_obj must
+/// point to a live object of the domain.
+#[cfg(feature = "debug-checks")]
+pub unsafe fn rlu_check_write<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, p_obj: *mut T) {
+    rlu_check_in_section(rlu, id, "a write");
+    let obj = p_obj as usize;
+    unsafe {
+        if !(*p_obj).is_copy() || !(*p_obj).has_ws_hdr() {
+            rlu_violation(RluViolation::WriteToOriginal { id, obj });
+        }
+        let owner = (*p_obj).get_locking_thread_from_ws_obj();
+        if owner != id {
+            rlu_violation(RluViolation::WriteToForeignCopy { id, obj, owner });
         }
+        let copy_run_counter = (*p_obj).get_ws_run_counter();
+        (*rlu).threads[id].as_ref().map_or_else(
+            || unreachable!(),
+            |box_thread| {
+                let run_counter = box_thread.shared.run_counter.load(Ordering::Relaxed);
+                // copies of deferred sections stay locked into later ones, see rlu_defer_commit
+                let deferred = box_thread.deferred_sections > 0 && copy_run_counter < run_counter;
+                if copy_run_counter != run_counter && !deferred {
+                    rlu_violation(RluViolation::StaleCopy {
+                        id,
+                        obj,
+                        copy_run_counter,
+                        run_counter,
+                    });
+                }
+            },
+        );
+    }
+}
+
+/// # Safety
+/// rlu must point to a live domain, id must be the calling thread's slot, and p_obj must
+/// point to a live object of the domain.
+#[cfg(not(feature = "debug-checks"))]
+#[inline(always)]
+pub unsafe fn rlu_check_write<T: RluObj>(_rlu: *mut GlobalRlu<T>, _id: usize, _p_obj: *mut T) {}
+
+#[cfg(feature = "debug-checks")]
+fn rlu_check_in_section<T: RluObj>(rlu: *mut GlobalRlu<T>, id: usize, op: &'static str) {
+    if !rlu_in_section(rlu, id) {
+        rlu_violation(RluViolation::OutsideSection { id, op });
+    }
+}
+
+#[cfg(not(feature = "debug-checks"))]
+#[inline(always)]
+fn rlu_check_in_section<T: RluObj>(_rlu: *mut GlobalRlu<T>, _id: usize, _op: &'static str) {}
+
+#[cfg(feature = "debug-checks")]
+unsafe fn rlu_check_assigned<T: RluObj>(p_obj: *mut T) {
+    if !p_obj.is_null() && (*p_obj).is_copy() {
+        rlu_violation(RluViolation::AssignedCopy {
+            obj: p_obj as usize,
+        });
+    }
+}
+
+#[cfg(not(feature = "debug-checks"))]
+#[inline(always)]
+unsafe fn rlu_check_assigned<T: RluObj>(_p_obj: *mut T) {}
+
+// A copy without its header would be written back to whatever its stale original pointer says
+#[cfg(feature = "debug-checks")]
+fn rlu_check_log_copy<T: RluObj>(id: usize, obj_copy: &T) {
+    if !obj_copy.has_ws_hdr() || obj_copy.get_locking_thread_from_ws_obj() != id {
+        rlu_violation(RluViolation::CopyLostHeader {
+            id,
+            obj: obj_copy as *const T as usize,
+        });
     }
 }
 
+#[cfg(not(feature = "debug-checks"))]
+#[inline(always)]
+fn rlu_check_log_copy<T: RluObj>(_id: usize, _obj_copy: &T) {}
+
 // End main externally exposed RLU functions
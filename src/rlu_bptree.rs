@@ -1,14 +1,22 @@
 use std::fmt::Debug;
-use std::sync::atomic::{AtomicPtr, Ordering};
 use std::ptr;
-use crate::rlu::{RluObj, RluObjHdr,  WsHdr, PTR_ID_OBJ_COPY, RLU_MAX_THREADS};
-use crate::{rlu_abort, rlu_dereference, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init, GlobalRlu, rlu_try_lock};
+use std::sync::Arc;
+use crate::rlu::{rlu_latest, GlobalRluBuilder, RluMode, RluObj, RluObjHdr};
+use rlu_derive::RluObj;
+use crate::rlu_group::{RluGroup, RluGroupMember, RluGroupReadGuard, RluGroupWriteGuard};
+use crate::rlu_guard::RluSection;
+use crate::rlu_local::RluShared;
+use crate::rlu_txn::RluWriter;
+use crate::rlu_error::RluError;
+use crate::rlu_inspect::RluSnapshot;
+use crate::GlobalRlu;
 
 
 // lets define the node order for simplicity
 const B: usize = 4; // small order for demonstration
 
-#[derive(Debug)]
+#[derive(Debug, RluObj)]
+#[rlu(crate = "crate")]
 pub struct Node<K:Clone , V: Clone> {
     // The RLU header for managing concurreny:
     pub hdr: RluObjHdr<Node<K, V>>,
@@ -31,13 +39,12 @@ pub struct Node<K:Clone , V: Clone> {
     // In internal nodes, children pointers are stored
     pub values: [Option<V>; B],
 
-    // For internal nodes, values would be child pointers, for now, lets keep it simple
-    // we'll refine this in a leter step.
-
     // If leaf, a pointer to the next leaf node (for range queries):
     pub next_leaf: *mut Node<K, V>,
 
-    // parent pointr
+    // parent pointer, null for the root. A split locks every child that moves to the new
+    // node to keep it up to date. Writers find their way back up through the path they took
+    // down, so this is only for users of the tree.
     pub parent: *mut Node<K, V>,
 }
 
@@ -45,10 +52,7 @@ pub struct Node<K:Clone , V: Clone> {
 impl<K: Clone + Copy, V: Clone + Copy> Node<K, V> {
     pub fn new(is_leaf: bool) -> Self {
         Node {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                ws_hdr: None,
-            },
+            hdr: RluObjHdr::new(),
             is_leaf,
             num_keys: 0,
             keys: [None; B],
@@ -56,7 +60,6 @@ impl<K: Clone + Copy, V: Clone + Copy> Node<K, V> {
             values: [None; B],
             next_leaf: ptr::null_mut(),
             parent: ptr::null_mut(),
-
         }
     }
     pub fn set_parent(&mut self, parent: *mut Node<K, V>) {
@@ -64,1163 +67,511 @@ impl<K: Clone + Copy, V: Clone + Copy> Node<K, V> {
     }
 }
 
-impl<K: Clone, V: Clone> RluObj for Node<K, V> {
-    fn get_p_obj_copy(&self) -> *mut Self {
-        self.hdr.p_obj_copy.load(Ordering::SeqCst)
-    }
-
-    fn is_locked(&self) -> bool {
-        // if p_obj_copy is not null and not equal to PTR_ID_OBJ_COPY, then it is locked
-        let p = self.hdr.p_obj_copy.load(Ordering::SeqCst);
-        !p.is_null() && p != PTR_ID_OBJ_COPY as *mut Self
+impl<K: Clone + Copy + Ord, V: Clone + Copy> Node<K, V> {
+    // Slot of key in a leaf, if present
+    fn position_of(&self, key: &K) -> Option<usize> {
+        self.keys[..self.num_keys].iter().position(|k| k.as_ref() == Some(key))
     }
 
-    fn is_copy(&self) -> bool {
-        self.hdr.ws_hdr.is_some()
+    // if key < keys[0], child = children[0]
+    // If keys[i-1] <= key < keys[i], child = children[i]
+    // If key >= keys[num_keys - 1], child = children[num_keys]
+    fn child_index(&self, key: &K) -> usize {
+        self.keys[..self.num_keys]
+            .iter()
+            .position(|k_opt| k_opt.as_ref().is_none_or(|k| key < k))
+            .unwrap_or(self.num_keys)
     }
 
-    fn has_ws_hdr(&self) -> bool {
-        self.hdr.ws_hdr.is_some()
+    fn insert_into_leaf(&mut self, key: K, value: V) {
+        // insert key in sorted order, caller made sure there is room
+        let pos = self.child_index(&key);
+        for i in (pos..self.num_keys).rev() {
+            self.keys[i + 1] = self.keys[i].take();
+            self.values[i + 1] = self.values[i].take();
+        }
+        self.keys[pos] = Some(key);
+        self.values[pos] = Some(value);
+        self.num_keys += 1;
     }
 
-    fn get_p_original(&self) -> *mut Self {
-        // If this is a copy, we should have a ws_hdr. If not, return self pointer.
-        if let Some(ws) = &self.hdr.ws_hdr {
-            ws.p_obj_actual
-        } else {
-            self as *const Self as *mut Self
+    // Splits a full leaf while inserting key. The left half stays here, the right half goes
+    // to a new leaf that only this section can see until it commits. Returns the separator
+    // for the parent and the new leaf, which the caller frees if the section aborts.
+    fn split_leaf(&mut self, key: K, value: V) -> (K, *mut Node<K, V>) {
+        // temporary array to hold all keys+values including the new one
+        let mut temp_keys = Vec::with_capacity(B + 1);
+        let mut temp_values = Vec::with_capacity(B + 1);
+        for i in 0..self.num_keys {
+            temp_keys.push(self.keys[i].take().unwrap());
+            temp_values.push(self.values[i].take().unwrap());
         }
-    }
+        let pos = temp_keys.iter().position(|k| k > &key).unwrap_or(temp_keys.len());
+        temp_keys.insert(pos, key);
+        temp_values.insert(pos, value);
 
-    fn get_locking_thread_from_ws_obj(&self) -> usize {
-        if let Some(ws) = &self.hdr.ws_hdr {
-            if ws.thread_id >= RLU_MAX_THREADS {
-                // Invalid thread ID detected
-                // dbg!("Invalid thread ID in ws_hdr", ws.thread_id);
-                return RLU_MAX_THREADS;  // Return invalid ID
-            }
-            ws.thread_id
-        } else {
-            // if no ws_hdr, it's not locked, return invalid
-            RLU_MAX_THREADS  // Return invalid ID for unlocked objects
+        // Split into two halves, left half goes back into this leaf
+        let split = B.div_ceil(2);
+        for i in 0..split {
+            self.keys[i] = Some(temp_keys[i]);
+            self.values[i] = Some(temp_values[i]);
         }
-    }
+        self.num_keys = split;
 
-    fn get_ws_run_counter(&self) -> u64 {
-        if let Some(ws) = &self.hdr.ws_hdr {
-            ws.run_counter
-        } else {
-            0
+        let new_leaf = unsafe { &mut *Box::into_raw(Box::new(Node::new(true))) };
+        for i in split..(B + 1) {
+            new_leaf.keys[i - split] = Some(temp_keys[i]);
+            new_leaf.values[i - split] = Some(temp_values[i]);
         }
-    }
+        new_leaf.num_keys = (B + 1) - split;
 
-    fn get_copy_with_ws_hdr(&self, run_counter: u64, thread_id: usize) -> Self {
-        // Create a copy of the node:
-        let mut copy = Node {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                ws_hdr: Some(WsHdr {
-                    p_obj_actual: self.get_p_original(),
-                    run_counter,
-                    thread_id,
-                }),
-            },
-            is_leaf: self.is_leaf,
-            num_keys: self.num_keys,
-            keys: self.keys.clone(),
-            children: self.children,
-            values: self.values.clone(),
-            next_leaf: self.next_leaf,
-            parent: self.parent,
-        };
-
-        // Mar the copy's p_obj_copy to point as PTR_ID_OBJ_COPy to indicate it is a copy
-        copy.hdr
-            .p_obj_copy
-            .store(PTR_ID_OBJ_COPY as *mut Self, Ordering::SeqCst);
-        copy
-    }
+        // fix leaf links
+        new_leaf.next_leaf = self.next_leaf;
+        self.next_leaf = new_leaf;
 
-    fn cas(&self, new_obj: *mut Self) -> bool {
-        // Compre-and-swap on p_pbj_copy. We expect it to be nul if unlocked, and set it to newoj.
-        let expected = ptr::null_mut();
-        self.hdr
-            .p_obj_copy
-            .compare_exchange(
-                expected, 
-                new_obj, 
-                Ordering::SeqCst,
-                Ordering::SeqCst,
-            )
-            .is_ok() 
+        // The split key for the parent is the first key of the new leaf
+        (temp_keys[split], new_leaf)
     }
 
-    fn copy_back_to_original(&self) {
-        // dbg!("copy_back_to_original called");
-        // In a full implementation, we would copy all fields from this ( a ws copy) tk the original
-        // self is the copy, so its original is get_p_original()
-        // Safety: we know self is copy, so ws_hdr is SOme and p_obj_actual is valid.
-        let orig = self.get_p_original();
-        unsafe {
-            (*orig).is_leaf = self.is_leaf;
-            (*orig).num_keys = self.num_keys;
-            for i in 0..B {
-                (*orig).keys[i] = self.keys[i].clone();
-                assert!((*orig).num_keys <= B, "num_keys out of range after copy_back");
-                (*orig).values[i] = self.values[i].clone();
-            }
-            for i in 0..=B {
-                (*orig).children[i] = self.children[i];
-            }
-            (*orig).parent = self.parent;
-            (*orig).next_leaf = self.next_leaf;
-            // Clear the ws_hdr so this node is no longer considered a copy
-            (*orig).hdr.ws_hdr = None;
-            // IMPORTANT: unlock the node after copy-back
-            
-            (*orig).hdr.p_obj_copy.store(ptr::null_mut(), Ordering::SeqCst);
+    // Adds the separator and right child produced by a split below. Caller made sure there
+    // is room.
+    fn insert_child(&mut self, key: K, right: *mut Node<K, V>) {
+        let pos = self.child_index(&key);
+        for i in (pos..self.num_keys).rev() {
+            self.keys[i + 1] = self.keys[i].take();
+            self.children[i + 2] = self.children[i + 1];
         }
-        // dbg!("copy_back_to_original completed");
+        self.keys[pos] = Some(key);
+        self.children[pos + 1] = right;
+        self.num_keys += 1;
     }
 
-    fn unlock_original(&self) {
-        // dbg!("unlock_original called");
-        let orig = self.get_p_original();
-        unsafe {
-            (*orig)
-                .hdr
-                .p_obj_copy
-                .store(ptr::null_mut(), Ordering::SeqCst);
+    // Splits a full internal node while adding key/right. Returns the key that moves up to
+    // the parent and the new right node, whose children still point at this node as their
+    // parent.
+    fn split_internal(&mut self, key: K, right: *mut Node<K, V>) -> (K, *mut Node<K, V>) {
+        let mut temp_keys: Vec<K> = Vec::with_capacity(B + 1);
+        let mut temp_children = Vec::with_capacity(B + 2);
+        let pos = self.child_index(&key);
+        for i in 0..self.num_keys {
+            temp_keys.push(self.keys[i].take().unwrap());
         }
-        // dbg!("unlock_original completed");
-    }
+        temp_children.extend_from_slice(&self.children[..=self.num_keys]);
+        temp_keys.insert(pos, key);
+        temp_children.insert(pos + 1, right);
+
+        // Left node keeps keys[0..split], the split key moves up, right node gets the rest
+        let split = B.div_ceil(2);
+        self.children[..=split].copy_from_slice(&temp_children[..=split]);
+        self.children[split + 1..].fill(ptr::null_mut());
+        for (i, key) in temp_keys.iter().enumerate().take(split) {
+            self.keys[i] = Some(*key);
+        }
+        self.num_keys = split;
+
+        let new_node = unsafe { &mut *Box::into_raw(Box::new(Node::new(false))) };
+        for i in (split + 1)..temp_keys.len() {
+            new_node.keys[i - split - 1] = Some(temp_keys[i]);
+            new_node.children[i - split - 1] = temp_children[i];
+        }
+        new_node.children[temp_keys.len() - split - 1] = temp_children[temp_keys.len()];
+        new_node.num_keys = temp_keys.len() - split - 1;
 
-    fn unlock(&self) {
-        // Just unlock this node, if its locked. if its a copy we set original's p_obj_copy to PTR_ID_OBJ_COPY
-        // Actually, unlcok is typically only called on the orignal or somethin  or something that was locked
-        // Acoording to this RLU logic, unlocking happens via unlock_original().
-        self.unlock_original();
+        (temp_keys[split], new_node)
     }
 }
 
+// Shared between threads as is, each thread gets its own RLU thread on first use. The domain
+// is declared first so the last clone gives back every thread's slot before freeing the nodes.
 #[derive(Debug)]
-pub struct BPlusTree<K: Clone, V: Clone> {
-    rlu: *mut GlobalRlu<Node<K, V>>,
-    id: usize,
-    root: *mut Node<K, V>,
+pub struct BPlusTree<K: 'static + Clone, V: 'static + Clone> {
+    rlu: RluShared<Node<K, V>>,
+    nodes: Arc<BPlusTreeNodes<K, V>>,
+    member: Option<RluGroupMember<Node<K, V>>>, //the tree's domain, if it is part of a group
 }
 
-unsafe impl<K: Clone, V: Clone> Send for BPlusTree<K, V> {}
-unsafe impl<K: Clone, V: Clone > Sync for BPlusTree<K, V> {}
+// The tree, shared by every clone_ref and freed with the last one
+#[derive(Debug)]
+struct BPlusTreeNodes<K: Clone, V: Clone> {
+    // Internal node whose children[0] is the real root. It never changes, so every handle
+    // shares it, and growing the tree is an ordinary RLU update of the anchor.
+    anchor: *mut Node<K, V>,
+}
 
-impl<K: Ord + Clone + Copy + Debug + Unpin + Default, V: Ord + Clone + Copy + Debug + Unpin> BPlusTree<K, V> {
-    pub fn clone_ref(&self) -> Self {
-        let thread_id = rlu_thread_init(self.rlu);
-        BPlusTree {
-            rlu: self.rlu,
-            root: self.root,
-            id: thread_id,
+impl<K: Clone, V: Clone> BPlusTreeNodes<K, V> {
+    // Frees node and everything below it. Every node has exactly one parent (the anchor
+    // counts as an internal node without keys), so next_leaf is not followed.
+    unsafe fn free_subtree(node: *mut Node<K, V>) {
+        let current = &*rlu_latest(node);
+        if !current.is_leaf {
+            for &child in &current.children[..=current.num_keys] {
+                BPlusTreeNodes::free_subtree(child);
+            }
         }
+        drop(Box::from_raw(node)); //current may be one of its versions, so it goes last
     }
-    pub fn new() -> Self {
-        // Initialise globa RLU
-        let rlu = GlobalRlu::<Node<K,V>>::init_rlu();
-        let id = rlu_thread_init(rlu);
-
-        // for a brand new tree, creeate a single leaf node as root
-        // We'll allocate it on the heap:
-        let root_box = Box::new(Node::new(true));
-        let root_ptr = Box::into_raw(root_box);
+}
 
-        BPlusTree {
-            rlu,
-            id,
-            root: root_ptr,
-        }
+impl<K: Clone, V: Clone> Drop for BPlusTreeNodes<K, V> {
+    fn drop(&mut self) {
+        unsafe { BPlusTreeNodes::free_subtree(self.anchor) }
     }
+}
 
-    // Basi search method
-    // FOr now, we assume the three has only a root node ( no splits) and keys are only in root.
-    // later, we'lll traverse the treee properly,
-    pub fn search(&self, key: &K) -> Option<V> {
-        // Aquire reader lock 
-        unsafe {
-            // acrue reader lock for duration of search
-            rlu_reader_lock(self.rlu, self.id);
-
-            // If tree is empty
-            let mut node_ptr = rlu_dereference(self.rlu, self.id, self.root);
-            // dbg!("search called for key {:?}", &key);
-            // dbg!("search called and root is {:?}", &*node_ptr);
-            if node_ptr.is_null() {
-                // othing in tree
-                rlu_reader_unlock(self.rlu, self.id);
-                return None;
-            }
-
-            loop {
-                let node = &*node_ptr;
-
-                if node.num_keys == 0 {
-                    // Empty node
-                    rlu_reader_unlock(self.rlu, self.id);
-                    return None;
-                }
-
-                if node.is_leaf {
-                    // we've reached a lead, search linearly for noww do binary search later
-                    for i in 0..node.num_keys {
-                        if let Some(ref k) =node.keys[i] {
-                            match key.cmp(k) {
-                                std::cmp::Ordering::Equal => {
-                                    // FOund the key
-                                    let val = node.values[i].clone();
-                                    rlu_reader_unlock(self.rlu, self.id);
-                                    return val;
-                                }
-                                std::cmp::Ordering::Less => {
-                                    // Since keys are sorted, once we find a key> search key,
-                                    // We won't find the target key futrther.
-                                    break;
-                                }
-                                std::cmp::Ordering::Greater => continue,
-                            }
-                        } else {
-                            // No key at i, should not happen if num_keys is correct, but just in case
-                            break;
-                        }
-                    }
-
-                    // key not found in this leaf
-                    rlu_reader_unlock(self.rlu, self.id);
-                    return None;
-                } else {
-                    // Internal Node, find the correct child to descend into
-
-                    // the logic : 
-                    // if key < keys[0], child = children[0]
-                    // If keys[i-1] <= key < keys[i], child = children[i]
-                    // If key >= keys[num_keys - 1], child = children[num_keys]
-
-                    let mut child_idx = node.num_keys; // default to last child
-                    // dbg!("searching in internal node", &node);
-                    // dereference the child pointer
-                    let child = rlu_dereference(self.rlu, self.id, node.children[child_idx]);
-                    // dbg!("child to descend into", &*child);
-
-
-                    for i in 0..node.num_keys {
-                        if let Some(ref k) = node.keys[i] {
-                            if key < k {
-                                child_idx = i;
-                                break;
-                            }
-                        } else {
-                            // unexpected missing key, just descent into i
-                            child_idx = i;
-                            break;
-                        }
-                    }
-                    // dbg!("Descending into child index", child_idx);
-                    let child_ptr = node.children[child_idx];
-                    if child_ptr.is_null() {
-                        // No child? tree might be in an incomplete state or something went wrong
-                        rlu_reader_unlock(self.rlu, self.id);
-                        return None;
-                    }
+unsafe impl<K: 'static + Clone, V: 'static + Clone> Send for BPlusTree<K, V> {}
+unsafe impl<K: 'static + Clone, V: 'static + Clone > Sync for BPlusTree<K, V> {}
 
-                    // Move down to child
-                    node_ptr = rlu_dereference(self.rlu, self.id, child_ptr);
-                    // dbg!("dereferenced child node", &*node_ptr);
-                    if node_ptr.is_null() {
-                        // Child pointer incalid 
-                        rlu_reader_unlock(self.rlu, self.id);
-                        return None;
-                    }
-                    // Loop continues untill we reach a leaf or fail
-                }
-            }
-            // // In a full B+tree, we'd descend through internal nodes to find the leaf node
-            // // For now, just read the root node directly
+impl<K: 'static + Ord + Clone + Copy + Debug + Unpin + Default, V: 'static + Ord + Clone + Copy + Debug + Unpin> BPlusTree<K, V> {
+    pub fn clone_ref(&self) -> Self {
+        BPlusTree {
+            rlu: self.rlu.clone(),
+            nodes: self.nodes.clone(),
+            member: self.member,
         }
     }
-    
-    
-    
-    /// Insert operation (just a placeholder for now)
-    /// In the future, we will:
-    /// - Acquire reader lock
-    /// - Find leaf node
-    /// - Upgrade to writer lock using rlu_try_lock for that node
-    /// - Insert the key, possibly splitting if full, locking additional nodes as needed
-    /// - Commit or abort the transaction
-    
-    pub fn insert(&mut self, key:K, value:V) {
-        unsafe  {
-            rlu_reader_lock(self.rlu, self.id);
-            
-
-            // First descent down to the appropriate leaf node
-            let leaf_ptr = self.find_leaf_for_key(&key);
-            // dbg!("leaf where to insert is : ", &*leaf_ptr);
-            if leaf_ptr.is_null() {
-                // Tree might be empty, create irst leaf node as root
-                // Let's lock the root (whicj mightbe null) - in a real scenario
-                // we’d have logic to handle empty tree carefully.
-                self.insert_empty_tree_case(key, value);
-                rlu_reader_unlock(self.rlu, self.id);
-                return;
-            } else {
-                // self.debug_node_rlu_state(leaf_ptr, "Leaf before locking");
-            }
-
-            // Now we have a leaf node pointer:
-            // Let's try to lock it as a writer
-            let mut p_leaf = leaf_ptr;
-            // dbg!("Attempting to lock leaf node for insertion");
-            if !rlu_try_lock(self.rlu, self.id, &mut p_leaf)  {
-                // Could not lock, abort this attempt and rety
-                rlu_abort(self.rlu, self.id);
-                // self.insert(key, value); // naive retry, ideally handle more gracefully
-                return;
-            }
-
-            let leaf_ref = &mut *p_leaf;
-
-            //Insert ey into leaf. If there's more room, just insert
-            if leaf_ref.num_keys < B {
-                // dbg!(" Leaf has space, inserting key direectly");
-                self.insert_into_leaf(leaf_ref, key, value);
-                // Reader unlock will commit changes
-                rlu_reader_unlock(self.rlu, self.id);
-                return;
-            } else {
-                // Need to split leaf
-                // dbg!("Leaf is full, splitting");
-                let (new_leaf_ptr, split_key) = self.split_leaf(leaf_ref, key, value);
-                // dbg!(" new leaf and split key are", &*new_leaf_ptr, &split_key);
-                // In insert_into_parent after creating new root:
-
-                // WE just created a new leaf and got a split_key to push up
-
-                // Unlock leaf and commit to ensure leaf changes are visible before dealing with parent
-                // Note: In traditional RLU usage, you might choose to do all changes in one atomic RLU write transaction,
-                // but let's keep it simple and commit step by step.
-                rlu_reader_unlock(self.rlu, self.id);
-
-                // Now we must insert `split_key` into the parent. This means we must:
-                // 1) Re-lock as a reader
-                // 2) Find the parent of this leaf (for now, assume we store parent pointers or handle a root special case)
-                // 3) Lock parent and insert the split_key as a separator, possibly splitting again if needed.
-                rlu_reader_lock(self.rlu, self.id);
-
-                // // Instead of using leaf_ptr directly, find the leaf again:
-                let leaf_ptr_deref = self.find_leaf_for_key(&split_key);
-                if leaf_ptr_deref.is_null() {
-                    // dbg!("could not find leaf after commit");
-                    rlu_reader_unlock(self.rlu, self.id);
-                    return;
-                }
-
-                // // Now re-derive new_leaf_ptr:
-                let new_leaf_ptr_deref = rlu_dereference(self.rlu, self.id, new_leaf_ptr);
-                self.insert_into_parent(leaf_ptr_deref, new_leaf_ptr_deref, split_key);
-                rlu_reader_unlock(self.rlu, self.id);
-                // this is how everything looks like after everything
-                rlu_reader_lock(self.rlu, self.id);
-                // dbg!("Tree after insertion", &*self.root);
-                // print all child nodes
-                let root = &*self.root;
-                for i in 0..root.num_keys {
-                    let child = rlu_dereference(self.rlu, self.id, root.children[i]);
-                    // dbg!("Child node", &*child);
-                }
-                rlu_reader_unlock(self.rlu, self.id);
-                // Validate parent pointers after insertion
-                // assert!(self.validate_parent_pointers(), "Parent pointers inconsistent after insertion");
-                // return;
-
-            }
-        }
+    pub fn new() -> Self {
+        BPlusTree::with_mode(RluMode::SingleVersion)
     }
-
-
-    // Find the lead node where the key should be inserted.
-    // SImilar to searhc, but we stop when we find a leaf
-    unsafe fn find_leaf_for_key(&self, key: &K) -> *mut Node<K, V> {
-        
-        let mut node_ptr = rlu_dereference(self.rlu, self.id, self.root);
-
-        while !node_ptr.is_null() {
-            let node = &*node_ptr;
-            if node.is_leaf {
-                return node_ptr;
-            } else {
-                // Internalnode : find child
-                let mut child_idx = node.num_keys;
-                for i in 0..node.num_keys {
-                    if let Some(ref k) = node.keys[i] {
-                        if key < k {
-                            child_idx = i;
-                            break;
-                        }
-
-                    } else {
-                        child_idx = i;
-                        break;
-                    }
-
-                }
-
-                let child_ptr = node.children[child_idx];
-                if child_ptr.is_null() {
-                    return  ptr::null_mut();
-                }
-                node_ptr = rlu_dereference(self.rlu, self.id, child_ptr);
-            }
-        }
-        ptr::null_mut()
+    pub fn with_mode(mode: RluMode) -> Self {
+        BPlusTree::with_builder(GlobalRlu::builder().mode(mode))
     }
-
-    unsafe fn insert_into_leaf(&self, leaf: &mut Node<K,V>, key: K, value: V) {
-        // dbg!("insert_into_leaf called", &key, &value);
-        // insert key in sorted orer
-        let pos = leaf.keys.iter()
-                                    .take(leaf.num_keys)
-                                    .position(|k_opt| k_opt.as_ref().map_or(true, |k|  k > &key))
-                                    .unwrap_or(leaf.num_keys);
-        
-        // SHift to make room
-        for i in (pos..leaf.num_keys).rev(){
-            leaf.keys[i+1] = leaf.keys[i].take();
-            leaf.values[i+1] = leaf.values[i].take();
-        }
-
-        leaf.keys[pos] = Some(key);
-        leaf.values[pos] = Some(value);
-        leaf.num_keys += 1;
-        // assert!(leaf.num_keys <= B, "num_keys exceeded B after inserting into leaf");
-        // dbg!("Inserted keys and values are:");
-        // dbg!("number of keys are {:?}", leaf.num_keys);
-        // dbg!("the root of this leaf is {:?}", );
-        // dbg!("the root looks like {:?}", &self.root);
-        // dbg!(&leaf.keys[..leaf.num_keys], &leaf.values[..leaf.num_keys]);
-
+    // Splits lock one node per level, so a capped max_log_size limits the tree's height
+    pub fn with_builder(builder: GlobalRluBuilder<Node<K, V>>) -> Self {
+        BPlusTree::with_shared(RluShared::new(builder), None)
     }
 
-    unsafe fn split_leaf(&self, leaf: &mut Node<K, V>, key: K, value: V) ->  (*mut Node<K, V>, K) {
-        // temporary array to hold all keys+values including the new one
-
-        let mut temp_keys = Vec::with_capacity(B+1);
-        let mut temp_values = Vec::with_capacity(B+1);
-
-        for i in 0..leaf.num_keys {
-            temp_keys.push(leaf.keys[i].take().unwrap());
-            temp_values.push(leaf.values[i].take().unwrap());
-        }
-
-        // Insert new key/ value into temp arrays
-        let pos = temp_keys.iter()
-                            .position(| k| k > &key )
-                            .unwrap_or(temp_keys.len());
-        temp_keys.insert(pos, key);
-        temp_values.insert(pos, value);
-
-        // SPlit into two halves
-        let split = (B+1)/2;
-
-        // // Clear the original leaf first
-        // for i in 0..B {
-        //     leaf.keys[i] = None;
-        //     leaf.values[i] = None;
-        // }
-
-        // left half goes back into leaf
-        for i in 0..split {
-            leaf.keys[i] = Some(temp_keys[i].clone());
-            leaf.values[i] = Some(temp_values[i].clone());
-        }
-
-        leaf.num_keys = split;
-        for i in split..B {
-            leaf.keys[i] = None;
-            leaf.values[i] = None;
-        }
-
-        
-        // keep track of old next_leaf
-        let old_next_leaf = leaf.next_leaf;
-
-        // Right half goes into new leaf
-        let new_leaf_box = Box::new(Node::new(true));
-        let new_leaf_ptr = Box::into_raw(new_leaf_box);
-
-        // setup new leaf properties
-        // lock the new leaf
-        let mut p_new_leaf = new_leaf_ptr;
-        if !rlu_try_lock(self.rlu, self.id, &mut p_new_leaf) {
-            rlu_abort(self.rlu, self.id);
-            return (ptr::null_mut(), key);
-        }
+    /// A tree whose domain is part of group, so that a group section can read and change it
+    /// together with the group's other domains, see `search_in` and `try_insert_in`. The
+    /// tree's own operations still work on their own, but not inside a section of the group.
+    pub fn in_group(group: &mut RluGroup, builder: GlobalRluBuilder<Node<K, V>>) -> Self {
+        let (rlu, member) = group.add_shared(builder);
+        BPlusTree::with_shared(rlu, Some(member))
+    }
 
-        let new_leaf = &mut *p_new_leaf;
-        let mut j =0;
+    fn with_shared(rlu: RluShared<Node<K, V>>, member: Option<RluGroupMember<Node<K, V>>>) -> Self {
+        // for a brand new tree, create a single leaf node as root
+        // We'll allocate it on the heap:
+        let root_ptr = Box::into_raw(Box::new(Node::new(true)));
+        let mut anchor = Node::new(false);
+        anchor.children[0] = root_ptr;
 
-        for i in split..(B+1) {
-            new_leaf.keys[j] = Some(temp_keys[i].clone());
-            new_leaf.values[j] = Some(temp_values[i].clone());
-            j += 1;
+        BPlusTree {
+            // Global RLU, owned by this tree and its clones (and its group)
+            rlu,
+            nodes: Arc::new(BPlusTreeNodes {
+                anchor: Box::into_raw(Box::new(anchor)),
+            }),
+            member,
         }
-        // new_leaf.num_keys = j;
-
-        // assert!(leaf.num_keys <= B, "Left leaf too many keys after split");
-        // assert!(new_leaf.num_keys <= B, "Right leaf too many keys after split");
-
-
-        new_leaf.num_keys = (B+1) - split;
-        // fix leaf links
-        new_leaf.next_leaf = old_next_leaf;
-        // new_leaf.parent = leaf.parent;
-        // (*new_leaf_ptr).parent = leaf.parent;
-        // print!("Leaf split done. Old leaf keys: {:?}, new leaf keys: {:?}", 
-        // &leaf.keys[..leaf.num_keys], 
-        // &(*new_leaf_ptr).keys[..(*new_leaf_ptr).num_keys]);
-        
-        // leaf.next_leaf = p_new_leaf;
-        leaf.next_leaf = new_leaf_ptr;
-        // Important: Keep parent pointer of new leaf temporarily null
-        // It will be set properly in insert_into_parent
-        new_leaf.parent = ptr::null_mut();
-
-        // The split key for the parent is the first key of the new leaf
-        let split_key = new_leaf.keys[0].as_ref().unwrap().clone();
-        // dbg!("look at the new right leaf", &*p_new_leaf);
-
-        (new_leaf_ptr, split_key)
-        // (p_new_leaf, split_key)
     }
 
-    // unsafe fn insert_into_parent(&self, old_node: *mut Node<K, V>, new_node: *mut Node<K, V>, key: K) {
-    //     // todo!("insert_into_parent");
-    //     // This is complex for now let's ust put a placeholder
-    //     // A real implementation would:
-    //     // 1) If old_node is root, create a new root and assign old_node and new_node as children.
-    //     // 2) Otherwise, find parent node (we need a way to store parent pointers or do root splitting),
-    //     //    lock the parent, insert key and pointer to new_node, and if parent is full, split again.
-
-    //     dbg!("insert_into_parent called - not yet implemented");
-    //     // For now, let's assume we are dealing with a single-level tree (no internal nodes).
-    //     // Proper implementation will come later.
-    // }
-
-    unsafe fn insert_empty_tree_case(&mut self, key: K, value: V) {
-        // CReate a new leaf node as root
-
-        // rlu_reader_lock(self.rlu, self.id);
-        let root_box = Box::new(Node::new(true));
-        let root_ptr = Box::into_raw(root_box);
-        // lock root (which doesn't exist yet, so we jsut assign)
-        // self.root = root_ptr;
-
-        let mut p_root = root_ptr;
-        if !rlu_try_lock(self.rlu, self.id, &mut p_root) {
-            rlu_abort(self.rlu, self.id);
-            return;
-        }
-        let root_ref = &mut *p_root;
-        root_ref.keys[0] = Some(key);
-        root_ref.values[0] = Some(value);
-        root_ref.num_keys = 1;
-        self.root = root_ptr;
-        // dbg!(root_created = &root_ref.keys[..root_ref.num_keys]);
-
-        // rlu_reader_unlock(self.rlu, self.id);
+    fn member(&self) -> RluGroupMember<Node<K, V>> {
+        self.member.expect("BPlusTree is not part of a group, see BPlusTree::in_group")
     }
 
-    // helper function to safely set up parent-child relationships
-    unsafe fn debug_node_rlu_state(&self, node: *mut Node<K, V>, msg: &str) {
-        let node = &*node;
-        dbg!(
-            msg,
-            "Node has ws_hdr:", node.has_ws_hdr(),
-            "Is copy:", node.is_copy(),
-            "Is locked:", node.is_locked(),
-            "ws_run_counter:", node.get_ws_run_counter(),
-        );
+    pub fn search(&self, key: &K) -> Option<V> {
+        // reader section for the duration of the search
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        let leaf = self.find_leaf_for_key(&guard, key);
+        leaf.position_of(key).and_then(|i| leaf.values[i])
     }
 
-    unsafe fn insert_into_parent(&mut self, left: *mut Node<K, V>, right: *mut Node<K, V>, key: K) -> bool {
-        // dbg!("insert_into_parent called", &key);
-        // dbg!("Left node: {:?}", &*left);
-        // dbg!("Right node: {:?}", &*right);
-        // assert!(!(*left).is_copy(), "Left node should not be a copy");
-        // assert!(!(*right).is_copy(), "Right node should not be a copy");
-
-        
-
-        let left_node = &*left;
-        let parent_ptr = left_node.parent;
-
-        if parent_ptr.is_null() {
-        // if (*left).parent.is_null() {
-            // create a new root
-            // dbg!("No parent, creating a new root");
-            
-            // create a new root
-            let root_box = Box::new(Node::new(false));
-            // dbg!("the ws_hdr of new root is {:?}", &root_box.hdr.ws_hdr);
-            let root_ptr = Box::into_raw(root_box);
+    /// search in a section of the tree's group. Panics if the tree was not made with
+    /// `in_group` for the guard's group.
+    pub fn search_in(&self, guard: &RluGroupReadGuard<'_>, key: &K) -> Option<V> {
+        let section = guard.section(self.member());
+        let leaf = self.find_leaf_for_key(&section, key);
+        leaf.position_of(key).and_then(|i| leaf.values[i])
+    }
 
+    /// Insert operation, updates the value if the key is already present.
+    /// The whole insert, including any splits, happens in one transaction, which is
+    /// re-run if another writer holds one of the nodes we need.
+    /// Panics with the error of try_insert, e.g. WriteLogFull if the domain's max_log_size
+    /// is too small for a split reaching the root.
+    pub fn insert(&self, key:K, value:V) {
+        self.try_insert(key, value).unwrap_or_else(|err| panic!("{}", err));
+    }
 
-            let mut p_root = root_ptr;
+    /// insert that returns the error instead of panicking. Inside a batch that includes
+    /// Err(Conflict), when another writer holds one of the nodes.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), RluError> {
+        self.rlu.handle().transaction(|txn| self.insert_txn(txn, key, value))
+    }
 
-            if !rlu_try_lock(self.rlu, self.id, &mut p_root) {
-                // dbg!("Failed to  lock new root");
-                rlu_abort(self.rlu, self.id);
-                return false; // try again or handle error
+    /// try_insert in a section of the tree's group, which commits with the section. On
+    /// Err(Conflict) the section should be aborted and retried.
+    pub fn try_insert_in(
+        &self,
+        guard: &RluGroupWriteGuard<'_>,
+        key: K,
+        value: V,
+    ) -> Result<(), RluError> {
+        self.insert_txn(&guard.txn(self.member()), key, value)
+    }
+
+    /// Runs f in one section, so that the searches and inserts it makes on this tree
+    /// see one snapshot and are committed together.
+    /// Inside f, try_insert returns Err(Conflict) when it finds a node held by another
+    /// writer, and f passing that on re-runs it. Any other error aborts the batch and is
+    /// returned.
+    pub fn batch<R, F>(&self, mut f: F) -> Result<R, RluError>
+    where
+        F: FnMut(&Self) -> Result<R, RluError>,
+    {
+        self.rlu.handle().transaction(|_txn| f(self))
+    }
+
+    fn insert_txn<W: RluWriter<Node<K, V>>>(
+        &self,
+        txn: &W,
+        key: K,
+        value: V,
+    ) -> Result<(), RluError> {
+        // Nodes made by splits are only linked from copies, which an abort throws away
+        let mut new_nodes = Vec::new();
+        let result = self.insert_nodes(txn, key, value, &mut new_nodes);
+        if result.is_err() {
+            for node in new_nodes {
+                drop(unsafe { Box::from_raw(node) });
             }
-
-
-            let root_node = &mut *p_root;
-            root_node.num_keys = 1;
-            root_node.keys[0] = Some(key);
-            root_node.children[0] = left;
-            root_node.children[1] = right;
-
-            // Update parent pointers
-            // let mut p_left = left;
-            // let mut p_right = right;
-            (*left).parent = root_ptr;
-            (*right).parent = root_ptr;
-            // after inserting into new root, this is what the root and its children look
-            // dbg!("New root created with key {:?}", key);
-            // dbg!("root looks like", &*root_node);
-            // dbg!("Left child looks like", &*left);
-            // dbg!("Right child looks like", &*right);
-
-
-            
-
-            // dbg!("New root created with key {:?}", key);
-            // dbg!("Root ptr: 0x{:x}", root_ptr as usize);
-            // dbg!("Left ptr: 0x{:x}", left as usize);
-            // dbg!("Right ptr: 0x{:x}", right as usize);
-
-            // Use link_nodes to establish parent-child relationships
-            // if !self.link_nodes(root_ptr, left, 0) ||
-            //     !self.link_nodes(root_ptr, right, 1) {
-            //     rlu_abort(self.rlu, self.id);
-            //     return;
-            // }
-
-            // // Lock both children to update their parent pointers
-            // if !rlu_try_lock(self.rlu, self.id, &mut p_left) ||
-            //     !rlu_try_lock(self.rlu, self.id, &mut p_right) {
-            //     dbg!("Failed to lock children");
-            //     rlu_abort(self.rlu, self.id);
-            //     return;
-            // }
-            // (*p_left).parent = root_ptr;
-            // (*p_right).parent = root_ptr;
-
-            // Update tree root
-            self.root = root_ptr;
-
-            // self.debug_print_tree();
-            // Now commit the transaction which will copy back all locked nodes
-            // rlu_reader_unlock(self.rlu, self.id);
-
-            // println!("Created new root with key: {:?}", key);
-            // println!("Root keys: {:?}", &(*p_root).keys[..(*p_root).num_keys]);
-            // println!("Left child keys: {:?}", &(*left).keys[..(*left).num_keys]);
-            // println!("Right child keys: {:?}", &(*right).keys[..(*right).num_keys]);
-
-
-            // Start a new read transaction to ensure we see the committed state
-            // rlu_reader_lock(self.rlu, self.id);
-            // let root_check = rlu_dereference(self.rlu, self.id, self.root);
-            // dbg!("New root state after commit:", &*root_check);
-            // rlu_reader_unlock(self.rlu, self.id);
-            // rlu_reader_unlock(self.rlu, self.id);
-            return true;
         }
+        result
+    }
 
-        // LOCK EXISTING PARENT
-
-        // Get original parent if dealing with a copy
-        let original_parent_ptr = if (*parent_ptr).is_copy() {
-            (*parent_ptr).get_p_original()
-        } else {
-            parent_ptr
-        };
-
-        // dbg!("Parent exists, inserting key into parent");
-        // dbg!("Parent keys before insert: {:?}", &(*parent_ptr).keys[..(*parent_ptr).num_keys]);
-
-        // If we have a parent
-        // let mut p_parent = parent_ptr;
-        let mut p_parent = original_parent_ptr;
-        // rlu_reader_lock(self.rlu, self.id);
-        if !rlu_try_lock(self.rlu, self.id, &mut p_parent) {
-            rlu_abort(self.rlu, self.id);
-            return false;
+    fn insert_nodes<W: RluWriter<Node<K, V>>>(
+        &self,
+        txn: &W,
+        key: K,
+        value: V,
+        new_nodes: &mut Vec<*mut Node<K, V>>,
+    ) -> Result<(), RluError> {
+        // First descend down to the appropriate leaf node, remembering the way back up
+        let anchor = txn.deref(self.nodes.anchor).unwrap();
+        let mut path = Vec::new();
+        let mut node = txn.deref(anchor.children[0]).unwrap();
+        while !node.is_leaf {
+            path.push(node);
+            node = txn.deref(node.children[node.child_index(&key)]).unwrap();
+        }
+
+        if let Some(i) = node.position_of(&key) {
+            txn.lock(node)?.values[i] = Some(value);
+            return Ok(());
         }
 
-        let parent_node = &mut *p_parent;
-
-        // FInd insert position in parent
-        let pos = parent_node.keys[..parent_node.num_keys]
-            .iter()
-            .position(|k_opt| k_opt.as_ref().map_or(true, |k| k > &key))
-            .unwrap_or(parent_node.num_keys);
-
-        // dbg!("position in parent for key {:?} is {:?}", key, pos);
-
-        if parent_node.num_keys < B {
-            // Simple insert case
-            // Shift existing keys/children
-            for i in (pos..parent_node.num_keys).rev() {
-                parent_node.keys[i+1] = parent_node.keys[i].take();
-                parent_node.children[i+2] = parent_node.children[i+1];
-            }
-
-            parent_node.keys[pos] = Some(key);
-            parent_node.children[pos+1] = right;
-            parent_node.num_keys += 1;
-
-            // Update the right node's parent pointer
-            (*right).parent = original_parent_ptr;
-
-            // Lock right child to update parent
-            // let mut p_right = right;
-            // if !rlu_try_lock(self.rlu, self.id, &mut p_right) {
-            //     rlu_abort(self.rlu, self.id);
-            //     return;
-            // }
-            // (*p_right).parent = p_parent;
-
-            // Use link_nodes to set up the new child's parent pointer
-            // if !self.link_nodes(p_parent, right, pos + 1) {
-            //     rlu_abort(self.rlu, self.id);
-            //     return;
-            // }
-            
-            return true;
+        // Lock the leaf and every ancestor the split will reach at once, before changing
+        // anything, so a conflict never leaves half-built nodes behind. The snapshot tells
+        // which ones are full, since a node that changed since cannot be locked. The last one
+        // has room (or is the anchor).
+        let mut nodes = vec![node];
+        let mut full = node.num_keys == B;
+        while full {
+            let parent = path.pop().unwrap_or(anchor);
+            full = parent.get_p_original() != self.nodes.anchor && parent.num_keys == B;
+            nodes.push(parent);
         }
-        // println!("Inserting key {:?} into parent. Parent keys before: {:?}", key, &parent_node.keys[..parent_node.num_keys]);
-        // dbg!("Inserting key into parent. Parent keys before:", &parent_node.keys[..parent_node.num_keys]);
-
-        // let original_key = key;
-        // let original_right = right;
-
-        // // Insert the key into the parent, keeping keys sorted:
-        // let pos = parent_node.keys.iter()
-        //     .take(parent_node.num_keys)
-        //     .position(|k_opt| k_opt.as_ref().map_or(true, |k| k > &key))
-        //     .unwrap_or(parent_node.num_keys);
-
-        // assert!(parent_node.num_keys < B, "Parent full before insertion! Must split first.");
-
-
-        // // Make room for new key/child
-        // for i in (pos..parent_node.num_keys).rev() {
-        //     parent_node.keys[i+1] = parent_node.keys[i].take();
-        //     parent_node.children[i+2] = parent_node.children[i+1];
-        // }
-
-        // parent_node.keys[pos] = Some(key);
-        // parent_node.children[pos+1] = right;
-        // parent_node.num_keys += 1;
-        // (*right).parent = p_parent;
-
-
-
-        
-            // Fits, no split needed
-            // rlu_reader_unlock(self.rlu, self.id);
-        
-        // Parent is full, need to split
-        // Parent needs splitting
-        // let (new_parent_ptr, parent_split_key) = self.split_internal_node(parent_node);
-        // dbg!("new_parent , left node and parent_split_key are", &*new_parent_ptr, &*parent_node, &parent_split_key);
-        // if new_parent_ptr.is_null() {
-        //     // self.insert_into_parent(p_parent, new_parent_ptr, parent_split_key);
-        //     return false;
-        // }
+        let mut ancestors = txn.lock_all(&nodes)?.into_iter();
+        let mut leaf = ancestors.next().unwrap();
 
-
-        // self.insert_into_parent(p_parent, new_parent_ptr, parent_split_key)
-
-
-        // Parent is full, need to split
-        //before spliting parent , this is what left , right and their parent looks like
-        // dbg!("Before spliting parent, left, right and their parent looks like", &*left, &*right, &*p_parent);
-        let (p_new_parent, parent_split_key) = self.split_internal_node(parent_node);
-        if p_new_parent.is_null() {
-            return false;
+        if leaf.num_keys < B {
+            // Leaf has space, insert key directly
+            leaf.insert_into_leaf(key, value);
+            return Ok(());
         }
-
-        // Validate the split result
-        // if !p_new_parent.is_null() {
-        //     assert!((*p_new_parent).is_copy(), "New parent should be a copy");
-        // }
-
-        // Now attach our original 'right' node to the appropriate parent
-        if key >= parent_split_key {
-            // Should be a child of the new right parent
-            let new_parent = &mut *p_new_parent;
-            // Find position in new parent
-            let pos = new_parent.keys[..new_parent.num_keys]
-                .iter()
-                .position(|k_opt| k_opt.as_ref().map_or(true, |k| k > &key))
-                .unwrap_or(new_parent.num_keys);
-            
-            // Shift keys and children
-            for i in (pos..new_parent.num_keys).rev() {
-                new_parent.keys[i + 1] = new_parent.keys[i].take();
-                new_parent.children[i + 2] = new_parent.children[i + 1];
+        let (mut split_key, mut right) = leaf.split_leaf(key, value);
+        new_nodes.push(right);
+        let mut left = leaf.get_p_original();
+        drop(leaf); //left may be relocked below
+        for mut parent in ancestors {
+            if parent.get_p_original() == self.nodes.anchor {
+                // the root itself split, so the tree grows a level
+                let root = Box::into_raw(Box::new(Node::new(false)));
+                new_nodes.push(root);
+                unsafe {
+                    (*root).num_keys = 1;
+                    (*root).keys[0] = Some(split_key);
+                    (*root).children[0] = left;
+                    (*root).children[1] = right;
+                }
+                parent.children[0] = root;
+                self.set_parent_of(txn, left, root, new_nodes)?;
+                self.set_parent_of(txn, right, root, new_nodes)?;
+                break;
             }
-            
-            // Insert the key and right node
-            new_parent.keys[pos] = Some(key);
-            new_parent.children[pos + 1] = right;
-            new_parent.num_keys += 1;
-            (*right).parent = p_new_parent;
-        } else {
-            // Should be a child of the original parent
-            // Find position in original parent
-            let pos = parent_node.keys[..parent_node.num_keys]
-                .iter()
-                .position(|k_opt| k_opt.as_ref().map_or(true, |k| k > &key))
-                .unwrap_or(parent_node.num_keys);
-            
-            // Shift keys and children
-            for i in (pos..parent_node.num_keys).rev() {
-                parent_node.keys[i + 1] = parent_node.keys[i].take();
-                parent_node.children[i + 2] = parent_node.children[i + 1];
+            let original = parent.get_p_original();
+            unsafe { (*right).set_parent(original) };
+            if parent.num_keys < B {
+                parent.insert_child(split_key, right);
+                break;
             }
-            
-            // Insert the key and right node
-            parent_node.keys[pos] = Some(key);
-            parent_node.children[pos + 1] = right;
-            parent_node.num_keys += 1;
-            (*right).parent = original_parent_ptr;
-        }
-
-        // Now we can do the recursive call to handle the split
-        let result = self.insert_into_parent(original_parent_ptr, p_new_parent, parent_split_key);
-        // dbg!("Write log after insert_into_parent recursice:");
-        // self.debug_write_log();
-        // // If we're at the top level of recursion, we need to re-dereference the root
-        // // to get the most up-to-date pointer
-        // if !parent_ptr.is_null() {
-        //     let current_root = rlu_dereference(self.rlu, self.id, self.root);
-        //     if !current_root.is_null() {
-        //         self.root = current_root;
-        //     }
-        // }
-        result
-        // recursive_result
-
-    }
-
-    
-    
-    unsafe fn split_internal_node(&self, node: &mut Node<K,V>) -> (*mut Node<K,V>, K) {
-        // dbg!("Write log before split:");
-        // self.debug_write_log();
-        // dbg!("Splitting internal node, parent node looks like:", &node.keys[..node.num_keys]);
-        let mut temp_keys: Vec<K> = Vec::with_capacity(B+1);
-        let mut temp_children = Vec::with_capacity(B+2);
-
-        // Copy existing keys and children
-        for i in 0..node.num_keys {
-            temp_keys.push(node.keys[i].take().unwrap());
-            temp_children.push(node.children[i]);
-        }
-        temp_children.push(node.children[node.num_keys]);
-        // dbg!("Temp keys and children", &temp_keys, &temp_children);
-
-        // for i in 0..=node.num_keys {
-        //     temp_children.push(node.children[i]);
-        // }
-
-        let split = (B+1)/2;
-        let split_key = temp_keys[split -  1].clone();
-        // dbg!("Split key is", &split_key);
-
-        // Reset left node
-        // Left node keeps keys[0..split], right node gets keys[split+1..]
-        for i in 0..split - 1{
-            node.keys[i] = Some(temp_keys[i].clone());
-            node.children[i] = temp_children[i];
-        }
-
-        node.children[split - 1] = temp_children[split - 1];
-        node.num_keys = split - 1;
-
-        // Update parent pointers for left node's children
-        for i in 0..=node.num_keys {
-            if !node.children[i].is_null() {
-                (*node.children[i]).parent = node as *mut Node<K, V>;
+            let (up_key, new_right) = parent.split_internal(split_key, right);
+            new_nodes.push(new_right);
+            let moved = unsafe { (*new_right).children };
+            for &child in &moved[..=unsafe { (*new_right).num_keys }] {
+                self.set_parent_of(txn, child, new_right, new_nodes)?;
             }
+            split_key = up_key;
+            right = new_right;
+            left = original;
         }
+        Ok(())
+    }
 
-        for i in split - 1..B {
-            node.keys[i] = None;
-            if i < B{
-                node.children[i+1] = ptr::null_mut();
-            }
-            
-        }
-        // dbg!("Left node after split and children is :", &node.keys[..node.num_keys], &node.children[..node.num_keys+1]);
-        // dbg!("left node parent is ", &node.parent);
-
-        // create nnew right node
-        // New node (right side)
-        let new_node_box = Box::new(Node::new(false));
-        let new_node_ptr = Box::into_raw(new_node_box);
-
-        let mut p_new_node = new_node_ptr;
-        if !rlu_try_lock(self.rlu, self.id, &mut p_new_node) {
-            rlu_abort(self.rlu, self.id);
-            return (ptr::null_mut(), split_key);
-        }
-        let new_node = &mut *p_new_node;
-
-        // Fill the right node
-        let mut j = 0;
-        for i in split..temp_keys.len() {
-            new_node.keys[j] = Some(temp_keys[i].clone());
-            new_node.children[j] = temp_children[i];
-            // Update child's parent pointer
-            // if !temp_children[i].is_null() {
-            //     (*temp_children[i]).parent = new_node_ptr;
-            // }
-            j += 1;
+    // Points child's parent pointer at parent. Nodes made by this insert are not visible to
+    // other threads yet, the others are locked, so the change commits with the split.
+    fn set_parent_of<W: RluWriter<Node<K, V>>>(
+        &self,
+        txn: &W,
+        child: *mut Node<K, V>,
+        parent: *mut Node<K, V>,
+        new_nodes: &[*mut Node<K, V>],
+    ) -> Result<(), RluError> {
+        if new_nodes.contains(&child) {
+            unsafe { (*child).set_parent(parent) };
+        } else {
+            txn.lock(txn.deref(child).unwrap())?.set_parent(parent);
         }
-        new_node.children[j] = temp_children[temp_children.len() - 1];
-        new_node.num_keys = j;
+        Ok(())
+    }
 
-        // Update parent pointers for all children in right node
-        for i in 0..=new_node.num_keys {
-            if !new_node.children[i].is_null() {
-                (*new_node.children[i]).parent = p_new_node;
-            }
+    // Find the leaf node where the key is or should be inserted.
+    fn find_leaf_for_key<'g, S: RluSection<Node<K, V>>>(&self, guard: &'g S, key: &K) -> &'g Node<K, V> {
+        let anchor = guard.deref(self.nodes.anchor).unwrap();
+        let mut node = guard.deref(anchor.children[0]).unwrap();
+        while !node.is_leaf {
+            node = guard.deref(node.children[node.child_index(key)]).unwrap();
         }
-
-        new_node.parent = node.parent;
-        // dbg!(" right node after split", &new_node.keys[..new_node.num_keys], &new_node.children[..new_node.num_keys+1]);
-
-
-        // let temp_children_store = temp_children.clone();
-        // dbg!("Temp children store", &temp_children_store);
-        // new_node.children[j] = temp_children_store[temp_children_store.len() - 1];
-        // if !temp_children[temp_children_store.len() - 1].is_null() {
-        //     (*temp_children[temp_children_store.len() - 1]).parent = new_node_ptr;
-        // }
-        // new_node.num_keys = j;
-
-        // Set parent pointer for new node (will be properly set in insert_into_parent)
-        // dbg!("Right node after setup:", &new_node.keys[..new_node.num_keys]);
-        // dbg!("Right node children:", &new_node.children[..=new_node.num_keys]);
-        // dbg!("Split key being returned:", split_key);
-        // dbg!("new_node is", &*p_new_node);
-
-        
-        // rlu_reader_unlock(self.rlu, self.id);
-        // dbg!("new node in split_internal_node is", &*new_node_ptr);
-        // CRITICAL: This is where we need to ensure the lock state is correct
-        // The new node should be marked as a copy but NOT have ws_hdr
-        // (*p_new_node).hdr.p_obj_copy.store(PTR_ID_OBJ_COPY as *mut Node<K,V>, Ordering::SeqCst);
-        // (*p_new_node).hdr.ws_hdr = None;  
-        // Before returning, validate RLU state
-        assert!(new_node.is_copy(), "New node should be a copy");
-        // assert!(!new_node.has_ws_hdr(), "New node should not have ws_hdr");
-        // Commit the split
-        // rlu_reader_unlock(self.rlu, self.id);
-        // rlu_reader_lock(self.rlu, self.id);
-        // dbg!("Write log after unlock:");
-        self.debug_write_log();
-        (p_new_node, split_key)
+        node
     }
 
-
-    pub unsafe fn debug_write_log(&self) {
-        (*self.rlu).threads[self.id].as_ref().map(|thread| {
-            // dbg!("Write Log contents:");
-            // dbg!("Number of objects:", thread.wlog.num_of_objs);
-            // dbg!("Current position:", thread.wlog.cur_pos);
-            
-            // Look at objects in the log
-            for i in (thread.wlog.cur_pos - thread.wlog.num_of_objs)..thread.wlog.cur_pos {
-                if thread.wlog.buffer[i].is_some() {
-                    let obj = &thread.wlog.buffer[i].as_ref().unwrap();
-                    // dbg!("Object at position", i, "is_copy:", obj.is_copy(), 
-                    //      "is_locked:", obj.is_locked(),
-                    //      "has_ws_hdr:", obj.has_ws_hdr());
-                }
-            }
-        });
+    /// A snapshot of the tree's RLU domain, see `GlobalRlu::inspect`.
+    pub fn inspect(&self) -> RluSnapshot {
+        unsafe { (*self.rlu.rlu_ptr()).inspect() }
     }
 
     pub fn range_search(&self, start_key: &K, end_key: &K) -> Vec<(K, V)> {
         let mut result = Vec::new();
-        
-        unsafe {
-            rlu_reader_lock(self.rlu, self.id);
-            
-            // Find the leaf containing start_key
-            let mut current = self.find_leaf_for_key(start_key);
-            if current.is_null() {
-                rlu_reader_unlock(self.rlu, self.id);
-                return result;
-            }
-            
-            // Traverse the leaves using next_leaf pointers
-            loop {
-                let node = &*current;
-                
-                // Add all keys/values in current leaf that are in range
-                for i in 0..node.num_keys {
-                    if let (Some(k), Some(v)) = (&node.keys[i], &node.values[i]) {
-                        if k >= start_key && k <= end_key {
-                            result.push((*k, *v));
-                        }
-                        if k > end_key {
-                            rlu_reader_unlock(self.rlu, self.id);
-                            return result;
-                        }
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+
+        // Find the leaf containing start_key
+        let mut current = Some(self.find_leaf_for_key(&guard, start_key));
+
+        // Traverse the leaves using next_leaf pointers
+        while let Some(node) = current {
+            // Add all keys/values in current leaf that are in range
+            for i in 0..node.num_keys {
+                if let (Some(k), Some(v)) = (&node.keys[i], &node.values[i]) {
+                    if k > end_key {
+                        return result;
+                    }
+                    if k >= start_key {
+                        result.push((*k, *v));
                     }
-                }
-                
-                // Move to next leaf if it exists
-                if node.next_leaf.is_null() {
-                    break;
-                }
-                current = rlu_dereference(self.rlu, self.id, node.next_leaf);
-                if current.is_null() {
-                    break;
                 }
             }
-            
-            rlu_reader_unlock(self.rlu, self.id);
+
+            // Move to next leaf if it exists
+            current = guard.deref(node.next_leaf);
         }
         result
     }
 
-    
+
+}
+impl<K, V> Default for BPlusTree<K, V>
+where
+    K: 'static + Ord + Clone + Copy + Debug + Unpin + Default,
+    V: 'static + Ord + Clone + Copy + Debug + Unpin,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
-impl<K: Ord + Clone + Copy + Debug, V: Ord + Clone + Copy + Debug> BPlusTree<K, V> {
+
+impl<K: 'static + Ord + Clone + Copy + Debug, V: 'static + Ord + Clone + Copy + Debug> BPlusTree<K, V> {
     pub fn debug_print_tree(&self) {
         println!("\n=== B+ Tree Structure with Detailed Pointer Analysis ===");
         println!("Order (B) = {}", B);
-        
-        unsafe {
-            rlu_reader_lock(self.rlu, self.id);
-            let root = rlu_dereference(self.rlu, self.id, self.root);
-            if root.is_null() {
-                println!("Empty tree");
-                rlu_reader_unlock(self.rlu, self.id);
-                return;
-            }
 
-            println!("Root address: 0x{:x}", root as usize);
-            
-            let mut current_level = vec![root];
-            let mut level_number = 0;
-            let mut all_nodes = Vec::new(); // Keep track of all valid node addresses
-
-            while !current_level.is_empty() {
-                println!("\nLevel {}: ", level_number);
-                println!("{}", "=".repeat(80));
-                
-                let mut next_level = Vec::new();
-                
-                for (node_idx, &node_ptr) in current_level.iter().enumerate() {
-                    let node = &*node_ptr;
-                    all_nodes.push(node_ptr as usize);
-                    
-                    print!("Node {}: [", node_idx);
-                    for i in 0..node.num_keys {
-                        if let Some(key) = &node.keys[i] {
-                            print!("{:?}", key);
-                            if i < node.num_keys - 1 {
-                                print!(" | ");
-                            }
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        let anchor = guard.deref(self.nodes.anchor).unwrap();
+        let root = guard.deref(anchor.children[0]).unwrap();
+
+        println!("Root address: 0x{:x}", anchor.children[0] as usize);
+
+        let mut current_level = vec![(anchor.children[0], root)];
+        let mut level_number = 0;
+
+        while !current_level.is_empty() {
+            println!("\nLevel {}: ", level_number);
+            println!("{}", "=".repeat(80));
+
+            let mut next_level = Vec::new();
+
+            for (node_idx, &(node_ptr, node)) in current_level.iter().enumerate() {
+                print!("Node {}: [", node_idx);
+                for i in 0..node.num_keys {
+                    if let Some(key) = &node.keys[i] {
+                        print!("{:?}", key);
+                        if i < node.num_keys - 1 {
+                            print!(" | ");
                         }
                     }
-                    print!("] (addr: 0x{:x})", node_ptr as usize);
-                    
-                    if node.is_leaf {
-                        print!("🍃");
-                        if !node.next_leaf.is_null() {
-                            print!(" next_leaf: 0x{:x}", node.next_leaf as usize);
-                        }
-                    } else {
-                        print!("🔵");
-                        for i in 0..=node.num_keys {
-                            if !node.children[i].is_null() {
-                                next_level.push(node.children[i]);
-                                print!("\n    child[{}] addr: 0x{:x}", i, node.children[i] as usize);
-                            }
-                        }
+                }
+                print!("] (addr: 0x{:x})", node_ptr as usize);
+
+                if node.is_leaf {
+                    print!("🍃");
+                    if !node.next_leaf.is_null() {
+                        print!(" next_leaf: 0x{:x}", node.next_leaf as usize);
                     }
-                    
-                    if !node.parent.is_null() {
-                        print!("\n    parent addr: 0x{:x}", node.parent as usize);
-                        if !all_nodes.contains(&(node.parent as usize)) {
-                            print!(" WARNING: Parent not found in previous levels!");
+                } else {
+                    print!("🔵");
+                    for i in 0..=node.num_keys {
+                        if let Some(child) = guard.deref(node.children[i]) {
+                            next_level.push((node.children[i], child));
+                            print!("\n    child[{}] addr: 0x{:x}", i, node.children[i] as usize);
                         }
                     }
-                    println!();
                 }
-                
-                println!("{}", "-".repeat(80));
-                current_level = next_level;
-                level_number += 1;
+                println!();
             }
-            
-            rlu_reader_unlock(self.rlu, self.id);
+
+            println!("{}", "-".repeat(80));
+            current_level = next_level;
+            level_number += 1;
         }
         println!("\n=== End of Tree ===\n");
     }
 
 }
 
-impl<K: Ord + Clone + std::fmt::Debug, V: Clone + std::fmt::Debug> BPlusTree<K, V> {
-    pub fn get_tree_size(&self) -> usize {
-        unsafe {
-            // Acquire reader lock for traversal
-            rlu_reader_lock(self.rlu, self.id);
-            
-            let size = if self.root.is_null() {
-                0
-            } else {
-                self.count_nodes(self.root)
-            };
-            
-            rlu_reader_unlock(self.rlu, self.id);
-            size
-        }
+impl<K: 'static + Ord + Clone + std::fmt::Debug, V: 'static + Clone + std::fmt::Debug> BPlusTree<K, V> {
+    fn root<'g, S: RluSection<Node<K, V>>>(&self, guard: &'g S) -> &'g Node<K, V> {
+        let anchor = guard.deref(self.nodes.anchor).unwrap();
+        guard.deref(anchor.children[0]).unwrap()
     }
 
-    unsafe fn count_nodes(&self, node_ptr: *mut Node<K, V>) -> usize {
-        if node_ptr.is_null() {
-            return 0;
-        }
+    pub fn get_tree_size(&self) -> usize {
+        // Acquire reader lock for traversal
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        self.count_nodes(&guard, self.root(&guard))
+    }
 
-        let node_ptr = rlu_dereference(self.rlu, self.id, node_ptr);
-        let node = &*node_ptr;
-        
+    fn count_nodes<S: RluSection<Node<K, V>>>(&self, guard: &S, node: &Node<K, V>) -> usize {
         if node.is_leaf {
             1 // Count this leaf node
         } else {
             // Count this internal node plus all its children
             let mut count = 1;
             for i in 0..=node.num_keys {
-                if !node.children[i].is_null() {
-                    count += self.count_nodes(node.children[i]);
+                if let Some(child) = guard.deref(node.children[i]) {
+                    count += self.count_nodes(guard, child);
                 }
             }
             count
@@ -1228,37 +579,21 @@ impl<K: Ord + Clone + std::fmt::Debug, V: Clone + std::fmt::Debug> BPlusTree<K,
     }
 
     pub fn get_tree_height(&self) -> usize {
-        unsafe {
-            // Acquire reader lock for traversal
-            rlu_reader_lock(self.rlu, self.id);
-            
-            let height = if self.root.is_null() {
-                0
-            } else {
-                self.measure_height(self.root)
-            };
-            
-            rlu_reader_unlock(self.rlu, self.id);
-            height
-        }
+        // Acquire reader lock for traversal
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        self.measure_height(&guard, self.root(&guard))
     }
 
-    unsafe fn measure_height(&self, node_ptr: *mut Node<K, V>) -> usize {
-        if node_ptr.is_null() {
-            return 0;
-        }
-
-        let node_ptr = rlu_dereference(self.rlu, self.id, node_ptr);
-        let node = &*node_ptr;
-
+    fn measure_height<S: RluSection<Node<K, V>>>(&self, guard: &S, node: &Node<K, V>) -> usize {
         if node.is_leaf {
             1 // Leaf nodes are at height 1
         } else {
             // Find the maximum height among children and add 1 for this level
             let mut max_child_height = 0;
             for i in 0..=node.num_keys {
-                if !node.children[i].is_null() {
-                    let child_height = self.measure_height(node.children[i]);
+                if let Some(child) = guard.deref(node.children[i]) {
+                    let child_height = self.measure_height(guard, child);
                     max_child_height = std::cmp::max(max_child_height, child_height);
                 }
             }
@@ -1268,33 +603,28 @@ impl<K: Ord + Clone + std::fmt::Debug, V: Clone + std::fmt::Debug> BPlusTree<K,
 
     // Helper function to validate tree structure
     pub fn validate_tree_structure(&self) -> Result<(), String> {
-        unsafe {
-            rlu_reader_lock(self.rlu, self.id);
-            
-            if self.root.is_null() {
-                rlu_reader_unlock(self.rlu, self.id);
-                return Ok(());
-            }
-
-            let result = self.validate_node(self.root, None, None);
-            rlu_reader_unlock(self.rlu, self.id);
-            result
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        let root = self.root(&guard);
+        if !root.parent.is_null() {
+            return Err("Root has a parent".to_string());
+        }
+        if root.is_leaf && root.num_keys == 0 {
+            return Ok(()); // empty tree
         }
+        let anchor = guard.deref(self.nodes.anchor).unwrap();
+        self.validate_node(&guard, anchor.children[0], root, None, None)
     }
 
-    unsafe fn validate_node(
+    // node_ptr is the original of node, as found in its parent
+    fn validate_node<S: RluSection<Node<K, V>>>(
         &self,
+        guard: &S,
         node_ptr: *mut Node<K, V>,
+        node: &Node<K, V>,
         min_key: Option<&K>,
         max_key: Option<&K>,
     ) -> Result<(), String> {
-        if node_ptr.is_null() {
-            return Ok(());
-        }
-
-        let node_ptr = rlu_dereference(self.rlu, self.id, node_ptr);
-        let node = &*node_ptr;
-
         // Check number of keys is within bounds
         if node.num_keys == 0 {
             return Err("Node has no keys".to_string());
@@ -1312,10 +642,10 @@ impl<K: Ord + Clone + std::fmt::Debug, V: Clone + std::fmt::Debug> BPlusTree<K,
             }
         }
 
-        // Check key range
+        // Check key range, separators are inclusive lower bounds of the right subtree
         if let Some(min) = min_key {
             if let Some(first_key) = &node.keys[0] {
-                if first_key <= min {
+                if first_key < min {
                     return Err(format!("Key {:?} violates min bound {:?}", first_key, min));
                 }
             }
@@ -1331,14 +661,19 @@ impl<K: Ord + Clone + std::fmt::Debug, V: Clone + std::fmt::Debug> BPlusTree<K,
         // For internal nodes, recursively validate children
         if !node.is_leaf {
             for i in 0..=node.num_keys {
-                if node.children[i].is_null() {
-                    return Err(format!("Null child pointer at index {}", i));
+                let child = match guard.deref(node.children[i]) {
+                    Some(child) => child,
+                    None => return Err(format!("Null child pointer at index {}", i)),
+                };
+
+                if child.parent != node_ptr {
+                    return Err(format!("Child at index {} has the wrong parent", i));
                 }
 
                 let min = if i == 0 { min_key } else { node.keys[i-1].as_ref() };
                 let max = if i == node.num_keys { max_key } else { node.keys[i].as_ref() };
-                
-                self.validate_node(node.children[i], min, max)?;
+
+                self.validate_node(guard, node.children[i], child, min, max)?;
             }
         }
 
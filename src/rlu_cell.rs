@@ -0,0 +1,122 @@
+// A single RLU managed value, for read-mostly data such as configuration or routing tables:
+// readers never block, and an update swaps in a modified copy of the whole value.
+
+use crate::rlu::{GlobalRlu, GlobalRluBuilder, RluObjHdr};
+use crate::rlu_error::RluError;
+use crate::rlu_local::RluShared;
+use rlu_derive::RluObj;
+use std::sync::Arc;
+
+// The RLU object behind an RluCell: the value plus its header. Updates clone the value into
+// the write log, so T should be cheap to clone or hold its bulk behind an Arc.
+#[derive(RluObj)]
+#[rlu(crate = "crate")]
+pub struct RluBox<T: 'static + Clone> {
+    hdr: RluObjHdr<RluBox<T>>,
+    value: T,
+}
+
+impl<T> RluBox<T>
+where
+    T: Clone,
+{
+    pub fn new(value: T) -> RluBox<T> {
+        RluBox {
+            hdr: RluObjHdr::new(),
+            value,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+}
+
+// Like the other RLU structures, a cell can be shared between threads as is, or through
+// clone_ref()s of it. The last clone frees the value and the domain.
+pub struct RluCell<T: 'static + Clone> {
+    rlu: RluShared<RluBox<T>>,
+    owner: Arc<RluBoxOwner<T>>,
+}
+
+struct RluBoxOwner<T: 'static + Clone> {
+    obj: *mut RluBox<T>,
+}
+
+impl<T> Drop for RluBoxOwner<T>
+where
+    T: Clone,
+{
+    fn drop(&mut self) {
+        // in MV mode the original owns the versions, so they go with it
+        drop(unsafe { Box::from_raw(self.obj) });
+    }
+}
+
+unsafe impl<T: Clone + Send + Sync> Send for RluCell<T> {}
+unsafe impl<T: Clone + Send + Sync> Sync for RluCell<T> {}
+
+impl<T> RluCell<T>
+where
+    T: Clone,
+{
+    pub fn new(value: T) -> RluCell<T> {
+        RluCell::with_builder(value, GlobalRlu::builder())
+    }
+
+    pub fn with_builder(value: T, builder: GlobalRluBuilder<RluBox<T>>) -> RluCell<T> {
+        RluCell {
+            rlu: RluShared::new(builder),
+            owner: Arc::new(RluBoxOwner {
+                obj: Box::into_raw(Box::new(RluBox::new(value))),
+            }),
+        }
+    }
+
+    pub fn clone_ref(&self) -> RluCell<T> {
+        RluCell {
+            rlu: self.rlu.clone(),
+            owner: self.owner.clone(),
+        }
+    }
+
+    // Runs f on the current value inside a reader section
+    pub fn read<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        f(guard.deref(self.owner.obj).unwrap().get())
+    }
+
+    // Runs f on a write log copy of the value and commits it. Losing the lock to another
+    // writer re-runs the transaction, but f only runs once the lock is held, so it runs once.
+    pub fn update<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        // one object per section always fits the write log, a cap of zero is a setup error,
+        // and so is a conflict inside another section
+        self.try_update(f).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // update that returns the error instead of panicking. Inside another section of this
+    // thread that includes Err(Conflict), when another writer holds the value, and then f
+    // has not run.
+    pub fn try_update<R, F>(&self, f: F) -> Result<R, RluError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut f = Some(f);
+        self.rlu.handle().transaction(|txn| {
+            let mut locked = txn.lock(txn.deref(self.owner.obj).unwrap())?;
+            Ok(f.take().unwrap()(&mut locked.value))
+        })
+    }
+
+    // Replaces the value, returning the previous one
+    pub fn replace(&self, value: T) -> T {
+        self.update(|old| std::mem::replace(old, value))
+    }
+}
@@ -0,0 +1,109 @@
+// Protocol checks, compiled in with the "debug-checks" feature. A check that fails panics
+// with a report of what was misused, by which thread and on which object, at the call that
+// misused it rather than wherever the corrupted memory would have shown up. Without the
+// feature the checks in rlu.rs are empty and RluWriteCheck is a zero sized type.
+
+#[cfg(feature = "debug-checks")]
+use crate::rlu::rlu_check_write;
+use crate::rlu::{GlobalRlu, RluObj};
+#[cfg(feature = "debug-checks")]
+use std::fmt;
+#[cfg(not(feature = "debug-checks"))]
+use std::marker::PhantomData;
+
+#[cfg(feature = "debug-checks")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum RluViolation {
+    // op was called while the thread had no section open
+    OutsideSection { id: usize, op: &'static str },
+    // a write went to an object that is not a write log copy
+    WriteToOriginal { id: usize, obj: usize },
+    // a write went to a copy that another thread locked
+    WriteToForeignCopy { id: usize, obj: usize, owner: usize },
+    // a write went to a copy locked by a section of this thread that has already ended
+    StaleCopy { id: usize, obj: usize, copy_run_counter: u64, run_counter: u64 },
+    // rlu_assign_ptr stored a pointer to a copy, which readers must never reach
+    AssignedCopy { obj: usize },
+    // a copy in the write log lost the header that ties it to its original
+    CopyLostHeader { id: usize, obj: usize },
+}
+
+#[cfg(feature = "debug-checks")]
+impl fmt::Display for RluViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RluViolation::OutsideSection { id, op } => {
+                write!(f, "thread {} called {} outside of a section", id, op)
+            }
+            RluViolation::WriteToOriginal { id, obj } => write!(
+                f,
+                "thread {} wrote to {:#x}, which is not a write log copy (lock it first)",
+                id, obj
+            ),
+            RluViolation::WriteToForeignCopy { id, obj, owner } => write!(
+                f,
+                "thread {} wrote to {:#x}, a copy locked by thread {}",
+                id, obj, owner
+            ),
+            RluViolation::StaleCopy {
+                id,
+                obj,
+                copy_run_counter,
+                run_counter,
+            } => write!(
+                f,
+                "thread {} wrote to {:#x}, a copy locked at run counter {} by a section that \
+                 has ended (run counter is now {})",
+                id, obj, copy_run_counter, run_counter
+            ),
+            RluViolation::AssignedCopy { obj } => write!(
+                f,
+                "rlu_assign_ptr stored {:#x}, which is a write log copy",
+                obj
+            ),
+            RluViolation::CopyLostHeader { id, obj } => write!(
+                f,
+                "copy {:#x} in the write log of thread {} lost its write-set header",
+                obj, id
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+pub(crate) fn rlu_violation(violation: RluViolation) -> ! {
+    panic!("RLU protocol violation: {}", violation)
+}
+
+// What RluLocked needs to check its writes: the section's domain and thread
+#[cfg(feature = "debug-checks")]
+pub(crate) struct RluWriteCheck<T: RluObj> {
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+}
+
+#[cfg(feature = "debug-checks")]
+impl<T: RluObj> RluWriteCheck<T> {
+    pub(crate) fn new(rlu: *mut GlobalRlu<T>, id: usize) -> RluWriteCheck<T> {
+        RluWriteCheck { rlu, id }
+    }
+
+    #[inline]
+    pub(crate) fn check(&self, p_obj: *mut T) {
+        unsafe { rlu_check_write(self.rlu, self.id, p_obj) };
+    }
+}
+
+#[cfg(not(feature = "debug-checks"))]
+pub(crate) struct RluWriteCheck<T: RluObj>(PhantomData<*mut T>);
+
+#[cfg(not(feature = "debug-checks"))]
+impl<T: RluObj> RluWriteCheck<T> {
+    #[inline(always)]
+    pub(crate) fn new(_rlu: *mut GlobalRlu<T>, _id: usize) -> RluWriteCheck<T> {
+        RluWriteCheck(PhantomData)
+    }
+
+    #[inline(always)]
+    pub(crate) fn check(&self, _p_obj: *mut T) {}
+}
@@ -0,0 +1,61 @@
+use crate::rlu_watchdog::RluStallReport;
+use std::error::Error;
+use std::fmt;
+
+// Errors reported by the RLU functions. Losing a lock race is not an error: rlu_try_lock
+// returns Ok(false) for that, and the section should be aborted and retried. Transactions
+// turn it into Conflict, which is what makes RluHandle::transaction retry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RluError {
+    // Every thread slot of the domain is registered
+    ThreadSlotsExhausted,
+    // The section locked more objects than the domain's max_log_size allows
+    WriteLogFull,
+    // The section freed (or deferred) more than the domain's max_free_nodes allows
+    FreeListFull,
+    // Closing (or locking in) a section that isn't open
+    UnbalancedSection,
+    // The thread id was never registered, or has exited
+    InvalidHandle,
+    // An object's lock points to something that is not a copy owned by a registered thread
+    CorruptedHeader,
+    // Locking or freeing a null pointer
+    NullObject,
+    // Freeing an object that this section has not locked
+    NotLocked,
+    // Destroying a domain that still has registered threads
+    DomainInUse,
+    // A transaction lost a lock race, or its closure asked to be re-run. Also what closing a
+    // section returns when a section nested in it aborted.
+    Conflict,
+    // Reclaiming a thread slot that was not poisoned, see rlu_reclaim
+    NotPoisoned,
+    // Readers were still in their sections when rlu_try_synchronize timed out, as reported
+    // to the stall handler
+    SyncTimeout(RluStallReport),
+    // Locking an object while an RluLocked of it is alive, or giving one object twice to a
+    // lock_all
+    AlreadyLocked,
+}
+
+impl fmt::Display for RluError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RluError::ThreadSlotsExhausted => write!(f, "no free RLU thread slots"),
+            RluError::WriteLogFull => write!(f, "RLU write log is full"),
+            RluError::FreeListFull => write!(f, "RLU free list is full"),
+            RluError::UnbalancedSection => write!(f, "unbalanced RLU section"),
+            RluError::InvalidHandle => write!(f, "invalid RLU thread id"),
+            RluError::CorruptedHeader => write!(f, "corrupted RLU object header"),
+            RluError::NullObject => write!(f, "null RLU object"),
+            RluError::NotLocked => write!(f, "RLU object is not locked by this section"),
+            RluError::DomainInUse => write!(f, "RLU domain still has registered threads"),
+            RluError::Conflict => write!(f, "RLU transaction conflict"),
+            RluError::NotPoisoned => write!(f, "RLU thread slot is not poisoned"),
+            RluError::SyncTimeout(report) => write!(f, "RLU synchronize timed out: {}", report),
+            RluError::AlreadyLocked => write!(f, "RLU object locked while it is already held"),
+        }
+    }
+}
+
+impl Error for RluError {}
@@ -0,0 +1,526 @@
+// A group of RLU domains of different object types that share one clock. A section opened
+// through a group handle covers every domain of the group with a single snapshot, and its
+// writes to all of them commit together. Each domain keeps its own typed write logs, the
+// group drives them through the type erased RluMember trait.
+
+use crate::rlu::{
+    rlu_abort, rlu_assign_ptr, rlu_commit_finish, rlu_commit_start, rlu_dereference, rlu_free,
+    rlu_in_section, rlu_inner_aborted, rlu_section_enter, rlu_section_exit, rlu_section_set_clock,
+    rlu_thread_exit, rlu_thread_init, rlu_try_lock, rlu_try_lock_all, CachePadded, GlobalRlu,
+    GlobalRluBuilder, RluLockAll, RluObj,
+};
+use crate::rlu_error::RluError;
+use crate::rlu_guard::{RluDomain, RluHeld, RluLocked, RluSection};
+use crate::rlu_local::RluShared;
+use crate::rlu_txn::RluWriter;
+use crate::rlu_sync::AtomicU64;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+// The parts of a domain a group needs, without its object type
+trait RluMember: Send + Sync {
+    fn thread_init(&self) -> Result<usize, RluError>;
+    fn thread_exit(&self, id: usize) -> Result<(), RluError>;
+    fn enter(&self, id: usize) -> Result<bool, RluError>;
+    fn set_clock(&self, id: usize, clock: u64);
+    fn exit(&self, id: usize) -> Result<bool, RluError>;
+    fn in_section(&self, id: usize) -> bool;
+    fn inner_aborted(&self, id: usize) -> bool;
+    fn abort(&self, id: usize) -> Result<(), RluError>;
+    fn commit_start(&self, id: usize, write_clock: u64);
+    fn commit_finish(&self, id: usize, write_clock: u64);
+    fn domain(&self) -> *const ();
+}
+
+// Shares one domain of the group, which is destroyed with the group, or with the structure
+// built on it if that goes last
+struct RluMemberDomain<T: RluObj> {
+    domain: Arc<RluDomain<T>>,
+}
+
+impl<T: RluObj> RluMemberDomain<T> {
+    fn rlu(&self) -> *mut GlobalRlu<T> {
+        self.domain.rlu
+    }
+}
+
+impl<T> RluMember for RluMemberDomain<T>
+where
+    T: RluObj,
+{
+    fn thread_init(&self) -> Result<usize, RluError> {
+        unsafe { rlu_thread_init(self.rlu()) }
+    }
+    fn thread_exit(&self, id: usize) -> Result<(), RluError> {
+        unsafe { rlu_thread_exit(self.rlu(), id) }
+    }
+    fn enter(&self, id: usize) -> Result<bool, RluError> {
+        rlu_section_enter(self.rlu(), id)
+    }
+    fn set_clock(&self, id: usize, clock: u64) {
+        rlu_section_set_clock(self.rlu(), id, clock);
+    }
+    fn exit(&self, id: usize) -> Result<bool, RluError> {
+        rlu_section_exit(self.rlu(), id)
+    }
+    fn in_section(&self, id: usize) -> bool {
+        rlu_in_section(self.rlu(), id)
+    }
+    fn inner_aborted(&self, id: usize) -> bool {
+        rlu_inner_aborted(self.rlu(), id)
+    }
+    fn abort(&self, id: usize) -> Result<(), RluError> {
+        unsafe { rlu_abort(self.rlu(), id) }
+    }
+    fn commit_start(&self, id: usize, write_clock: u64) {
+        rlu_commit_start(self.rlu(), id, write_clock);
+    }
+    fn commit_finish(&self, id: usize, write_clock: u64) {
+        rlu_commit_finish(self.rlu(), id, write_clock);
+    }
+    fn domain(&self) -> *const () {
+        self.rlu() as *const ()
+    }
+}
+
+// Names one domain of a group, and the type of the objects it holds
+pub struct RluGroupMember<T: RluObj> {
+    index: usize,
+    rlu: *mut GlobalRlu<T>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: RluObj> Clone for RluGroupMember<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: RluObj> Copy for RluGroupMember<T> {}
+
+impl<T: RluObj> fmt::Debug for RluGroupMember<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RluGroupMember").field("index", &self.index).finish()
+    }
+}
+
+unsafe impl<T: RluObj> Send for RluGroupMember<T> {}
+unsafe impl<T: RluObj> Sync for RluGroupMember<T> {}
+
+impl<T> RluGroupMember<T>
+where
+    T: RluObj,
+{
+    // The domain itself, which RluHandle::new can also register on for sections that only
+    // touch this type. It lives as long as the group.
+    pub fn rlu_ptr(&self) -> *mut GlobalRlu<T> {
+        self.rlu
+    }
+}
+
+pub struct RluGroup {
+    clock: Arc<CachePadded<AtomicU64>>,
+    members: Vec<Box<dyn RluMember>>,
+}
+
+impl RluGroup {
+    pub fn new() -> RluGroup {
+        RluGroup {
+            clock: Arc::default(),
+            members: Vec::new(),
+        }
+    }
+
+    // Adds a domain built from builder. Domains can only be added before the group is shared.
+    // Group sections commit every domain at once, so panics if builder defers commits or
+    // locks coarsely.
+    pub fn add<T: RluObj + 'static>(&mut self, builder: GlobalRluBuilder<T>) -> RluGroupMember<T> {
+        let rlu = builder.shared_clock(self.clock.clone()).init_rlu();
+        self.add_domain(Arc::new(RluDomain::new(rlu)))
+    }
+
+    // Adds a domain that threads can also use on their own through the returned RluShared,
+    // for the structures built on one, see RluSet::in_group
+    pub(crate) fn add_shared<T: RluObj + 'static>(
+        &mut self,
+        builder: GlobalRluBuilder<T>,
+    ) -> (RluShared<T>, RluGroupMember<T>) {
+        let shared = RluShared::new(builder.shared_clock(self.clock.clone()));
+        let member = self.add_domain(shared.domain().clone());
+        (shared, member)
+    }
+
+    fn add_domain<T: RluObj + 'static>(&mut self, domain: Arc<RluDomain<T>>) -> RluGroupMember<T> {
+        let rlu = domain.rlu;
+        self.members.push(Box::new(RluMemberDomain { domain }));
+        RluGroupMember {
+            index: self.members.len() - 1,
+            rlu,
+            _marker: PhantomData,
+        }
+    }
+
+    // Shares the finished group, and registers its first thread
+    pub fn handle(self) -> RluGroupHandle {
+        RluGroupHandle::new(Arc::new(self))
+    }
+}
+
+impl Default for RluGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// One thread of a group, registered in each of its domains. Like RluHandle it can move to
+// another OS thread, clone_ref registers another one, and the last handle frees the group.
+pub struct RluGroupHandle {
+    ids: Vec<usize>,
+    group: Arc<RluGroup>,
+    held: RluHeld,
+}
+
+unsafe impl Send for RluGroupHandle {}
+
+impl RluGroupHandle {
+    // Panics if a domain of the group has no free thread slot
+    pub fn new(group: Arc<RluGroup>) -> RluGroupHandle {
+        RluGroupHandle::try_new(group).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new(group: Arc<RluGroup>) -> Result<RluGroupHandle, RluError> {
+        let mut ids = Vec::with_capacity(group.members.len());
+        for member in group.members.iter() {
+            match member.thread_init() {
+                Ok(id) => ids.push(id),
+                Err(err) => {
+                    for (member, &id) in group.members.iter().zip(ids.iter()) {
+                        member.thread_exit(id)?;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(RluGroupHandle {
+            ids,
+            group,
+            held: RluHeld::default(),
+        })
+    }
+
+    pub fn clone_ref(&self) -> RluGroupHandle {
+        RluGroupHandle::new(self.group.clone())
+    }
+
+    pub fn try_clone_ref(&self) -> Result<RluGroupHandle, RluError> {
+        RluGroupHandle::try_new(self.group.clone())
+    }
+
+    // This handle's thread id in the member's domain
+    fn id<T: RluObj>(&self, member: RluGroupMember<T>) -> usize {
+        let belongs = self
+            .group
+            .members
+            .get(member.index)
+            .is_some_and(|domain| domain.domain() == member.rlu as *const ());
+        assert!(belongs, "RluGroupMember from another group");
+        self.ids[member.index]
+    }
+
+    fn deref<T: RluObj>(&self, member: RluGroupMember<T>, p_obj: *mut T) -> Option<&T> {
+        match unsafe { rlu_dereference(member.rlu, self.id(member), p_obj) } {
+            Ok(p_obj) => {
+                self.held.check_deref(p_obj as *const ());
+                unsafe { p_obj.as_ref() }
+            }
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn try_lock<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        obj: &T,
+    ) -> Result<Option<RluLocked<'_, T>>, RluError> {
+        let (id, mut p_obj) = (self.id(member), obj as *const T as *mut T);
+        if unsafe { rlu_try_lock(member.rlu, id, &mut p_obj) }? {
+            RluLocked::new(member.rlu, id, p_obj, &self.held).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn try_lock_all<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        objs: &[&T],
+    ) -> Result<Result<Vec<RluLocked<'_, T>>, usize>, RluError> {
+        let id = self.id(member);
+        let mut p_objs: Vec<*mut T> = objs.iter().map(|&obj| obj as *const T as *mut T).collect();
+        let mut p_p_objs: Vec<&mut *mut T> = p_objs.iter_mut().collect();
+        match unsafe { rlu_try_lock_all(member.rlu, id, &mut p_p_objs) }? {
+            RluLockAll::Locked => p_objs
+                .into_iter()
+                .map(|p_obj| RluLocked::new(member.rlu, id, p_obj, &self.held))
+                .collect::<Result<Vec<_>, RluError>>()
+                .map(Ok),
+            RluLockAll::Conflict { index } => Ok(Err(index)),
+        }
+    }
+
+    fn free<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        obj: RluLocked<'_, T>,
+    ) -> Result<(), RluError> {
+        unsafe { rlu_free(member.rlu, self.id(member), obj.as_ptr()) }
+    }
+
+    // Enters every domain before reading the clock, so that a commit which moves the clock
+    // after this point waits for the section in all of them. A section nested in another one
+    // of this handle keeps the outer one's clock.
+    fn enter(&self) {
+        let mut outermost = false;
+        for (member, &id) in self.group.members.iter().zip(self.ids.iter()) {
+            outermost |= member.enter(id).unwrap_or_else(|err| panic!("{}", err));
+        }
+        if !outermost {
+            return;
+        }
+        self.held.clear();
+        let clock = self.group.clock.load(Ordering::Acquire);
+        for (member, &id) in self.group.members.iter().zip(self.ids.iter()) {
+            member.set_clock(id, clock);
+        }
+    }
+
+    // Leaves every domain and commits the ones the section wrote to under one write clock.
+    // Like rlu_reader_unlock, Err(Conflict) means a section nested in this one aborted, which
+    // undid the writes of the whole section in every domain.
+    fn exit(&self) -> Result<(), RluError> {
+        let mut writers = Vec::new();
+        for (member, &id) in self.group.members.iter().zip(self.ids.iter()) {
+            if member.exit(id).unwrap_or_else(|err| panic!("{}", err)) {
+                writers.push((member, id));
+            }
+        }
+        let mut members = self.group.members.iter().zip(self.ids.iter());
+        if members.clone().any(|(member, &id)| member.in_section(id)) {
+            return Ok(()); //an inner section
+        }
+        if !writers.is_empty() {
+            let write_clock = self.group.clock.load(Ordering::Relaxed) + 1;
+            for (member, id) in writers.iter() {
+                member.commit_start(*id, write_clock);
+            }
+            self.group.clock.fetch_add(1, Ordering::Release);
+            for (member, id) in writers.iter() {
+                member.commit_finish(*id, write_clock);
+            }
+        }
+        if members.any(|(member, &id)| member.inner_aborted(id)) {
+            return Err(RluError::Conflict);
+        }
+        Ok(())
+    }
+
+    fn abort(&self) -> Result<(), RluError> {
+        for (member, &id) in self.group.members.iter().zip(self.ids.iter()) {
+            member.abort(id)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&self) -> RluGroupReadGuard<'_> {
+        self.enter();
+        RluGroupReadGuard { handle: self }
+    }
+
+    pub fn write(&self) -> RluGroupWriteGuard<'_> {
+        self.enter();
+        RluGroupWriteGuard {
+            handle: self,
+            finished: false,
+        }
+    }
+}
+
+impl Drop for RluGroupHandle {
+    fn drop(&mut self) {
+        for (member, &id) in self.group.members.iter().zip(self.ids.iter()) {
+            member.thread_exit(id).unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+// A reader section over every domain of a group, with one snapshot
+pub struct RluGroupReadGuard<'a> {
+    handle: &'a RluGroupHandle,
+}
+
+impl<'a> RluGroupReadGuard<'a> {
+    // p_obj must be null or a pointer read from an object of member (or a root)
+    pub fn deref<T: RluObj>(&self, member: RluGroupMember<T>, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(member, p_obj)
+    }
+
+    // The section seen from member, for code written against RluSection
+    pub fn section<T: RluObj>(&self, member: RluGroupMember<T>) -> RluGroupSection<'_, T> {
+        RluGroupSection {
+            handle: self.handle,
+            member,
+        }
+    }
+
+    pub fn unlock(self) {}
+}
+
+impl<'a> Drop for RluGroupReadGuard<'a> {
+    fn drop(&mut self) {
+        let result = self.handle.exit();
+        if !thread::panicking() {
+            result.unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+// A writer section over every domain of a group. Like RluWriteGuard it commits when dropped,
+// in every domain at once, and aborts in all of them on abort() or a panic. Dropping it
+// panics if a section nested in it aborted, commit() returns that as Err(Conflict).
+pub struct RluGroupWriteGuard<'a> {
+    handle: &'a RluGroupHandle,
+    finished: bool,
+}
+
+impl<'a> RluGroupWriteGuard<'a> {
+    // p_obj must be null or a pointer read from an object of member (or a root)
+    pub fn deref<T: RluObj>(&self, member: RluGroupMember<T>, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(member, p_obj)
+    }
+
+    // None means another writer holds the object, and the section should be aborted and
+    // retried. Like RluWriteGuard::try_lock, an object with a live RluLocked fails with
+    // AlreadyLocked.
+    pub fn try_lock<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        obj: &T,
+    ) -> Result<Option<RluLocked<'_, T>>, RluError> {
+        self.handle.try_lock(member, obj)
+    }
+
+    // Locks all of objs or none of them, see RluWriteGuard::try_lock_all
+    pub fn try_lock_all<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        objs: &[&T],
+    ) -> Result<Result<Vec<RluLocked<'_, T>>, usize>, RluError> {
+        self.handle.try_lock_all(member, objs)
+    }
+
+    // The section seen from member, for code written against RluWriter, like the operations
+    // of the structures built with in_group. Its locks return Err(Conflict) when another
+    // writer holds the object, and the whole section should then be aborted and retried.
+    pub fn txn<T: RluObj>(&self, member: RluGroupMember<T>) -> RluGroupTxn<'_, T> {
+        RluGroupTxn {
+            handle: self.handle,
+            member,
+        }
+    }
+
+    // Stores the original of obj (never a copy) in a pointer field
+    pub fn assign<T: RluObj>(&self, p_ptr: &mut *mut T, obj: Option<&T>) {
+        let p_obj = obj.map_or(ptr::null_mut(), |o| o as *const T as *mut T);
+        unsafe { rlu_assign_ptr(p_ptr, p_obj) };
+    }
+
+    // Frees the original of a locked object once no reader can see it anymore
+    pub fn free<T: RluObj>(
+        &self,
+        member: RluGroupMember<T>,
+        obj: RluLocked<'_, T>,
+    ) -> Result<(), RluError> {
+        self.handle.free(member, obj)
+    }
+
+    // Err(Conflict) if a section nested in this one aborted, and nothing was committed
+    pub fn commit(mut self) -> Result<(), RluError> {
+        self.finished = true;
+        self.handle.exit()
+    }
+
+    pub fn abort(mut self) {
+        self.finished = true;
+        self.handle.abort().unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+impl<'a> Drop for RluGroupWriteGuard<'a> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if thread::panicking() {
+            // the sections are known to be open, and panicking again would abort the process
+            let _ = self.handle.abort();
+        } else {
+            self.handle.exit().unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+// One domain of a group reader section, see RluGroupReadGuard::section
+pub struct RluGroupSection<'s, T: RluObj> {
+    handle: &'s RluGroupHandle,
+    member: RluGroupMember<T>,
+}
+
+impl<'s, T> RluSection<T> for RluGroupSection<'s, T>
+where
+    T: RluObj,
+{
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(self.member, p_obj)
+    }
+}
+
+// One domain of a group writer section, see RluGroupWriteGuard::txn
+pub struct RluGroupTxn<'s, T: RluObj> {
+    handle: &'s RluGroupHandle,
+    member: RluGroupMember<T>,
+}
+
+impl<'s, T> RluSection<T> for RluGroupTxn<'s, T>
+where
+    T: RluObj,
+{
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(self.member, p_obj)
+    }
+}
+
+impl<'s, T> RluWriter<T> for RluGroupTxn<'s, T>
+where
+    T: RluObj,
+{
+    fn lock(&self, obj: &T) -> Result<RluLocked<'_, T>, RluError> {
+        self.handle.try_lock(self.member, obj)?.ok_or(RluError::Conflict)
+    }
+
+    fn lock_all(&self, objs: &[&T]) -> Result<Vec<RluLocked<'_, T>>, RluError> {
+        self.handle.try_lock_all(self.member, objs)?.map_err(|_| RluError::Conflict)
+    }
+
+    fn assign(&self, p_ptr: &mut *mut T, obj: Option<&T>) {
+        let p_obj = obj.map_or(ptr::null_mut(), |o| o as *const T as *mut T);
+        unsafe { rlu_assign_ptr(p_ptr, p_obj) };
+    }
+
+    fn free(&self, obj: RluLocked<'_, T>) -> Result<(), RluError> {
+        self.handle.free(self.member, obj)
+    }
+}
@@ -0,0 +1,528 @@
+// Safe section guards on top of the raw RLU functions. A guard opens a section when it is
+// created and closes it when it goes out of scope, so early returns and panics can no longer
+// leave a thread's run_counter odd or its objects locked.
+
+use crate::rlu::{
+    rlu_abort, rlu_assign_ptr, rlu_defer, rlu_dereference, rlu_destroy, rlu_flush, rlu_free,
+    rlu_in_section, rlu_inner_aborted, rlu_is_nested, rlu_poison, rlu_reader_lock,
+    rlu_reader_unlock, rlu_reclaim, rlu_synchronize, rlu_thread_exit, rlu_thread_init, rlu_try_lock,
+    rlu_try_lock_all, rlu_try_synchronize, rlu_unwind_sections, rlu_writer_lock, GlobalRlu,
+    GlobalRluBuilder, RluLockAll, RluObj,
+};
+use crate::rlu_debug::RluWriteCheck;
+use crate::rlu_error::RluError;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+// Implemented by both section guards, for code that only needs to traverse
+pub trait RluSection<T: RluObj> {
+    fn deref(&self, p_obj: *mut T) -> Option<&T>;
+}
+
+// A domain shared by the handles of RluHandle::new_domain and by RluShared. The last one to
+// go destroys it.
+#[derive(Debug)]
+pub(crate) struct RluDomain<T: RluObj> {
+    pub(crate) rlu: *mut GlobalRlu<T>,
+    // Slots of the thread-local handles of RluShared, None once they were given back
+    pub(crate) local_ids: Mutex<Option<Vec<usize>>>,
+}
+
+unsafe impl<T: RluObj> Send for RluDomain<T> {}
+unsafe impl<T: RluObj> Sync for RluDomain<T> {}
+
+impl<T> RluDomain<T>
+where
+    T: RluObj,
+{
+    pub(crate) fn new(rlu: *mut GlobalRlu<T>) -> RluDomain<T> {
+        RluDomain {
+            rlu,
+            local_ids: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    // Gives back the slots of threads that still have a thread-local handle, once nothing
+    // can reach those handles anymore
+    pub(crate) fn close_local_handles(&self) {
+        let ids = self.local_ids.lock().unwrap_or_else(PoisonError::into_inner).take();
+        for id in ids.into_iter().flatten() {
+            unsafe { rlu_thread_exit(self.rlu, id) }.unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+impl<T> Drop for RluDomain<T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        self.close_local_handles();
+        unsafe { rlu_destroy(self.rlu) }.unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+// The copies one thread currently hands out as RluLocked, so that each of them is reachable
+// through one RluLocked at a time. Locking a copy that is in here again fails, and so does
+// dereferencing it.
+#[derive(Debug, Default)]
+pub(crate) struct RluHeld {
+    copies: RefCell<Vec<usize>>,
+}
+
+impl RluHeld {
+    fn hold(&self, p_obj: *const ()) -> Result<(), RluError> {
+        let mut copies = self.copies.borrow_mut();
+        if copies.contains(&(p_obj as usize)) {
+            return Err(RluError::AlreadyLocked);
+        }
+        copies.push(p_obj as usize);
+        Ok(())
+    }
+
+    fn release(&self, p_obj: *const ()) {
+        let mut copies = self.copies.borrow_mut();
+        if let Some(i) = copies.iter().position(|&copy| copy == p_obj as usize) {
+            copies.swap_remove(i);
+        }
+    }
+
+    // deref() must not hand out a shared reference to a copy an RluLocked can write to
+    pub(crate) fn check_deref(&self, p_obj: *const ()) {
+        if self.copies.borrow().contains(&(p_obj as usize)) {
+            panic!("deref of an RLU object that is locked, use its RluLocked instead");
+        }
+    }
+
+    // Only an RluLocked that was leaked can still be in here once its section is over
+    pub(crate) fn clear(&self) {
+        self.copies.borrow_mut().clear();
+    }
+
+    fn free(&self) {
+        self.copies.take();
+    }
+}
+
+// One registered RLU thread. The handle owns its thread id, so it can be moved to another
+// OS thread but not shared between two of them. Dropping it gives the slot back.
+#[derive(Debug)]
+pub struct RluHandle<T: RluObj> {
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+    domain: Option<Arc<RluDomain<T>>>, //None if the caller owns the domain
+    held: RluHeld,
+}
+
+unsafe impl<T: RluObj> Send for RluHandle<T> {}
+
+impl<T> RluHandle<T>
+where
+    T: RluObj,
+{
+    // Registers on a domain the caller keeps alive, and frees with rlu_destroy if it wants to.
+    // Panics if every thread slot of the domain is taken.
+    /// # Safety
+    /// rlu must point to a live domain that outlives the handle and every handle cloned from
+    /// it with clone_ref.
+    pub unsafe fn new(rlu: *mut GlobalRlu<T>) -> RluHandle<T> {
+        RluHandle::try_new(rlu).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// # Safety
+    /// As for new.
+    pub unsafe fn try_new(rlu: *mut GlobalRlu<T>) -> Result<RluHandle<T>, RluError> {
+        let id = rlu_thread_init(rlu)?;
+        Ok(RluHandle {
+            rlu,
+            id,
+            domain: None,
+            held: RluHeld::default(),
+        })
+    }
+
+    // Builds a domain that this handle and its clone_refs own together. It is destroyed when
+    // the last of them drops.
+    pub fn new_domain(builder: GlobalRluBuilder<T>) -> RluHandle<T> {
+        let rlu = builder.init_rlu();
+        // the domain lives as long as the handles that share it, and a new domain has at
+        // least one free slot
+        let mut handle = unsafe { RluHandle::new(rlu) };
+        handle.domain = Some(Arc::new(RluDomain::new(rlu)));
+        handle
+    }
+
+    pub fn rlu_ptr(&self) -> *mut GlobalRlu<T> {
+        self.rlu
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    // Registers a new thread on the same domain, sharing ownership of it if this handle has any
+    pub fn clone_ref(&self) -> RluHandle<T> {
+        self.try_clone_ref().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_clone_ref(&self) -> Result<RluHandle<T>, RluError> {
+        let mut handle = unsafe { RluHandle::try_new(self.rlu) }?;
+        handle.domain = self.domain.clone();
+        Ok(handle)
+    }
+
+    // A header that fails validation means memory corruption, there is nothing to recover
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        match unsafe { rlu_dereference(self.rlu, self.id, p_obj) } {
+            Ok(p_obj) => {
+                self.held.check_deref(p_obj as *const ());
+                unsafe { p_obj.as_ref() }
+            }
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    // For a handle that is never dropped, see LocalHandle
+    pub(crate) fn free_held(&self) {
+        self.held.free();
+    }
+
+    fn begin_section(&self) {
+        if !rlu_in_section(self.rlu, self.id) {
+            self.held.clear();
+        }
+    }
+
+    // Sections borrow the handle, so they always end. One opened while another is open is
+    // part of it, and only the outermost one commits, see rlu_reader_lock.
+    pub fn read(&self) -> RluReadGuard<'_, T> {
+        self.begin_section();
+        unsafe { rlu_reader_lock(self.rlu, self.id) }.unwrap_or_else(|err| panic!("{}", err));
+        RluReadGuard { handle: self }
+    }
+
+    // In coarse mode this waits for the domain's writer lock first, so that the section's
+    // try_locks cannot fail, see rlu_writer_lock
+    pub fn write(&self) -> RluWriteGuard<'_, T> {
+        self.begin_section();
+        unsafe { rlu_writer_lock(self.rlu, self.id) }.unwrap_or_else(|err| panic!("{}", err));
+        RluWriteGuard {
+            handle: self,
+            finished: false,
+        }
+    }
+
+    // Runs f after a grace period, see rlu_defer. Called while no section is open, f waits
+    // for this handle's next commit or synchronize(), or for the handle to drop.
+    pub fn defer<F>(&self, f: F) -> Result<(), RluError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        unsafe { rlu_defer(self.rlu, self.id, f) }
+    }
+
+    // Waits for every section open on the domain to end, then runs this handle's deferred
+    // callbacks. Panics if one of this handle's own sections is open.
+    pub fn synchronize(&self) {
+        unsafe { rlu_synchronize(self.rlu, self.id) }.unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    // synchronize() that gives up after timeout with Err(SyncTimeout), carrying the report on
+    // the readers it waited on. Deferred sections stay pending, see rlu_try_synchronize.
+    pub fn try_synchronize(&self, timeout: Duration) -> Result<(), RluError> {
+        unsafe { rlu_try_synchronize(self.rlu, self.id, timeout) }
+    }
+
+    // Reclaims another thread's poisoned slot, see rlu_reclaim. Err(UnbalancedSection) if one
+    // of this handle's sections is open, since the reclaim would wait for it.
+    pub fn reclaim(&self, id: usize) -> Result<(), RluError> {
+        if rlu_in_section(self.rlu, self.id) {
+            return Err(RluError::UnbalancedSection);
+        }
+        unsafe { rlu_reclaim(self.rlu, id) }
+    }
+
+    // Commits the sections this handle deferred, see GlobalRluBuilder::defer_commits. Panics
+    // if one of this handle's own sections is open.
+    pub fn flush(&self) {
+        unsafe { rlu_flush(self.rlu, self.id) }.unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+impl<T> Drop for RluHandle<T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // a section opened with the raw functions on this handle's id may still be open,
+            // and panicking again would abort the process
+            rlu_unwind_sections(self.rlu, self.id);
+            let _ = unsafe { rlu_thread_exit(self.rlu, self.id) };
+            return;
+        }
+        unsafe { rlu_thread_exit(self.rlu, self.id) }.unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+// For code on the raw functions, which has no guard to abort its section on a panic. Created
+// once the thread is registered, it aborts whatever section the thread has open if it unwinds
+// past the guard, and poisons the slot, since nothing will call rlu_thread_exit for it.
+pub struct RluUnwindGuard<T: RluObj> {
+    rlu: *mut GlobalRlu<T>,
+    id: usize,
+}
+
+impl<T> RluUnwindGuard<T>
+where
+    T: RluObj,
+{
+    /// # Safety
+    /// rlu must point to a live domain that outlives the guard, and id must be the slot the
+    /// calling thread got from rlu_thread_init.
+    pub unsafe fn new(rlu: *mut GlobalRlu<T>, id: usize) -> RluUnwindGuard<T> {
+        RluUnwindGuard { rlu, id }
+    }
+}
+
+impl<T> Drop for RluUnwindGuard<T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        if thread::panicking() {
+            rlu_unwind_sections(self.rlu, self.id);
+            let _ = unsafe { rlu_poison(self.rlu, self.id) };
+        }
+    }
+}
+
+// A reader section. Objects handed out by deref() borrow the guard, so they cannot be used
+// after the section ends.
+pub struct RluReadGuard<'a, T: RluObj> {
+    handle: &'a RluHandle<T>,
+}
+
+impl<'a, T> RluReadGuard<'a, T>
+where
+    T: RluObj,
+{
+    // p_obj must be null or a pointer read from an RLU protected object (or a root)
+    pub fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(p_obj)
+    }
+
+    pub fn unlock(self) {}
+}
+
+impl<'a, T> RluSection<T> for RluReadGuard<'a, T>
+where
+    T: RluObj,
+{
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        RluReadGuard::deref(self, p_obj)
+    }
+}
+
+impl<'a, T> Drop for RluReadGuard<'a, T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        let result = unsafe { rlu_reader_unlock(self.handle.rlu, self.handle.id) };
+        if !thread::panicking() {
+            result.unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+// A writer section. It commits when dropped, and aborts instead if abort() is called or the
+// thread is unwinding from a panic. Dropping it panics if a section nested in it aborted,
+// commit() returns that as Err(Conflict).
+pub struct RluWriteGuard<'a, T: RluObj> {
+    handle: &'a RluHandle<T>,
+    finished: bool,
+}
+
+impl<'a, T> RluWriteGuard<'a, T>
+where
+    T: RluObj,
+{
+    // p_obj must be null or a pointer read from an RLU protected object (or a root)
+    pub fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        self.handle.deref(p_obj)
+    }
+
+    // Locks an object returned by deref(), and returns the write log copy to modify. None
+    // means another writer holds the object, and the section should be aborted and retried.
+    // The copy is reachable through one RluLocked at a time: locking the object again while
+    // it is alive fails with AlreadyLocked, and deref() of the object panics. Once it is
+    // dropped, deref() returns the copy and locking it again hands it out again.
+    pub fn try_lock(&self, obj: &T) -> Result<Option<RluLocked<'_, T>>, RluError> {
+        let mut p_obj = obj as *const T as *mut T;
+        if unsafe { rlu_try_lock(self.handle.rlu, self.handle.id, &mut p_obj) }? {
+            RluLocked::new(self.handle.rlu, self.handle.id, p_obj, &self.handle.held).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Locks all of objs or none of them, see rlu_try_lock_all. The copies come back in the
+    // order of objs. Err(index) means objs[index] is held by another writer, and the section
+    // should be aborted and retried. An object given twice fails with AlreadyLocked, see
+    // rlu_try_lock_all. So does an object with a live RluLocked, and what was locked then
+    // stays locked until the section aborts.
+    pub fn try_lock_all(
+        &self,
+        objs: &[&T],
+    ) -> Result<Result<Vec<RluLocked<'_, T>>, usize>, RluError> {
+        let mut p_objs: Vec<*mut T> = objs.iter().map(|&obj| obj as *const T as *mut T).collect();
+        let mut p_p_objs: Vec<&mut *mut T> = p_objs.iter_mut().collect();
+        let (rlu, id, held) = (self.handle.rlu, self.handle.id, &self.handle.held);
+        match unsafe { rlu_try_lock_all(rlu, id, &mut p_p_objs) }? {
+            RluLockAll::Locked => p_objs
+                .into_iter()
+                .map(|p_obj| RluLocked::new(rlu, id, p_obj, held))
+                .collect::<Result<Vec<_>, RluError>>()
+                .map(Ok),
+            RluLockAll::Conflict { index } => Ok(Err(index)),
+        }
+    }
+
+    // Stores the original of obj (never a copy) in a pointer field
+    pub fn assign(&self, p_ptr: &mut *mut T, obj: Option<&T>) {
+        let p_obj = obj.map_or(ptr::null_mut(), |o| o as *const T as *mut T);
+        unsafe { rlu_assign_ptr(p_ptr, p_obj) };
+    }
+
+    // Frees the original of a locked object once no reader can see it anymore
+    pub fn free(&self, obj: RluLocked<'_, T>) -> Result<(), RluError> {
+        unsafe { rlu_free(self.handle.rlu, self.handle.id, obj.p_obj) }
+    }
+
+    // Runs f once no reader can see what this section unlinked, or drops it if the section
+    // aborts
+    pub fn defer<F>(&self, f: F) -> Result<(), RluError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.handle.defer(f)
+    }
+
+    // Err(Conflict) if a section nested in this one aborted, and nothing was committed
+    pub fn commit(mut self) -> Result<(), RluError> {
+        self.finished = true;
+        unsafe { rlu_reader_unlock(self.handle.rlu, self.handle.id) }
+    }
+
+    pub(crate) fn is_nested(&self) -> bool {
+        rlu_is_nested(self.handle.rlu, self.handle.id)
+    }
+
+    pub(crate) fn inner_aborted(&self) -> bool {
+        rlu_inner_aborted(self.handle.rlu, self.handle.id)
+    }
+
+    pub fn abort(mut self) {
+        self.finished = true;
+        unsafe { rlu_abort(self.handle.rlu, self.handle.id) }
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+impl<'a, T> RluSection<T> for RluWriteGuard<'a, T>
+where
+    T: RluObj,
+{
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        RluWriteGuard::deref(self, p_obj)
+    }
+}
+
+impl<'a, T> Drop for RluWriteGuard<'a, T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if thread::panicking() {
+            // the section is known to be open, and panicking again would abort the process
+            let _ = unsafe { rlu_abort(self.handle.rlu, self.handle.id) };
+        } else {
+            unsafe { rlu_reader_unlock(self.handle.rlu, self.handle.id) }
+                .unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+}
+
+// The write log copy of a locked object, valid until its section ends. It is the only way to
+// reach the copy while it is alive, see RluWriteGuard::try_lock.
+pub struct RluLocked<'g, T: RluObj> {
+    p_obj: *mut T,
+    check: RluWriteCheck<T>,
+    held: &'g RluHeld,
+    _section: PhantomData<&'g mut T>,
+}
+
+impl<'g, T> RluLocked<'g, T>
+where
+    T: RluObj,
+{
+    // p_obj must be a copy locked by the section of thread id that 'g borrows, and held the
+    // copies that thread hands out. Err(AlreadyLocked) if one of them is p_obj.
+    pub(crate) fn new(
+        rlu: *mut GlobalRlu<T>,
+        id: usize,
+        p_obj: *mut T,
+        held: &'g RluHeld,
+    ) -> Result<RluLocked<'g, T>, RluError> {
+        held.hold(p_obj as *const ())?;
+        Ok(RluLocked {
+            p_obj,
+            check: RluWriteCheck::new(rlu, id),
+            held,
+            _section: PhantomData,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.p_obj
+    }
+}
+
+impl<'g, T> Deref for RluLocked<'g, T>
+where
+    T: RluObj,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.p_obj }
+    }
+}
+
+impl<'g, T> DerefMut for RluLocked<'g, T>
+where
+    T: RluObj,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.check.check(self.p_obj);
+        unsafe { &mut *self.p_obj }
+    }
+}
+
+impl<'g, T> Drop for RluLocked<'g, T>
+where
+    T: RluObj,
+{
+    fn drop(&mut self) {
+        self.held.release(self.p_obj as *const ());
+    }
+}
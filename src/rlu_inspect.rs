@@ -0,0 +1,171 @@
+// Plain-data snapshots of a domain for debugging tools, taken by GlobalRlu::inspect. They
+// print as a table with Display, and as JSON with to_json for tools that parse them.
+
+use crate::rlu::RluMode;
+use std::env;
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Set to text or json, makes dump_requested print snapshots
+pub const RLU_INSPECT_ENV: &str = "RLU_INSPECT";
+// Set to a number of milliseconds, makes RluInspectDumper print a snapshot that often
+pub const RLU_INSPECT_INTERVAL_ENV: &str = "RLU_INSPECT_INTERVAL";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RluThreadSnapshot {
+    pub id: usize,
+    pub run_counter: u64, //odd while in a section
+    pub local_clock: u64,
+    pub write_clock: Option<u64>, //None unless committing
+    pub is_writer: bool,
+    pub cur_pos: usize,           //write log entries in use
+    pub num_of_objs: usize,       //copies locked by the current section
+    pub pending_frees: usize,     //objects waiting for a grace period
+    pub pending_callbacks: usize, //rlu_defer callbacks waiting for one
+    pub poisoned: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RluSnapshot {
+    pub global_clock: u64,
+    pub mode: RluMode,
+    pub max_threads: usize,
+    pub threads: Vec<RluThreadSnapshot>, //registered threads only, by id
+}
+
+impl RluSnapshot {
+    pub fn to_json(&self) -> String {
+        let threads: Vec<String> = self
+            .threads
+            .iter()
+            .map(|thread| {
+                format!(
+                    "{{\"id\":{},\"run_counter\":{},\"local_clock\":{},\"write_clock\":{},\
+                     \"is_writer\":{},\"cur_pos\":{},\"num_of_objs\":{},\"pending_frees\":{},\
+                     \"pending_callbacks\":{},\"poisoned\":{}}}",
+                    thread.id,
+                    thread.run_counter,
+                    thread.local_clock,
+                    thread
+                        .write_clock
+                        .map_or_else(|| "null".to_string(), |clock| clock.to_string()),
+                    thread.is_writer,
+                    thread.cur_pos,
+                    thread.num_of_objs,
+                    thread.pending_frees,
+                    thread.pending_callbacks,
+                    thread.poisoned
+                )
+            })
+            .collect();
+        format!(
+            "{{\"global_clock\":{},\"mode\":\"{:?}\",\"max_threads\":{},\"threads\":[{}]}}",
+            self.global_clock,
+            self.mode,
+            self.max_threads,
+            threads.join(",")
+        )
+    }
+
+    // For benchmarks, once their threads are done: prints the snapshot to stderr, labeled,
+    // in the format RLU_INSPECT asks for. Does nothing if it is not set.
+    pub fn dump_requested(&self, label: &str) {
+        if env::var(RLU_INSPECT_ENV).is_ok() {
+            self.dump(label);
+        }
+    }
+
+    fn dump(&self, label: &str) {
+        if env::var(RLU_INSPECT_ENV).as_deref() == Ok("json") {
+            eprintln!("{{\"label\":\"{}\",\"rlu\":{}}}", label, self.to_json());
+        } else {
+            eprintln!("{}\n{}", label, self);
+        }
+    }
+}
+
+// For benchmarks, while their threads run: a side thread takes a snapshot every
+// RLU_INSPECT_INTERVAL milliseconds and prints it like dump_requested (text unless RLU_INSPECT
+// is json), so a stalled run can be looked at while it is stuck. Stops when dropped. Does
+// nothing if RLU_INSPECT_INTERVAL is not set.
+pub struct RluInspectDumper {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RluInspectDumper {
+    pub fn new<F>(label: String, take: F) -> Self
+    where
+        F: Fn() -> RluSnapshot + Send + 'static,
+    {
+        let interval = match env::var(RLU_INSPECT_INTERVAL_ENV).map(|ms| ms.parse::<u64>()) {
+            Ok(Ok(ms)) => Duration::from_millis(ms.max(1)),
+            _ => return RluInspectDumper { stop: None, thread: None },
+        };
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            //the sender is never used, dropping it is the stop signal
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                take().dump(&label);
+            }
+        });
+        RluInspectDumper { stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+impl Drop for RluInspectDumper {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl fmt::Display for RluSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "RLU domain ({:?}): global clock {}, {} of {} threads registered",
+            self.mode,
+            self.global_clock,
+            self.threads.len(),
+            self.max_threads
+        )?;
+        write!(
+            f,
+            "{:>4} {:>12} {:>12} {:>12} {:>6} {:>8} {:>8} {:>6} {:>9} {:>8}",
+            "id",
+            "run_counter",
+            "local_clock",
+            "write_clock",
+            "writer",
+            "cur_pos",
+            "objs",
+            "frees",
+            "callbacks",
+            "poisoned"
+        )?;
+        for thread in &self.threads {
+            write!(
+                f,
+                "\n{:>4} {:>12} {:>12} {:>12} {:>6} {:>8} {:>8} {:>6} {:>9} {:>8}",
+                thread.id,
+                thread.run_counter,
+                thread.local_clock,
+                thread
+                    .write_clock
+                    .map_or_else(|| "-".to_string(), |clock| clock.to_string()),
+                thread.is_writer,
+                thread.cur_pos,
+                thread.num_of_objs,
+                thread.pending_frees,
+                thread.pending_callbacks,
+                thread.poisoned
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+// Domains that are shared between threads by reference instead of through one handle per
+// thread. Each OS thread that uses an RluShared registers on its domain the first time, and
+// keeps that RLU thread in a thread-local registry until it exits, which gives the slot back.
+// Structures built on it (RluSet, BPlusTree, RluCell) can be shared as Arc or &, since a
+// thread can no longer end up using another thread's RluThread.
+
+use crate::rlu::{rlu_in_section, GlobalRlu, GlobalRluBuilder, RluObj};
+use crate::rlu_error::RluError;
+use crate::rlu_guard::{RluDomain, RluHandle};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::{Arc, PoisonError, Weak};
+use std::thread;
+
+thread_local! {
+    // This thread's handles, by the address of their domain. An entry holds a Weak on its
+    // domain, which keeps the address from being reused while the entry is around.
+    static LOCAL_HANDLES: RefCell<HashMap<usize, Rc<dyn LocalSlot>>> =
+        RefCell::new(HashMap::new());
+}
+
+trait LocalSlot {
+    fn is_closed(&self) -> bool;
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any>;
+}
+
+// A thread's RLU thread on a shared domain. Exiting it needs the domain's local_ids lock, so
+// a domain being closed waits for a thread that is exiting at the same time.
+struct LocalHandle<T: RluObj + 'static> {
+    handle: ManuallyDrop<RluHandle<T>>,
+    domain: Weak<RluDomain<T>>,
+}
+
+impl<T> LocalHandle<T>
+where
+    T: RluObj + 'static,
+{
+    fn register(domain: &Arc<RluDomain<T>>) -> Result<LocalHandle<T>, RluError> {
+        let mut local_ids = domain
+            .local_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // only closed once the last RluShared is gone, and registering needs one
+        let ids = local_ids.as_mut().unwrap_or_else(|| unreachable!());
+        let handle = unsafe { RluHandle::try_new(domain.rlu) }?; //the domain outlives it
+        ids.push(handle.id());
+        Ok(LocalHandle {
+            handle: ManuallyDrop::new(handle),
+            domain: Arc::downgrade(domain),
+        })
+    }
+}
+
+impl<T> LocalSlot for LocalHandle<T>
+where
+    T: RluObj + 'static,
+{
+    fn is_closed(&self) -> bool {
+        self.domain.upgrade().is_none_or(|domain| {
+            domain
+                .local_ids
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_none()
+        })
+    }
+
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any> {
+        self
+    }
+}
+
+impl<T> Drop for LocalHandle<T>
+where
+    T: RluObj + 'static,
+{
+    fn drop(&mut self) {
+        self.handle.free_held(); //the handle itself is only dropped if it still has its slot
+        let domain = match self.domain.upgrade() {
+            Some(domain) => domain,
+            None => return, //the domain gave the slot back when it was destroyed
+        };
+        let mut local_ids = domain
+            .local_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let id = self.handle.id();
+        if let Some(ids) = local_ids.as_mut() {
+            if let Some(pos) = ids.iter().position(|&local_id| local_id == id) {
+                ids.swap_remove(pos);
+                unsafe { ManuallyDrop::drop(&mut self.handle) };
+            }
+        }
+    }
+}
+
+// An RLU domain that any number of threads use through the same reference. Clones share the
+// domain, and once the last one is gone the slots of threads still holding a thread-local
+// handle are given back, so whatever the domain protects can be freed right after it.
+#[derive(Debug)]
+pub struct RluShared<T: RluObj + 'static> {
+    inner: Arc<RluSharedDomain<T>>,
+}
+
+#[derive(Debug)]
+struct RluSharedDomain<T: RluObj + 'static> {
+    domain: Arc<RluDomain<T>>,
+}
+
+impl<T> Drop for RluSharedDomain<T>
+where
+    T: RluObj + 'static,
+{
+    fn drop(&mut self) {
+        self.domain.close_local_handles();
+    }
+}
+
+impl<T> RluShared<T>
+where
+    T: RluObj + 'static,
+{
+    pub fn new(builder: GlobalRluBuilder<T>) -> RluShared<T> {
+        RluShared {
+            inner: Arc::new(RluSharedDomain {
+                domain: Arc::new(RluDomain::new(builder.init_rlu())),
+            }),
+        }
+    }
+
+    pub fn rlu_ptr(&self) -> *mut GlobalRlu<T> {
+        self.inner.domain.rlu
+    }
+
+    pub(crate) fn domain(&self) -> &Arc<RluDomain<T>> {
+        &self.inner.domain
+    }
+
+    // This thread's handle on the domain, registered the first time. Panics if every thread
+    // slot of the domain is taken.
+    pub fn handle(&self) -> RluLocalHandle<'_, T> {
+        self.try_handle().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_handle(&self) -> Result<RluLocalHandle<'_, T>, RluError> {
+        let domain = &self.inner.domain;
+        let key = Arc::as_ptr(domain) as usize;
+        let found = LOCAL_HANDLES
+            .try_with(|handles| handles.borrow().get(&key).cloned())
+            .ok()
+            .flatten();
+        let local = match found {
+            Some(slot) => slot.into_any().downcast::<LocalHandle<T>>().unwrap(),
+            None => {
+                let local = Rc::new(LocalHandle::register(domain)?);
+                // fails while the thread's registry is being torn down, and the handle then
+                // only lives as long as the caller uses it
+                let _ = LOCAL_HANDLES.try_with(|handles| {
+                    let mut handles = handles.borrow_mut();
+                    handles.retain(|_, slot| !slot.is_closed());
+                    handles.insert(key, local.clone());
+                });
+                local
+            }
+        };
+        Ok(RluLocalHandle {
+            local,
+            shared: PhantomData,
+        })
+    }
+}
+
+impl<T> Clone for RluShared<T>
+where
+    T: RluObj + 'static,
+{
+    fn clone(&self) -> RluShared<T> {
+        RluShared {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// The calling thread's handle on an RluShared domain. It cannot leave the thread, and
+// sections opened on it while another one is open nest, see rlu_reader_lock.
+pub struct RluLocalHandle<'a, T: RluObj + 'static> {
+    local: Rc<LocalHandle<T>>,
+    shared: PhantomData<&'a RluShared<T>>,
+}
+
+impl<'a, T> Deref for RluLocalHandle<'a, T>
+where
+    T: RluObj + 'static,
+{
+    type Target = RluHandle<T>;
+
+    fn deref(&self) -> &RluHandle<T> {
+        &self.local.handle
+    }
+}
+
+// Nothing tells when the thread is back, and until then the locks of the sections it deferred
+// would hold up every other writer, so they are committed once its outermost use of the
+// domain ends
+impl<'a, T> Drop for RluLocalHandle<'a, T>
+where
+    T: RluObj + 'static,
+{
+    fn drop(&mut self) {
+        let handle = &self.local.handle;
+        if !thread::panicking() && !rlu_in_section(handle.rlu_ptr(), handle.id()) {
+            handle.flush();
+        }
+    }
+}
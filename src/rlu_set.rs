@@ -1,15 +1,20 @@
 // Author: Hudson Ayers
 
 use crate::concurrent_set::ConcurrentSet;
-use crate::rlu::{
-    rlu_abort, rlu_assign_ptr, rlu_dereference, rlu_free, rlu_reader_lock, rlu_reader_unlock,
-    rlu_thread_init, rlu_try_lock, GlobalRlu, RluObj, RluObjHdr, WsHdr, PTR_ID_OBJ_COPY,
-};
-use std::fmt::Debug;
+use crate::rlu::{rlu_latest, GlobalRlu, GlobalRluBuilder, RluMode, RluObjHdr};
+use rlu_derive::RluObj;
+use crate::rlu_error::RluError;
+use crate::rlu_group::{RluGroup, RluGroupMember, RluGroupReadGuard, RluGroupWriteGuard};
+use crate::rlu_guard::RluSection;
+use crate::rlu_local::RluShared;
+use crate::rlu_txn::RluWriter;
+use std::fmt::{self, Debug};
 use std::mem;
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
 
+#[derive(RluObj)]
+#[rlu(crate = "crate")]
 pub struct Node<T: 'static + Clone> {
     hdr: RluObjHdr<Node<T>>,
     next: NodePtr<T>,
@@ -17,142 +22,221 @@ pub struct Node<T: 'static + Clone> {
 }
 type NodePtr<T> = *mut Node<T>;
 
-impl<T: 'static> RluObj for Node<T>
+// Shared between threads as is, each thread gets its own RLU thread on first use. The domain
+// is declared first so the last clone gives back every thread's slot before freeing the nodes.
+pub struct RluSet<T: 'static + Clone> {
+    rlu: RluShared<Node<T>>,
+    nodes: Arc<RluSetNodes<T>>,
+    member: Option<RluGroupMember<Node<T>>>, //the set's domain, if it is part of a group
+}
+
+// The list, shared by every clone_ref of a set and freed with the last one
+struct RluSetNodes<T: 'static + Clone> {
+    head: NodePtr<T>,
+}
+
+impl<T> Drop for RluSetNodes<T>
 where
     T: Clone,
 {
-    fn copy_back_to_original(&self) {
-        assert!(self.has_ws_hdr());
-        self.hdr.ws_hdr.as_ref().map(|hdr| unsafe {
-            (*hdr.p_obj_actual).next = self.next;
-            (*hdr.p_obj_actual).data = self.data.clone();
-            (*hdr.p_obj_actual)
-                .hdr
-                .p_obj_copy
-                .store(ptr::null_mut(), Ordering::SeqCst);
-        });
+    fn drop(&mut self) {
+        let mut node_ptr = self.head;
+        while !node_ptr.is_null() {
+            unsafe {
+                let next = (*rlu_latest(node_ptr)).next;
+                drop(Box::from_raw(node_ptr));
+                node_ptr = next;
+            }
+        }
     }
-    fn unlock(&self) {
-        assert!(!self.has_ws_hdr());
-        assert!(self.is_locked());
-        self.hdr.p_obj_copy.store(ptr::null_mut(), Ordering::SeqCst);
+}
+
+unsafe impl<T: Clone> Send for RluSet<T> {}
+unsafe impl<T: Clone> Sync for RluSet<T> {}
+
+pub fn rlu_new_node<T: Clone>(value: T) -> &'static mut Node<T> {
+    Box::leak(Box::new(Node {
+        hdr: RluObjHdr::new(),
+        next: ptr::null_mut(),
+        data: value,
+    }))
+}
+
+impl<T> RluSet<T>
+where
+    T: PartialEq + PartialOrd + Copy + Clone + Debug,
+{
+    pub fn new() -> RluSet<T> {
+        RluSet::with_mode(RluMode::SingleVersion)
     }
-    fn unlock_original(&self) {
-        unsafe {
-            (*self.get_p_original()).unlock();
-        }
+
+    pub fn with_mode(mode: RluMode) -> RluSet<T> {
+        RluSet::with_builder(GlobalRlu::builder().mode(mode))
     }
-    fn get_p_obj_copy(&self) -> *mut Self {
-        self.hdr.p_obj_copy.load(Ordering::SeqCst)
+
+    pub fn with_builder(builder: GlobalRluBuilder<Node<T>>) -> RluSet<T> {
+        RluSet::with_shared(RluShared::new(builder), None)
     }
-    fn is_locked(&self) -> bool {
-        !self.get_p_obj_copy().is_null()
+
+    // A set whose domain is part of group, so that a group section can read and change it
+    // together with the group's other domains, see contains_in and try_insert_in. The set's
+    // own operations still work on their own, but not inside a section of the group.
+    pub fn in_group(group: &mut RluGroup, builder: GlobalRluBuilder<Node<T>>) -> RluSet<T> {
+        let (rlu, member) = group.add_shared(builder);
+        RluSet::with_shared(rlu, Some(member))
     }
-    fn is_copy(&self) -> bool {
-        unsafe { self.get_p_obj_copy() == mem::transmute(PTR_ID_OBJ_COPY) }
+
+    fn with_shared(rlu: RluShared<Node<T>>, member: Option<RluGroupMember<Node<T>>>) -> RluSet<T> {
+        RluSet {
+            rlu,
+            nodes: Arc::new(RluSetNodes {
+                head: Box::into_raw(Box::new(Node {
+                    hdr: RluObjHdr::new(),
+                    next: ptr::null_mut(),
+                    data: unsafe { mem::MaybeUninit::zeroed().assume_init() }, // Okay bc this value will never be accessed
+                })),
+            }),
+            member,
+        }
     }
-    fn has_ws_hdr(&self) -> bool {
-        self.hdr.ws_hdr.is_some()
+
+    fn member(&self) -> RluGroupMember<Node<T>> {
+        self.member.expect("RluSet is not part of a group, see RluSet::in_group")
     }
-    fn get_p_original(&self) -> *mut Self {
-        assert!(self.has_ws_hdr());
-        self.hdr
-            .ws_hdr
-            .as_ref()
-            .map(|hdr| hdr.p_obj_actual)
-            .unwrap()
+
+    // Runs f in one section, so that the set operations it makes see one snapshot and are
+    // committed together. Inside f, try_insert and try_delete return Err(Conflict) when they
+    // find a node held by another writer, and f passing that on re-runs it. Any other error
+    // aborts the batch and is returned.
+    pub fn batch<R, F>(&self, mut f: F) -> Result<R, RluError>
+    where
+        F: FnMut(&Self) -> Result<R, RluError>,
+    {
+        self.rlu.handle().transaction(|_txn| f(self))
     }
-    fn get_locking_thread_from_ws_obj(&self) -> usize {
-        assert!(self.has_ws_hdr());
-        self.hdr.ws_hdr.as_ref().map(|hdr| hdr.thread_id).unwrap()
+
+    // insert that returns the error instead of panicking. Inside a batch that includes
+    // Err(Conflict), when another writer holds one of the nodes.
+    pub fn try_insert(&self, value: T) -> Result<bool, RluError> {
+        self.rlu.handle().transaction(|txn| self.insert_txn(txn, value))
     }
-    fn get_ws_run_counter(&self) -> u64 {
-        assert!(self.has_ws_hdr());
-        self.hdr.ws_hdr.as_ref().map(|hdr| hdr.run_counter).unwrap()
+
+    // delete that returns the error instead of panicking, see try_insert
+    pub fn try_delete(&self, value: T) -> Result<bool, RluError> {
+        self.rlu.handle().transaction(|txn| self.delete_txn(txn, value))
     }
-    fn get_copy_with_ws_hdr(&self, run_counter: u64, thread_id: usize) -> Self {
-        Node {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(unsafe { mem::transmute(PTR_ID_OBJ_COPY) }),
-                ws_hdr: Some(WsHdr {
-                    p_obj_actual: unsafe { mem::transmute(self) },
-                    run_counter: run_counter,
-                    thread_id: thread_id,
-                }),
-            },
-            next: self.next,
-            data: self.data.clone(),
-        }
+
+    // contains in a section of the set's group. Panics if the set was not made with in_group
+    // for the guard's group.
+    pub fn contains_in(&self, guard: &RluGroupReadGuard<'_>, value: T) -> bool {
+        self.contains_section(&guard.section(self.member()), value)
     }
-    fn cas(&self, new_obj: *mut Self) -> bool {
-        self.hdr
-            .p_obj_copy
-            .compare_and_swap(ptr::null_mut(), new_obj, Ordering::SeqCst)
-            .is_null()
+
+    // try_insert in a section of the set's group, which commits with the section. On
+    // Err(Conflict) the section should be aborted and retried.
+    pub fn try_insert_in(
+        &self,
+        guard: &RluGroupWriteGuard<'_>,
+        value: T,
+    ) -> Result<bool, RluError> {
+        self.insert_txn(&guard.txn(self.member()), value)
     }
-}
 
-pub struct RluSet<T: 'static + Clone> {
-    head: NodePtr<T>,
-    rlu_ptr: *mut GlobalRlu<Node<T>>,
-    thread_id: usize,
-}
+    // try_delete in a section of the set's group, see try_insert_in
+    pub fn try_delete_in(
+        &self,
+        guard: &RluGroupWriteGuard<'_>,
+        value: T,
+    ) -> Result<bool, RluError> {
+        self.delete_txn(&guard.txn(self.member()), value)
+    }
 
-unsafe impl<T: Clone> Send for RluSet<T> {}
-unsafe impl<T: Clone> Sync for RluSet<T> {}
+    fn contains_section<S: RluSection<Node<T>>>(&self, guard: &S, value: T) -> bool {
+        let head = guard.deref(self.nodes.head).unwrap();
+        let mut next = guard.deref(head.next);
+        while let Some(node) = next {
+            if node.data > value {
+                break;
+            }
+            if node.data == value {
+                return true;
+            }
+            next = guard.deref(node.next);
+        }
+        false
+    }
 
-pub fn rlu_new_node<T: Clone>(value: T) -> *mut Node<T> {
-    let node = Box::new(Node {
-        hdr: RluObjHdr {
-            p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-            ws_hdr: None,
-        },
-        next: ptr::null_mut(),
-        data: value,
-    });
+    fn insert_txn<W: RluWriter<Node<T>>>(&self, txn: &W, value: T) -> Result<bool, RluError> {
+        let mut prev = txn.deref(self.nodes.head).unwrap();
+        let mut next = txn.deref(prev.next);
+        while let Some(node) = next {
+            if node.data >= value {
+                if node.data == value {
+                    return Ok(false); //dont insert if already in list
+                }
+                break;
+            }
+            prev = node;
+            next = txn.deref(node.next);
+        }
+        let mut locked_prev = txn.lock(prev)?;
+        if let Some(node) = next {
+            txn.lock(node)?; //maybe can remove this? see gradescope
+        }
+
+        let new_node = rlu_new_node(value);
+        // make the new node point to the rest of the list
+        txn.assign(&mut new_node.next, next);
+        txn.assign(&mut locked_prev.next, Some(new_node));
+        Ok(true)
+    }
 
-    let tmp = Box::into_raw(node);
-    tmp
+    fn delete_txn<W: RluWriter<Node<T>>>(&self, txn: &W, value: T) -> Result<bool, RluError> {
+        let mut prev = txn.deref(self.nodes.head).unwrap(); //points to dummy head node
+        let mut next = txn.deref(prev.next);
+        while let Some(node) = next {
+            if node.data > value {
+                break;
+            }
+            if node.data == value {
+                let mut locked_prev = txn.lock(prev)?;
+                let locked_next = txn.lock(node)?;
+                locked_prev.next = locked_next.next;
+                txn.free(locked_next)?;
+                return Ok(true);
+            }
+            prev = node;
+            next = txn.deref(node.next);
+        }
+        Ok(false)
+    }
 }
 
-impl<T> RluSet<T>
+// This does not use RLU when traversing, it is just a simple printer for single threaded
+// debugging
+impl<T> Default for RluSet<T>
 where
     T: PartialEq + PartialOrd + Copy + Clone + Debug,
 {
-    pub fn new() -> RluSet<T> {
-        let rlu_ptr: *mut GlobalRlu<Node<T>> = GlobalRlu::init_rlu();
-        let thread_id = rlu_thread_init(rlu_ptr);
-        RluSet {
-            rlu_ptr: rlu_ptr,
-            head: Box::into_raw(Box::new(Node {
-                hdr: RluObjHdr {
-                    p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                    ws_hdr: None,
-                },
-                next: ptr::null_mut(),
-                data: unsafe { mem::MaybeUninit::zeroed().assume_init() }, // Okay bc this value will never be accessed
-            })),
-            thread_id: thread_id,
-        }
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    // This function does not use RLU when traversing, is just a simple function
-    // for single threaded debugging
-    pub fn to_string(&self) -> String {
-        let mut ret = String::from("{");
+impl<T> fmt::Display for RluSet<T>
+where
+    T: PartialEq + PartialOrd + Copy + Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
         unsafe {
-            let mut node_ptr = (*self.head).next;
-            loop {
-                if node_ptr.is_null() {
-                    break;
-                } else {
-                    ret.push_str(&format!("{:?}, ", (*node_ptr).data));
-                    node_ptr = (*node_ptr).next;
-                }
+            let mut node_ptr = (*self.nodes.head).next;
+            while !node_ptr.is_null() {
+                write!(f, "{:?}, ", (*node_ptr).data)?;
+                node_ptr = (*node_ptr).next;
             }
         }
-        ret.push('}');
-        ret
+        write!(f, "}}")
     }
 }
 
@@ -161,163 +245,39 @@ where
     T: PartialEq + PartialOrd + Copy + Clone + Debug + Unpin,
 {
     fn contains(&self, value: T) -> bool {
-        let mut ret = false;
-        rlu_reader_lock(self.rlu_ptr, self.thread_id);
-        //println!("Contains {:?}?", value);
-        let mut node_ptr = rlu_dereference(self.rlu_ptr, self.thread_id, self.head);
-        let mut first = true;
-        loop {
-            if node_ptr.is_null() {
-                break;
-            } else if first {
-                first = false;
-                node_ptr =
-                    rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*node_ptr).next });
-                continue;
-            } else {
-                let v = unsafe { (*node_ptr).data };
-                if v > value {
-                    break;
-                }
-                if v == value {
-                    ret = true;
-                    break;
-                }
-                node_ptr =
-                    rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*node_ptr).next });
-            }
-        }
-        rlu_reader_unlock(self.rlu_ptr, self.thread_id);
-        ret
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        self.contains_section(&guard, value)
     }
 
     fn len(&self) -> usize {
         let mut len = 0;
-        rlu_reader_lock(self.rlu_ptr, self.thread_id);
-        let mut node_ptr = rlu_dereference(self.rlu_ptr, self.thread_id, self.head);
-        let mut first = true;
-        loop {
-            if node_ptr.is_null() {
-                break;
-            } else {
-                if !first {
-                    len += 1;
-                } else {
-                    first = false;
-                }
-                node_ptr =
-                    rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*node_ptr).next });
-            }
+        let rlu = self.rlu.handle();
+        let guard = rlu.read();
+        let head = guard.deref(self.nodes.head).unwrap();
+        let mut next = guard.deref(head.next);
+        while let Some(node) = next {
+            len += 1;
+            next = guard.deref(node.next);
         }
-        rlu_reader_unlock(self.rlu_ptr, self.thread_id);
         len
     }
 
     fn insert(&self, value: T) -> bool {
-        loop {
-            rlu_reader_lock(self.rlu_ptr, self.thread_id);
-            let mut p_prev = rlu_dereference(self.rlu_ptr, self.thread_id, self.head);
-            let mut p_next =
-                rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*p_prev).next });
-            let mut exact_match = false;
-            loop {
-                if p_next.is_null() {
-                    break;
-                }
-                let v = unsafe { (*p_next).data };
-                if v >= value {
-                    if v == value {
-                        exact_match = true;
-                    }
-                    break;
-                }
-                p_prev = p_next;
-                p_next = rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*p_next).next });
-            }
-            if exact_match {
-                break; //dont insert if already in list
-            }
-            if !rlu_try_lock(self.rlu_ptr, self.thread_id, &mut p_prev) {
-                rlu_abort(self.rlu_ptr, self.thread_id);
-                continue; //retry
-            }
-
-            if !p_next.is_null() {
-                if !rlu_try_lock(self.rlu_ptr, self.thread_id, &mut p_next) {
-                    //maybe can remove this? see gradescope
-                    rlu_abort(self.rlu_ptr, self.thread_id);
-                    continue; //retry
-                }
-            }
-
-            let p_new_node = rlu_new_node(value);
-            // make the new node point to the current head of the list
-            rlu_assign_ptr(unsafe { &mut (*p_new_node).next }, p_next);
-            rlu_assign_ptr(unsafe { &mut (*p_prev).next }, p_new_node);
-            break;
-        }
-        rlu_reader_unlock(self.rlu_ptr, self.thread_id);
-        true
+        // a set section never locks more than two nodes or frees more than one, so a
+        // domain too small for that is a setup error, and so is a conflict in a batch
+        self.try_insert(value).unwrap_or_else(|err| panic!("{}", err))
     }
 
     fn delete(&self, value: T) -> bool {
-        let mut ret = false;
-        loop {
-            let mut continue_outer = false;
-            //outer loop for restarting on failed lock
-            rlu_reader_lock(self.rlu_ptr, self.thread_id);
-            let mut p_prev = rlu_dereference(self.rlu_ptr, self.thread_id, self.head); //points to dummy head node
-            let mut p_next =
-                rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*p_prev).next });
-            loop {
-                if p_next.is_null() {
-                    break;
-                } else {
-                    let v = unsafe { (*p_next).data };
-                    if v > value {
-                        break;
-                    }
-                    if v == value {
-                        if !rlu_try_lock(self.rlu_ptr, self.thread_id, &mut p_prev) {
-                            rlu_abort(self.rlu_ptr, self.thread_id);
-                            continue_outer = true;
-                            break;
-                        }
-                        if !rlu_try_lock(self.rlu_ptr, self.thread_id, &mut p_next) {
-                            rlu_abort(self.rlu_ptr, self.thread_id);
-                            continue_outer = true;
-                            break;
-                        }
-                        ret = true;
-                        unsafe {
-                            (*p_prev).next = (*p_next).next;
-                        }
-                        unsafe {
-                            rlu_free(self.rlu_ptr, self.thread_id, p_next);
-                        }
-                        break;
-                    }
-                    p_prev = p_next;
-                    p_next =
-                        rlu_dereference(self.rlu_ptr, self.thread_id, unsafe { (*p_next).next });
-                }
-            }
-            if continue_outer {
-                continue;
-            } else {
-                rlu_reader_unlock(self.rlu_ptr, self.thread_id);
-                break;
-            }
-        }
-        ret
+        self.try_delete(value).unwrap_or_else(|err| panic!("{}", err))
     }
 
     fn clone_ref(&self) -> Self {
-        let thread_id = rlu_thread_init(self.rlu_ptr);
         RluSet {
-            rlu_ptr: self.rlu_ptr,
-            head: self.head,
-            thread_id: thread_id,
+            rlu: self.rlu.clone(),
+            nodes: self.nodes.clone(),
+            member: self.member,
         }
     }
 }
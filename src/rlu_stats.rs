@@ -0,0 +1,119 @@
+// Runtime counters, compiled in with the "stats" feature. Every RluThread keeps its own set,
+// only ever written by the thread that owns it, and GlobalRlu::stats() adds them up. Without
+// the feature the counters are a zero sized type whose methods do nothing.
+
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "stats")]
+use std::time::{Duration, Instant};
+
+// A snapshot of a domain's (or one thread's) counters since the domain was created. Threads
+// that exited still count, so the totals only ever grow.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RluStats {
+    // rlu_reader_lock calls, writer sections included
+    pub reader_sections: u64,
+    // write log commits. Sections that locked something commit once each, or once per batch
+    // with GlobalRluBuilder::defer_commits
+    pub writer_commits: u64,
+    // rlu_try_lock calls that returned Ok(false)
+    pub try_lock_failures: u64,
+    pub aborts: u64,
+    // locked objects whose copy a reader used in rlu_dereference
+    pub steals: u64,
+    // times rlu_synchronize spun waiting on a reader
+    pub sync_spins: u64,
+    // wall time spent in rlu_synchronize
+    pub sync_time: Duration,
+    // objects reclaimed by rlu_process_free
+    pub objects_freed: u64,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) enum RluCounter {
+    ReaderSections,
+    WriterCommits,
+    TryLockFailures,
+    Aborts,
+    Steals,
+    SyncSpins,
+    SyncNanos,
+    ObjectsFreed,
+}
+
+#[cfg(feature = "stats")]
+const NUM_COUNTERS: usize = 8;
+
+#[cfg(feature = "stats")]
+#[derive(Default)]
+pub(crate) struct RluThreadStats {
+    counters: [AtomicU64; NUM_COUNTERS],
+}
+
+#[cfg(feature = "stats")]
+impl RluThreadStats {
+    pub(crate) fn new() -> RluThreadStats {
+        RluThreadStats::default()
+    }
+
+    #[inline]
+    pub(crate) fn add(&self, counter: RluCounter, n: u64) {
+        self.counters[counter as usize].fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn add_time(&self, counter: RluCounter, timer: RluTimer) {
+        self.add(counter, timer.0.elapsed().as_nanos() as u64);
+    }
+
+    pub(crate) fn add_to(&self, stats: &mut RluStats) {
+        let get = |counter: RluCounter| self.counters[counter as usize].load(Ordering::Relaxed);
+        stats.reader_sections += get(RluCounter::ReaderSections);
+        stats.writer_commits += get(RluCounter::WriterCommits);
+        stats.try_lock_failures += get(RluCounter::TryLockFailures);
+        stats.aborts += get(RluCounter::Aborts);
+        stats.steals += get(RluCounter::Steals);
+        stats.sync_spins += get(RluCounter::SyncSpins);
+        stats.sync_time += Duration::from_nanos(get(RluCounter::SyncNanos));
+        stats.objects_freed += get(RluCounter::ObjectsFreed);
+    }
+}
+
+#[cfg(feature = "stats")]
+pub(crate) struct RluTimer(Instant);
+
+#[cfg(feature = "stats")]
+impl RluTimer {
+    #[inline]
+    pub(crate) fn start() -> RluTimer {
+        RluTimer(Instant::now())
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+pub(crate) struct RluThreadStats;
+
+#[cfg(not(feature = "stats"))]
+impl RluThreadStats {
+    pub(crate) fn new() -> RluThreadStats {
+        RluThreadStats
+    }
+
+    #[inline(always)]
+    pub(crate) fn add(&self, _counter: RluCounter, _n: u64) {}
+
+    #[inline(always)]
+    pub(crate) fn add_time(&self, _counter: RluCounter, _timer: RluTimer) {}
+}
+
+#[cfg(not(feature = "stats"))]
+pub(crate) struct RluTimer;
+
+#[cfg(not(feature = "stats"))]
+impl RluTimer {
+    #[inline(always)]
+    pub(crate) fn start() -> RluTimer {
+        RluTimer
+    }
+}
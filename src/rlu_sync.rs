@@ -0,0 +1,24 @@
+// The atomics, locks and scheduling hints the RLU core synchronizes with. Building with
+// RUSTFLAGS="--cfg loom" swaps in loom's versions, so that tests/loom.rs can run the protocol
+// under every interleaving and every value the memory model lets a load return.
+//
+// The protocol only needs sequential consistency in two places, both a store followed by a
+// load of another thread's counter: a reader making its run_counter odd before it reads the
+// global clock, against a writer moving the global clock before it reads run_counters; and a
+// parked writer counting itself in before checking run_counters, against a reader leaving
+// its section before checking for parked writers. Each side of those has a SeqCst fence
+// between the two, everything else is acquire/release or relaxed.
+#[cfg(loom)]
+pub(crate) use loom::{
+    hint,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize},
+    sync::{Condvar, Mutex},
+    thread,
+};
+#[cfg(not(loom))]
+pub(crate) use std::{
+    hint,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize},
+    sync::{Condvar, Mutex},
+    thread,
+};
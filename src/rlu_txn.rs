@@ -0,0 +1,212 @@
+// Closure based writer sections. RluHandle::transaction runs a closure in a writer section
+// and commits it, or, if the closure reports RluError::Conflict, aborts the section, backs
+// off according to the domain's ContentionPolicy and runs the closure again.
+
+use crate::rlu::RluObj;
+use crate::rlu_error::RluError;
+use crate::rlu_guard::{RluHandle, RluLocked, RluSection, RluWriteGuard};
+use std::hint;
+use std::thread;
+
+// Decides what a transaction does between a conflict and its next attempt
+pub trait ContentionPolicy: Send + Sync {
+    // attempt counts the conflicts of this transaction so far, starting at 1
+    fn on_conflict(&self, attempt: u32);
+}
+
+// Retries right away. Fine when conflicts are rare or sections are very short.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Immediate;
+
+impl ContentionPolicy for Immediate {
+    fn on_conflict(&self, _attempt: u32) {}
+}
+
+// Gives the rest of the time slice to the lock holder, for oversubscribed machines
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Yield;
+
+impl ContentionPolicy for Yield {
+    fn on_conflict(&self, _attempt: u32) {
+        thread::yield_now();
+    }
+}
+
+// Spins for min_spins after the first conflict, doubling with every further one up to
+// max_spins, and also yields once the cap is reached. This is the default.
+#[derive(Debug, Copy, Clone)]
+pub struct Backoff {
+    min_spins: u32,
+    max_spins: u32,
+}
+
+impl Backoff {
+    pub fn new(min_spins: u32, max_spins: u32) -> Backoff {
+        assert!(min_spins <= max_spins, "min_spins must not exceed max_spins");
+        Backoff {
+            min_spins,
+            max_spins,
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(16, 4096)
+    }
+}
+
+impl ContentionPolicy for Backoff {
+    fn on_conflict(&self, attempt: u32) {
+        let spins = self
+            .min_spins
+            .checked_shl(attempt.saturating_sub(1))
+            .map_or(self.max_spins, |spins| std::cmp::min(spins, self.max_spins));
+        for _ in 0..spins {
+            hint::spin_loop();
+        }
+        if spins == self.max_spins {
+            thread::yield_now();
+        }
+    }
+}
+
+// The section a transaction closure runs in. Locking an object someone else holds returns
+// RluError::Conflict, which the closure passes on with ? to get re-run.
+pub struct RluTxn<'a, T: RluObj> {
+    guard: RluWriteGuard<'a, T>,
+}
+
+impl<'a, T> RluTxn<'a, T>
+where
+    T: RluObj,
+{
+    // p_obj must be null or a pointer read from an RLU protected object (or a root)
+    pub fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        self.guard.deref(p_obj)
+    }
+
+    // See RluWriteGuard::try_lock, an object with a live RluLocked fails with AlreadyLocked
+    pub fn lock(&self, obj: &T) -> Result<RluLocked<'_, T>, RluError> {
+        self.guard.try_lock(obj)?.ok_or(RluError::Conflict)
+    }
+
+    // Locks all of objs or none of them, see rlu_try_lock_all. The copies come back in the
+    // order of objs, and an object given twice fails with AlreadyLocked.
+    pub fn lock_all(&self, objs: &[&T]) -> Result<Vec<RluLocked<'_, T>>, RluError> {
+        self.guard.try_lock_all(objs)?.map_err(|_| RluError::Conflict)
+    }
+
+    // Stores the original of obj (never a copy) in a pointer field
+    pub fn assign(&self, p_ptr: &mut *mut T, obj: Option<&T>) {
+        self.guard.assign(p_ptr, obj);
+    }
+
+    // Frees the original of a locked object once no reader can see it anymore
+    pub fn free(&self, obj: RluLocked<'_, T>) -> Result<(), RluError> {
+        self.guard.free(obj)
+    }
+
+    // Runs f once this transaction has committed and no reader can see what it unlinked. A
+    // retried attempt drops the callbacks of the attempts before it.
+    pub fn defer<F>(&self, f: F) -> Result<(), RluError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.guard.defer(f)
+    }
+}
+
+impl<'a, T> RluSection<T> for RluTxn<'a, T>
+where
+    T: RluObj,
+{
+    fn deref(&self, p_obj: *mut T) -> Option<&T> {
+        RluTxn::deref(self, p_obj)
+    }
+}
+
+// The writer sections structure operations are written against, so that they run both in an
+// RluTxn and in one domain of a group section, see RluGroupWriteGuard::txn
+pub trait RluWriter<T: RluObj>: RluSection<T> {
+    // Err(Conflict) if another writer holds obj
+    fn lock(&self, obj: &T) -> Result<RluLocked<'_, T>, RluError>;
+    fn lock_all(&self, objs: &[&T]) -> Result<Vec<RluLocked<'_, T>>, RluError>;
+    fn assign(&self, p_ptr: &mut *mut T, obj: Option<&T>);
+    fn free(&self, obj: RluLocked<'_, T>) -> Result<(), RluError>;
+}
+
+impl<'a, T> RluWriter<T> for RluTxn<'a, T>
+where
+    T: RluObj,
+{
+    fn lock(&self, obj: &T) -> Result<RluLocked<'_, T>, RluError> {
+        RluTxn::lock(self, obj)
+    }
+
+    fn lock_all(&self, objs: &[&T]) -> Result<Vec<RluLocked<'_, T>>, RluError> {
+        RluTxn::lock_all(self, objs)
+    }
+
+    fn assign(&self, p_ptr: &mut *mut T, obj: Option<&T>) {
+        RluTxn::assign(self, p_ptr, obj);
+    }
+
+    fn free(&self, obj: RluLocked<'_, T>) -> Result<(), RluError> {
+        RluTxn::free(self, obj)
+    }
+}
+
+impl<T> RluHandle<T>
+where
+    T: RluObj,
+{
+    // Runs f until it returns anything but Err(RluError::Conflict), backing off with the
+    // domain's contention policy in between. Ok commits the section, any other error aborts
+    // it and is returned. f may run several times, so it should only have effects through txn.
+    // Inside another section of this handle a conflict is returned rather than retried, and
+    // the outer section can no longer commit. In coarse mode f runs holding the domain's
+    // writer lock, so it only conflicts when nested.
+    pub fn transaction<R, F>(&self, f: F) -> Result<R, RluError>
+    where
+        F: FnMut(&RluTxn<'_, T>) -> Result<R, RluError>,
+    {
+        let policy = unsafe { (*self.rlu_ptr()).contention_policy() };
+        self.transaction_with(policy, f)
+    }
+
+    // Like transaction, with a policy other than the domain's
+    pub fn transaction_with<R, F, P>(&self, policy: &P, mut f: F) -> Result<R, RluError>
+    where
+        F: FnMut(&RluTxn<'_, T>) -> Result<R, RluError>,
+        P: ContentionPolicy + ?Sized,
+    {
+        let mut attempt = 0;
+        loop {
+            let txn = RluTxn {
+                guard: self.write(),
+            };
+            let nested = txn.guard.is_nested();
+            match f(&txn) {
+                Ok(result) if !txn.guard.inner_aborted() => {
+                    txn.guard.commit()?;
+                    return Ok(result);
+                }
+                Ok(_) | Err(RluError::Conflict) => {
+                    txn.guard.abort();
+                    if nested {
+                        // the lock holder's commit waits for the outer section, which has to
+                        // end before this can succeed, so the whole of it is retried instead
+                        return Err(RluError::Conflict);
+                    }
+                    attempt += 1;
+                    policy.on_conflict(attempt);
+                }
+                Err(err) => {
+                    txn.guard.abort();
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
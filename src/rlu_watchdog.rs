@@ -0,0 +1,65 @@
+// Reports of writers stuck waiting for readers. A domain built with a sync_timeout hands one
+// to its stall handler each time a commit or synchronize has waited that long, and keeps
+// waiting. rlu_try_synchronize reports the same way, then gives up with SyncTimeout, which
+// carries the report for callers without a handler. A writer waiting for a coarse domain's
+// writer lock reports too, with the thread holding the lock as the one blocking it.
+
+use std::fmt;
+use std::time::Duration;
+
+// What a stalled writer is waiting for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RluWaitKind {
+    // readers to leave their sections, in a commit or synchronize
+    Readers,
+    // a coarse domain's writer lock, held by the one blocking thread
+    WriterLock,
+}
+
+impl fmt::Display for RluWaitKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RluWaitKind::Readers => write!(f, "readers"),
+            RluWaitKind::WriterLock => write!(f, "the writer lock"),
+        }
+    }
+}
+
+// A thread that a writer is waiting on, as of the report: a reader, or the writer holding
+// the writer lock
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RluBlockingThread {
+    pub id: usize,
+    pub run_counter: u64, //odd, the section it is inside of
+    pub local_clock: u64, //the global clock when that section started
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RluStallReport {
+    pub waiter: usize, //the writer's thread id
+    pub waiting_for: RluWaitKind,
+    pub waited: Duration,
+    pub write_clock: u64, //u64::MAX unless the writer is committing
+    pub blocking: Vec<RluBlockingThread>,
+}
+
+impl fmt::Display for RluStallReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RLU thread {} has waited {:?} for {}:",
+            self.waiter, self.waited, self.waiting_for
+        )?;
+        for (i, reader) in self.blocking.iter().enumerate() {
+            write!(
+                f,
+                "{} thread {} (run_counter {}, local_clock {})",
+                if i == 0 { "" } else { "," },
+                reader.id,
+                reader.run_counter,
+                reader.local_clock
+            )?;
+        }
+        Ok(())
+    }
+}
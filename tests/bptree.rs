@@ -4,7 +4,7 @@
 #[cfg(test)]
 mod tests {
     use core::panic;
-    use std::{result, vec};
+    use std::vec;
     use rlu::{BPTree, BPTreeNode};
 
     #[test]
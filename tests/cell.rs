@@ -0,0 +1,62 @@
+use rlu::{GlobalRlu, RluCell, RluMode};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn cell_read_update() {
+    let cell = RluCell::new(vec![1, 2, 3]);
+    assert_eq!(cell.read(|v| v.len()), 3);
+    cell.update(|v| v.push(4));
+    assert_eq!(cell.read(|v| v.clone()), vec![1, 2, 3, 4]);
+    assert_eq!(cell.replace(vec![]), vec![1, 2, 3, 4]);
+    assert!(cell.read(|v| v.is_empty()));
+}
+
+#[test]
+fn cell_concurrent_updates() {
+    let cell = RluCell::with_builder(0u64, GlobalRlu::builder().mode(RluMode::MultiVersion));
+    let mut workers = vec![];
+    for _ in 0..4 {
+        let cell = cell.clone_ref();
+        workers.push(thread::spawn(move || {
+            for _ in 0..500 {
+                cell.update(|count| *count += 1);
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(cell.read(|count| *count), 2000);
+}
+
+// Readers must only ever see whole tables, while a writer keeps replacing them
+#[test]
+fn cell_routing_table() {
+    let cell = RluCell::new(HashMap::new());
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let cell = cell.clone_ref();
+        let stop = stop.clone();
+        readers.push(thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                cell.read(|table: &HashMap<u32, u32>| {
+                    let generation = table.get(&0).copied().unwrap_or(0);
+                    assert!(table.values().all(|&v| v == generation));
+                });
+            }
+        }));
+    }
+    for generation in 1..200 {
+        let table = (0..16).map(|route| (route, generation)).collect();
+        cell.replace(table);
+    }
+    stop.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(cell.read(|table| table[&5]), 199);
+}
@@ -0,0 +1,218 @@
+use rlu::{
+    rlu_abort, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init, rlu_try_lock, rlu_writer_lock,
+    ConcurrentSet, GlobalRlu, RluHandle, RluLocking, RluMode, RluObj, RluObjHdr, RluSet,
+    RluStallReport, RluWaitKind, WaitStrategy,
+};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Counters(*mut Counter, *mut Counter);
+
+unsafe impl Send for Counters {}
+
+fn new_counter() -> *mut Counter {
+    Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        value: 0,
+    }))
+}
+
+fn coarse() -> *mut GlobalRlu<Counter> {
+    GlobalRlu::builder()
+        .max_threads(8)
+        .locking(RluLocking::Coarse)
+        .init_rlu()
+}
+
+#[test]
+fn coarse_one_writer_at_a_time() {
+    let rlu_ptr = coarse();
+    assert_eq!(unsafe { (*rlu_ptr).locking() }, RluLocking::Coarse);
+    let (first, second) = (new_counter(), new_counter());
+    let writer = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let other = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+
+    unsafe { rlu_writer_lock(rlu_ptr, writer) }.unwrap();
+    let mut p_first = first;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_first) }.unwrap());
+    unsafe { (*p_first).value = 1 };
+
+    // a different object, but the domain has one writer lock
+    unsafe { rlu_reader_lock(rlu_ptr, other) }.unwrap();
+    let mut p_second = second;
+    assert!(!unsafe { rlu_try_lock(rlu_ptr, other, &mut p_second) }.unwrap());
+    unsafe { rlu_abort(rlu_ptr, other) }.unwrap();
+
+    unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, other) }.unwrap();
+    let mut p_second = second;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, other, &mut p_second) }.unwrap());
+    let mut p_first = first;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, other, &mut p_first) }.unwrap());
+    assert_eq!(unsafe { (*p_first).value }, 1);
+    unsafe { rlu_reader_unlock(rlu_ptr, other) }.unwrap();
+}
+
+#[test]
+fn coarse_abort_releases_writer_lock() {
+    let rlu_ptr = coarse();
+    let counter = new_counter();
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let other = unsafe { RluHandle::new(rlu_ptr) };
+    {
+        let guard = handle.write();
+        guard.try_lock(guard.deref(counter).unwrap())
+            .unwrap()
+            .unwrap()
+            .value = 5;
+        guard.abort();
+    }
+    // a section that only read gives the lock back too
+    drop(handle.write());
+    let guard = other.write();
+    let locked = guard.try_lock(guard.deref(counter).unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(locked.value, 0);
+}
+
+#[test]
+fn coarse_writers_never_conflict() {
+    let rlu_ptr = coarse();
+    let counters = Counters(new_counter(), new_counter());
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let handle = handle.clone_ref();
+            thread::spawn(move || {
+                let counters = counters;
+                for _ in 0..500 {
+                    // both locks always succeed, so the section never aborts
+                    let guard = handle.write();
+                    guard.try_lock(guard.deref(counters.0).unwrap())
+                        .unwrap()
+                        .unwrap()
+                        .value += 1;
+                    guard.try_lock(guard.deref(counters.1).unwrap())
+                        .unwrap()
+                        .unwrap()
+                        .value += 1;
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let guard = handle.read();
+    assert_eq!(guard.deref(counters.0).unwrap().value, 2000);
+    assert_eq!(guard.deref(counters.1).unwrap().value, 2000);
+}
+
+#[test]
+fn coarse_set() {
+    let set: Arc<RluSet<u64>> = Arc::new(RluSet::with_builder(
+        GlobalRlu::builder().locking(RluLocking::Coarse),
+    ));
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let set = set.clone();
+            thread::spawn(move || {
+                for i in 0..200 {
+                    assert!(set.insert(i * 4 + t));
+                }
+                for i in 0..100 {
+                    assert!(set.delete(i * 4 + t));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(set.len(), 400);
+    assert!(!set.contains(0) && set.contains(400));
+}
+
+#[test]
+#[should_panic(expected = "coarse locking needs RluMode::SingleVersion without deferral")]
+fn coarse_needs_single_version() {
+    let _ = GlobalRlu::<Counter>::builder()
+        .mode(RluMode::MultiVersion)
+        .locking(RluLocking::Coarse)
+        .build();
+}
+
+#[derive(Copy, Clone)]
+struct Shared(*mut GlobalRlu<Counter>, *mut Counter);
+
+unsafe impl Send for Shared {}
+
+// A writer waiting for the writer lock waits like a commit does, and reports the holder
+fn writer_lock_reports_stall(wait_strategy: WaitStrategy) {
+    let reports = Arc::new(Mutex::new(Vec::<RluStallReport>::new()));
+    let sink = reports.clone();
+    let rlu_ptr = GlobalRlu::builder()
+        .max_threads(3)
+        .locking(RluLocking::Coarse)
+        .wait_strategy(wait_strategy)
+        .sync_timeout(Duration::from_millis(10))
+        .on_stall(move |report| sink.lock().unwrap().push(report.clone()))
+        .init_rlu();
+    let shared = Shared(rlu_ptr, new_counter());
+
+    let (id_tx, id_rx) = mpsc::channel();
+    let (leave_tx, leave_rx) = mpsc::channel::<()>();
+    let holder = thread::spawn(move || {
+        let shared = shared;
+        let handle = unsafe { RluHandle::new(shared.0) };
+        let guard = handle.write();
+        guard.try_lock(guard.deref(shared.1).unwrap()).unwrap().unwrap().value += 1;
+        id_tx.send(handle.id()).unwrap();
+        leave_rx.recv().unwrap();
+    });
+    let holder_id = id_rx.recv().unwrap();
+    let waiter = thread::spawn(move || {
+        let shared = shared;
+        let handle = unsafe { RluHandle::new(shared.0) };
+        let guard = handle.write();
+        guard.try_lock(guard.deref(shared.1).unwrap()).unwrap().unwrap().value += 1;
+        handle.id()
+    });
+    while reports.lock().unwrap().len() < 2 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!waiter.is_finished());
+    leave_tx.send(()).unwrap();
+    holder.join().unwrap();
+    let waiter_id = waiter.join().unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert!(reports[1].waited > reports[0].waited);
+    for report in reports.iter() {
+        assert_eq!(report.waiter, waiter_id);
+        assert_eq!(report.waiting_for, RluWaitKind::WriterLock);
+        assert_eq!(report.write_clock, u64::MAX);
+        assert_eq!(report.blocking.len(), 1);
+        assert_eq!(report.blocking[0].id, holder_id);
+        assert_eq!(report.blocking[0].run_counter % 2, 1);
+    }
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    assert_eq!(handle.read().deref(shared.1).unwrap().value, 2);
+}
+
+#[test]
+fn coarse_writer_lock_reports_stall() {
+    writer_lock_reports_stall(WaitStrategy::Spin);
+    writer_lock_reports_stall(WaitStrategy::SpinThenYield { spins: 16 });
+    writer_lock_reports_stall(WaitStrategy::Park { spins: 16 });
+}
@@ -0,0 +1,96 @@
+// Run with `cargo test --features debug-checks`
+#![cfg(feature = "debug-checks")]
+
+use rlu::{
+    rlu_assign_ptr, rlu_check_write, rlu_dereference, rlu_reader_lock, rlu_reader_unlock,
+    rlu_thread_init, rlu_try_lock, GlobalRlu, RluObj, RluObjHdr,
+};
+use std::ptr;
+
+#[derive(RluObj)]
+pub struct Node {
+    hdr: RluObjHdr<Node>,
+    next: *mut Node,
+}
+
+fn new_node() -> *mut Node {
+    Box::into_raw(Box::new(Node {
+        hdr: RluObjHdr::new(),
+        next: ptr::null_mut(),
+    }))
+}
+
+// A domain with one registered thread, and a node that thread has locked in an open section
+fn locked_node() -> (*mut GlobalRlu<Node>, usize, *mut Node, *mut Node) {
+    let rlu_ptr: *mut GlobalRlu<Node> = GlobalRlu::init_rlu();
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let node = new_node();
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    let mut copy = node;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+    (rlu_ptr, id, node, copy)
+}
+
+#[test]
+fn debug_checks_allow_protocol() {
+    let (rlu_ptr, id, node, copy) = locked_node();
+    unsafe { rlu_check_write(rlu_ptr, id, copy) };
+    unsafe { rlu_assign_ptr(&mut (*copy).next, copy) };
+    assert_eq!(unsafe { (*copy).next }, node);
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
+
+#[test]
+#[should_panic(expected = "called rlu_dereference outside of a section")]
+fn debug_checks_dereference_outside_section() {
+    let rlu_ptr: *mut GlobalRlu<Node> = GlobalRlu::init_rlu();
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let _ = unsafe { rlu_dereference(rlu_ptr, id, new_node()) };
+}
+
+#[test]
+#[should_panic(expected = "which is not a write log copy (lock it first)")]
+fn debug_checks_write_to_original() {
+    let (rlu_ptr, id, node, _copy) = locked_node();
+    unsafe { rlu_check_write(rlu_ptr, id, node) };
+}
+
+#[test]
+#[should_panic(expected = "a copy locked by thread 0")]
+fn debug_checks_write_to_foreign_copy() {
+    let (rlu_ptr, id, _node, copy) = locked_node();
+    let other = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    assert_eq!(id, 0);
+    unsafe { rlu_reader_lock(rlu_ptr, other) }.unwrap();
+    unsafe { rlu_check_write(rlu_ptr, other, copy) };
+}
+
+#[test]
+#[should_panic(expected = "by a section that has ended")]
+fn debug_checks_stale_copy() {
+    let (rlu_ptr, id, _node, copy) = locked_node();
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    unsafe { rlu_check_write(rlu_ptr, id, copy) };
+}
+
+#[test]
+#[should_panic(expected = "which is a write log copy")]
+fn debug_checks_assign_copy() {
+    let (_rlu_ptr, _id, _node, copy) = locked_node();
+    // a copy whose original pointer leads to another copy
+    let bogus = Box::into_raw(Box::new(Node {
+        hdr: RluObjHdr::new_copy(copy, 1, 0),
+        next: ptr::null_mut(),
+    }));
+    let mut p_next = ptr::null_mut();
+    unsafe { rlu_assign_ptr(&mut p_next, bogus) };
+}
+
+#[test]
+#[should_panic(expected = "lost its write-set header")]
+fn debug_checks_copy_lost_header() {
+    let (rlu_ptr, id, _node, copy) = locked_node();
+    unsafe { (*copy).hdr.ws_hdr = None };
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
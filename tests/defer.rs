@@ -0,0 +1,140 @@
+use rlu::{
+    rlu_defer, rlu_reader_lock, rlu_reader_unlock, rlu_synchronize, GlobalRlu, RluError,
+    RluHandle, RluMode, RluObj, RluObjHdr,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(RluObj)]
+pub struct Entry {
+    hdr: RluObjHdr<Entry>,
+    file: usize,
+}
+
+fn new_entry(file: usize) -> *mut Entry {
+    Box::into_raw(Box::new(Entry {
+        hdr: RluObjHdr::new(),
+        file,
+    }))
+}
+
+// Stands in for an external resource, like a file handle, that readers reach through an entry
+struct Files {
+    closed: Vec<AtomicUsize>,
+}
+
+impl Files {
+    fn new(n: usize) -> Arc<Files> {
+        Arc::new(Files {
+            closed: (0..n).map(|_| AtomicUsize::new(0)).collect(),
+        })
+    }
+
+    fn closed(&self, file: usize) -> usize {
+        self.closed[file].load(Ordering::SeqCst)
+    }
+
+    fn close_later(self: &Arc<Files>, file: usize) -> impl FnOnce() + Send + 'static {
+        let files = self.clone();
+        move || {
+            files.closed[file].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+fn retire_after_reader(mode: RluMode) {
+    let rlu_ptr: *mut GlobalRlu<Entry> = GlobalRlu::builder().mode(mode).init_rlu();
+    let entry = new_entry(0);
+    let files = Files::new(2);
+    let reader = unsafe { RluHandle::new(rlu_ptr) };
+    let writer = reader.clone_ref();
+
+    // the reader still sees file 0 while the writer moves the entry to file 1
+    let guard = reader.read();
+    assert_eq!(guard.deref(entry).unwrap().file, 0);
+    let p_entry = entry as usize;
+    let close = files.close_later(0);
+    let worker = thread::spawn(move || {
+        let entry = p_entry as *mut Entry;
+        let guard = writer.write();
+        guard.try_lock(guard.deref(entry).unwrap()).unwrap().unwrap().file = 1;
+        guard.defer(close).unwrap();
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(guard.deref(entry).unwrap().file, 0);
+    assert_eq!(files.closed(0), 0);
+    drop(guard);
+    worker.join().unwrap();
+    assert_eq!(files.closed(0), 1);
+    assert_eq!(reader.read().deref(entry).unwrap().file, 1);
+}
+
+#[test]
+fn defer_waits_for_readers() {
+    retire_after_reader(RluMode::SingleVersion);
+}
+
+#[test]
+fn defer_waits_for_readers_mv() {
+    retire_after_reader(RluMode::MultiVersion);
+}
+
+#[test]
+fn defer_dropped_on_abort() {
+    let rlu_ptr: *mut GlobalRlu<Entry> = GlobalRlu::init_rlu();
+    let entry = new_entry(0);
+    let files = Files::new(2);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    // a callback queued outside of a section is not part of the next one
+    handle.defer(files.close_later(1)).unwrap();
+    let guard = handle.write();
+    guard.try_lock(guard.deref(entry).unwrap()).unwrap().unwrap().file = 1;
+    guard.defer(files.close_later(0)).unwrap();
+    guard.abort();
+    assert_eq!(Arc::strong_count(&files), 2);
+
+    // a retried transaction only keeps the callbacks of the attempt that committed
+    let mut runs = 0;
+    handle
+        .transaction(|txn| {
+            runs += 1;
+            txn.defer(files.close_later(0))?;
+            if runs < 3 {
+                return Err(RluError::Conflict);
+            }
+            txn.lock(txn.deref(entry).unwrap())?.file = 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(files.closed(0), 1);
+    assert_eq!(files.closed(1), 1);
+    assert_eq!(Arc::strong_count(&files), 1);
+}
+
+#[test]
+fn defer_synchronize() {
+    let rlu_ptr: *mut GlobalRlu<Entry> = GlobalRlu::builder().max_free_nodes(2).init_rlu();
+    let files = Files::new(3);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    handle.defer(files.close_later(0)).unwrap();
+    handle.defer(files.close_later(1)).unwrap();
+    assert_eq!(handle.defer(files.close_later(2)), Err(RluError::FreeListFull));
+    assert_eq!(files.closed(0), 0);
+    handle.synchronize();
+    assert_eq!((files.closed(0), files.closed(1), files.closed(2)), (1, 1, 0));
+
+    // synchronize inside one of our own sections is an error
+    let id = handle.id();
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    assert_eq!(unsafe { rlu_synchronize(rlu_ptr, id) }, Err(RluError::UnbalancedSection));
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+
+    // whatever is still queued runs when the thread exits
+    unsafe { rlu_defer(rlu_ptr, id, files.close_later(2)) }.unwrap();
+    drop(handle);
+    assert_eq!(files.closed(2), 1);
+}
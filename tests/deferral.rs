@@ -0,0 +1,205 @@
+use rlu::{BPlusTree, ConcurrentSet, GlobalRlu, RluHandle, RluObj, RluObjHdr, RluSet};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Counters([*mut Counter; 4]);
+
+unsafe impl Send for Counters {}
+
+fn new_counters(value: u64) -> Counters {
+    Counters([(); 4].map(|_| {
+        Box::into_raw(Box::new(Counter {
+            hdr: RluObjHdr::new(),
+            value,
+        }))
+    }))
+}
+
+fn add(handle: &RluHandle<Counter>, counter: *mut Counter, n: u64) {
+    handle
+        .transaction(|txn| {
+            txn.lock(txn.deref(counter).unwrap())?.value += n;
+            Ok(())
+        })
+        .unwrap();
+}
+
+fn value(handle: &RluHandle<Counter>, counter: *mut Counter) -> u64 {
+    handle.read().deref(counter).unwrap().value
+}
+
+#[test]
+fn deferral_batches_commits() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().defer_commits(3).init_rlu();
+    let counters = new_counters(0);
+    let writer = unsafe { RluHandle::new(rlu_ptr) };
+    let reader = writer.clone_ref();
+
+    // the writer sees its own deferred sections, nobody else does until the third commits
+    add(&writer, counters.0[0], 1);
+    add(&writer, counters.0[1], 1);
+    assert_eq!(value(&writer, counters.0[0]), 1);
+    assert_eq!(value(&reader, counters.0[0]), 0);
+    add(&writer, counters.0[2], 1);
+    assert_eq!(value(&reader, counters.0[0]), 1);
+    assert_eq!(value(&reader, counters.0[2]), 1);
+
+    add(&writer, counters.0[3], 1);
+    assert_eq!(value(&reader, counters.0[3]), 0);
+    writer.flush();
+    assert_eq!(value(&reader, counters.0[3]), 1);
+
+    // locking an object again that a deferred section holds commits the batch first
+    add(&writer, counters.0[0], 1);
+    add(&writer, counters.0[0], 1);
+    assert_eq!(value(&reader, counters.0[0]), 2);
+    assert_eq!(value(&writer, counters.0[0]), 3);
+
+    // as does dropping the handle
+    drop(writer);
+    assert_eq!(value(&reader, counters.0[0]), 3);
+}
+
+#[test]
+fn deferral_sync_request() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().defer_commits(100).init_rlu();
+    let counters = new_counters(0);
+    let holder = unsafe { RluHandle::new(rlu_ptr) };
+    let other = holder.clone_ref();
+
+    add(&holder, counters.0[0], 1);
+    let guard = other.write();
+    assert!(
+        guard.try_lock(guard.deref(counters.0[0]).unwrap())
+            .unwrap()
+            .is_none()
+    );
+    guard.abort();
+
+    // the failed lock asked the holder to commit, which it does when its next section ends
+    assert_eq!(value(&other, counters.0[0]), 0);
+    holder.read().unlock();
+    assert_eq!(value(&other, counters.0[0]), 1);
+    add(&other, counters.0[0], 1);
+    other.flush();
+    assert_eq!(value(&holder, counters.0[0]), 2);
+}
+
+#[test]
+fn deferral_log_pressure() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder()
+        .defer_commits(100)
+        .log_size(6)
+        .max_log_size(6)
+        .init_rlu();
+    let counters = new_counters(0);
+    let writer = unsafe { RluHandle::new(rlu_ptr) };
+    let reader = writer.clone_ref();
+
+    // half of the log holds 3 copies, a section that needs all of them commits the batch
+    add(&writer, counters.0[0], 1);
+    assert_eq!(value(&reader, counters.0[0]), 0);
+    let mut runs = 0;
+    writer
+        .transaction(|txn| {
+            runs += 1;
+            for &counter in counters.0.iter().skip(1) {
+                txn.lock(txn.deref(counter).unwrap())?.value += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(runs, 2);
+    assert_eq!(value(&reader, counters.0[0]), 1);
+    assert_eq!(value(&reader, counters.0[3]), 1);
+}
+
+#[test]
+fn deferral_concurrent_transfers() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().defer_commits(16).init_rlu();
+    let counters = new_counters(1000);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    let mut workers = vec![];
+    for t in 0..4 {
+        let handle = handle.clone_ref();
+        workers.push(thread::spawn(move || {
+            let counters = counters;
+            for i in 0..500 {
+                let from = counters.0[(t + i) % 4];
+                let to = counters.0[(t + i + 1) % 4];
+                handle
+                    .transaction(|txn| {
+                        txn.lock(txn.deref(from).unwrap())?.value -= 1;
+                        txn.lock(txn.deref(to).unwrap())?.value += 1;
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+        }));
+    }
+    for _ in 0..500 {
+        let guard = handle.read();
+        let total: u64 = counters
+            .0
+            .iter()
+            .map(|&counter| guard.deref(counter).unwrap().value)
+            .sum();
+        assert_eq!(total, 4000);
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let guard = handle.read();
+    for &counter in counters.0.iter() {
+        assert_eq!(guard.deref(counter).unwrap().value, 1000);
+    }
+}
+
+#[test]
+fn deferral_idle_thread() {
+    let set: RluSet<u64> = RluSet::with_builder(GlobalRlu::builder().defer_commits(64));
+    let tree = BPlusTree::with_builder(GlobalRlu::builder().defer_commits(64));
+    let (inserted_tx, inserted_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let idle = {
+        let (set, tree) = (set.clone_ref(), tree.clone_ref());
+        thread::spawn(move || {
+            assert!(set.insert(10));
+            tree.insert(10, 100);
+            inserted_tx.send(()).unwrap();
+            done_rx.recv().unwrap(); //idle, still registered on both domains
+        })
+    };
+    inserted_rx.recv().unwrap();
+
+    // these need the nodes the idle thread locked, which it committed when its inserts ended
+    let (finished_tx, finished_rx) = mpsc::channel();
+    let writer = {
+        let (set, tree) = (set.clone_ref(), tree.clone_ref());
+        thread::spawn(move || {
+            assert!(set.insert(20));
+            assert!(set.delete(10));
+            tree.insert(10, 101);
+            tree.insert(20, 200);
+            finished_tx.send(()).unwrap();
+        })
+    };
+    finished_rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("a writer waited on the locks of an idle deferring thread");
+    writer.join().unwrap();
+    assert!(!set.contains(10) && set.contains(20));
+    assert_eq!(tree.search(&10), Some(101));
+
+    done_tx.send(()).unwrap();
+    idle.join().unwrap();
+}
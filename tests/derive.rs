@@ -0,0 +1,129 @@
+use rlu::{
+    rlu_dereference, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init, rlu_try_lock,
+    GlobalRlu, RluHandle, RluMode, RluObj, RluObjHdr,
+};
+use std::ptr;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    count: u64,
+    name: String,
+}
+
+unsafe impl Send for Counter {}
+unsafe impl Sync for Counter {}
+
+#[derive(RluObj)]
+pub struct Cell<T: 'static + Clone> {
+    value: T,
+    next: *mut Cell<T>,
+    header: rlu::RluObjHdr<Cell<T>>,
+}
+
+#[derive(Copy, Clone)]
+struct CounterPtr(*mut Counter);
+
+unsafe impl Send for CounterPtr {}
+unsafe impl Sync for CounterPtr {}
+
+#[test]
+fn derive_copy_and_writeback() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let obj = Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        count: 1,
+        name: String::from("a"),
+    }));
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    let mut p_obj = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut p_obj) }.unwrap());
+    unsafe {
+        assert!((*obj).is_locked());
+        assert!((*p_obj).is_copy());
+        assert_eq!((*p_obj).get_p_original(), obj);
+        assert_eq!((*p_obj).get_locking_thread_from_ws_obj(), id);
+        (*p_obj).count = 2;
+        (*p_obj).name.push('b');
+        assert_eq!((*obj).count, 1);
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+
+    unsafe {
+        assert!(!(*obj).is_locked());
+        assert!(!(*obj).is_copy());
+        assert_eq!((*obj).count, 2);
+        assert_eq!((*obj).name, "ab");
+    }
+}
+
+#[test]
+fn derive_generic_list() {
+    let rlu_ptr: *mut GlobalRlu<Cell<u32>> = GlobalRlu::builder()
+        .mode(RluMode::MultiVersion)
+        .init_rlu();
+    let tail = Box::into_raw(Box::new(Cell {
+        value: 2,
+        next: ptr::null_mut(),
+        header: RluObjHdr::new(),
+    }));
+    let head = Box::into_raw(Box::new(Cell {
+        value: 1,
+        next: tail,
+        header: RluObjHdr::new(),
+    }));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    {
+        let guard = handle.write();
+        let head = guard.deref(head).unwrap();
+        let mut locked = guard.try_lock(head).unwrap().unwrap();
+        locked.value = 10;
+        guard.assign(&mut locked.next, None);
+    }
+
+    let guard = handle.read();
+    let head = guard.deref(head).unwrap();
+    assert_eq!(head.value, 10);
+    assert!(guard.deref(head.next).is_none());
+}
+
+#[test]
+fn derive_concurrent_increments() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let obj = CounterPtr(Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        count: 0,
+        name: String::new(),
+    })));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    let mut workers = vec![];
+    for _ in 0..4 {
+        let handle = handle.clone_ref();
+        workers.push(thread::spawn(move || {
+            let obj = obj;
+            let mut done = 0;
+            while done < 500 {
+                let guard = handle.write();
+                let counter = guard.deref(obj.0).unwrap();
+                let incremented = guard.try_lock(counter).unwrap().map(|mut locked| {
+                    locked.count += 1;
+                });
+                match incremented {
+                    Some(()) => done += 1,
+                    None => guard.abort(),
+                }
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let guard = handle.read();
+    assert_eq!(guard.deref(obj.0).unwrap().count, 2000);
+}
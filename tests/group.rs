@@ -0,0 +1,261 @@
+use rlu::{
+    BPlusTree, ConcurrentSet, GlobalRlu, RluError, RluGroup, RluGroupMember, RluHandle,
+    RluLocking, RluMode, RluObj, RluObjHdr, RluSet,
+};
+use std::panic;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Stock {
+    hdr: RluObjHdr<Stock>,
+    count: u64,
+}
+
+#[derive(RluObj)]
+pub struct Ledger {
+    hdr: RluObjHdr<Ledger>,
+    entries: Vec<u64>,
+}
+
+#[derive(Copy, Clone)]
+struct Shop {
+    stock_rlu: RluGroupMember<Stock>,
+    ledger_rlu: RluGroupMember<Ledger>,
+    stock: *mut Stock,
+    ledger: *mut Ledger,
+}
+
+unsafe impl Send for Shop {}
+
+const INITIAL_STOCK: u64 = 2000;
+
+fn new_shop(group: &mut RluGroup, stock_mode: RluMode, ledger_mode: RluMode) -> Shop {
+    Shop {
+        stock_rlu: group.add(GlobalRlu::builder().mode(stock_mode)),
+        ledger_rlu: group.add(GlobalRlu::builder().mode(ledger_mode)),
+        stock: Box::into_raw(Box::new(Stock {
+            hdr: RluObjHdr::new(),
+            count: INITIAL_STOCK,
+        })),
+        ledger: Box::into_raw(Box::new(Ledger {
+            hdr: RluObjHdr::new(),
+            entries: Vec::new(),
+        })),
+    }
+}
+
+// Writers move units from the stock into the ledger in one section, readers check that
+// nothing is ever missing or counted twice across the two domains
+fn sell_concurrently(stock_mode: RluMode, ledger_mode: RluMode) {
+    let mut group = RluGroup::new();
+    let shop = new_shop(&mut group, stock_mode, ledger_mode);
+    let handle = group.handle();
+
+    let mut workers = vec![];
+    for t in 0..4 {
+        let handle = handle.clone_ref();
+        workers.push(thread::spawn(move || {
+            let shop = shop;
+            let mut sold = 0;
+            while sold < 250 {
+                let guard = handle.write();
+                let stock = guard.deref(shop.stock_rlu, shop.stock).unwrap();
+                let ledger = guard.deref(shop.ledger_rlu, shop.ledger).unwrap();
+                let locked = (
+                    guard.try_lock(shop.stock_rlu, stock).unwrap(),
+                    guard.try_lock(shop.ledger_rlu, ledger).unwrap(),
+                );
+                if let (Some(mut stock), Some(mut ledger)) = locked {
+                    stock.count -= 1;
+                    ledger.entries.push(t);
+                    sold += 1;
+                } else {
+                    drop(locked);
+                    guard.abort();
+                }
+            }
+        }));
+    }
+    for _ in 0..4 {
+        let handle = handle.clone_ref();
+        workers.push(thread::spawn(move || {
+            let shop = shop;
+            for _ in 0..300 {
+                let guard = handle.read();
+                let stock = guard.deref(shop.stock_rlu, shop.stock).unwrap();
+                let ledger = guard.deref(shop.ledger_rlu, shop.ledger).unwrap();
+                assert_eq!(stock.count + ledger.entries.len() as u64, INITIAL_STOCK);
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let guard = handle.read();
+    assert_eq!(guard.deref(shop.stock_rlu, shop.stock).unwrap().count, INITIAL_STOCK - 1000);
+    assert_eq!(guard.deref(shop.ledger_rlu, shop.ledger).unwrap().entries.len(), 1000);
+    drop(guard);
+    unsafe {
+        drop(Box::from_raw(shop.stock));
+        drop(Box::from_raw(shop.ledger));
+    }
+}
+
+#[test]
+fn group_single_version() {
+    sell_concurrently(RluMode::SingleVersion, RluMode::SingleVersion);
+}
+
+#[test]
+fn group_multi_version() {
+    sell_concurrently(RluMode::MultiVersion, RluMode::MultiVersion);
+}
+
+#[test]
+fn group_mixed_modes() {
+    sell_concurrently(RluMode::SingleVersion, RluMode::MultiVersion);
+}
+
+#[test]
+fn group_abort() {
+    let mut group = RluGroup::new();
+    let shop = new_shop(&mut group, RluMode::SingleVersion, RluMode::SingleVersion);
+    let handle = group.handle();
+
+    let guard = handle.write();
+    let stock = guard.deref(shop.stock_rlu, shop.stock).unwrap();
+    guard.try_lock(shop.stock_rlu, stock).unwrap().unwrap().count = 0;
+    let ledger = guard.deref(shop.ledger_rlu, shop.ledger).unwrap();
+    guard.try_lock(shop.ledger_rlu, ledger).unwrap().unwrap().entries.push(1);
+    guard.abort();
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let guard = handle.write();
+        let stock = guard.deref(shop.stock_rlu, shop.stock).unwrap();
+        guard.try_lock(shop.stock_rlu, stock).unwrap().unwrap().count = 0;
+        panic!("halfway through");
+    }));
+    assert!(result.is_err());
+
+    let guard = handle.read();
+    assert_eq!(guard.deref(shop.stock_rlu, shop.stock).unwrap().count, INITIAL_STOCK);
+    assert!(guard.deref(shop.ledger_rlu, shop.ledger).unwrap().entries.is_empty());
+}
+
+// A nested group section that aborts undoes the outer one's writes in every domain, and the
+// outer commit says so
+#[test]
+fn group_nested_abort() {
+    let mut group = RluGroup::new();
+    let shop = new_shop(&mut group, RluMode::SingleVersion, RluMode::MultiVersion);
+    let handle = group.handle();
+
+    let outer = handle.write();
+    let stock = outer.deref(shop.stock_rlu, shop.stock).unwrap();
+    outer.try_lock(shop.stock_rlu, stock).unwrap().unwrap().count = 0;
+    let inner = handle.write();
+    let ledger = inner.deref(shop.ledger_rlu, shop.ledger).unwrap();
+    inner.try_lock(shop.ledger_rlu, ledger).unwrap().unwrap().entries.push(1);
+    inner.abort();
+    assert_eq!(outer.commit(), Err(RluError::Conflict));
+
+    let guard = handle.read();
+    assert_eq!(guard.deref(shop.stock_rlu, shop.stock).unwrap().count, INITIAL_STOCK);
+    assert!(guard.deref(shop.ledger_rlu, shop.ledger).unwrap().entries.is_empty());
+    drop(guard);
+    handle.write().commit().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "cannot defer commits or use coarse locking")]
+fn group_rejects_coarse_domain() {
+    let mut group = RluGroup::new();
+    group.add::<Stock>(GlobalRlu::builder().locking(RluLocking::Coarse));
+}
+
+// A plain handle on one domain of the group shares its clock, so its commits are ordered
+// with the group's
+#[test]
+fn group_member_handle() {
+    let mut group = RluGroup::new();
+    let shop = new_shop(&mut group, RluMode::MultiVersion, RluMode::MultiVersion);
+    let handle = group.handle();
+    let stock_handle = unsafe { RluHandle::new(shop.stock_rlu.rlu_ptr()) };
+
+    {
+        let guard = stock_handle.write();
+        let stock = guard.deref(shop.stock).unwrap();
+        guard.try_lock(stock).unwrap().unwrap().count = 7;
+    }
+    let guard = handle.read();
+    assert_eq!(guard.deref(shop.stock_rlu, shop.stock).unwrap().count, 7);
+}
+
+#[test]
+#[should_panic(expected = "another group")]
+fn group_foreign_member() {
+    let mut group = RluGroup::new();
+    let shop = new_shop(&mut group, RluMode::SingleVersion, RluMode::SingleVersion);
+    let mut other_group = RluGroup::new();
+    let _other_shop = new_shop(&mut other_group, RluMode::SingleVersion, RluMode::SingleVersion);
+    let other = other_group.handle();
+    other.read().deref(shop.stock_rlu, shop.stock);
+}
+
+// A set and a B+ tree index over the same keys in one group. Each key goes into both in one
+// section, and readers never see it in only one of them.
+#[test]
+fn group_set_and_tree() {
+    let mut group = RluGroup::new();
+    let set: RluSet<u64> = RluSet::in_group(&mut group, GlobalRlu::builder());
+    let index: BPlusTree<u64, u64> =
+        BPlusTree::in_group(&mut group, GlobalRlu::builder().mode(RluMode::MultiVersion));
+    let handle = group.handle();
+
+    let mut workers = vec![];
+    for t in 0..2 {
+        let (handle, set, index) = (handle.clone_ref(), set.clone_ref(), index.clone_ref());
+        workers.push(thread::spawn(move || {
+            for key in (0..100).map(|i| t * 1000 + i) {
+                loop {
+                    let guard = handle.write();
+                    let result = set
+                        .try_insert_in(&guard, key)
+                        .and_then(|_| index.try_insert_in(&guard, key, key * 2));
+                    match result {
+                        Ok(()) => break guard.commit().unwrap(),
+                        Err(RluError::Conflict) => guard.abort(),
+                        Err(err) => panic!("{}", err),
+                    }
+                }
+            }
+        }));
+    }
+    for _ in 0..2 {
+        let (handle, set, index) = (handle.clone_ref(), set.clone_ref(), index.clone_ref());
+        workers.push(thread::spawn(move || {
+            for _ in 0..50 {
+                let guard = handle.read();
+                for key in (0..100).flat_map(|i| vec![i, 1000 + i]) {
+                    let indexed = index.search_in(&guard, &key);
+                    assert_eq!(set.contains_in(&guard, key), indexed.is_some());
+                    assert!(indexed.is_none_or(|value| value == key * 2));
+                }
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // an aborted section changes neither, and the structures still work on their own
+    let guard = handle.write();
+    assert_eq!(set.try_delete_in(&guard, 5), Ok(true));
+    index.try_insert_in(&guard, 5, 0).unwrap();
+    guard.abort();
+    assert_eq!(set.len(), 200);
+    assert_eq!(index.search(&5), Some(10));
+    assert!(set.insert(5000));
+    assert!(set.contains_in(&handle.read(), 5000));
+}
@@ -0,0 +1,130 @@
+use rlu::{
+    rlu_free, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init, rlu_try_lock, GlobalRlu,
+    RluMode, RluObj, RluObjHdr, RluSnapshot, RluThreadSnapshot,
+};
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+fn new_counter() -> *mut Counter {
+    Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        value: 0,
+    }))
+}
+
+#[test]
+fn inspect_threads() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().max_threads(4).init_rlu();
+    let writer = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let reader = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let (counter, doomed) = (new_counter(), new_counter());
+
+    // a committed section, then one in progress with a copy and a pending free
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    let mut p_obj = counter;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_obj) }.unwrap());
+    unsafe { (*p_obj).value = 1 };
+    unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, reader) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    let mut p_doomed = doomed;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_doomed) }.unwrap());
+    unsafe { rlu_free(rlu_ptr, writer, p_doomed) }.unwrap();
+
+    let snapshot = unsafe { &*rlu_ptr }.inspect();
+    assert_eq!(snapshot.global_clock, 1);
+    assert_eq!(snapshot.mode, RluMode::SingleVersion);
+    assert_eq!(snapshot.max_threads, 4);
+    assert_eq!(snapshot.threads.len(), 2);
+    let (w, r) = (&snapshot.threads[writer], &snapshot.threads[reader]);
+    assert_eq!((w.id, r.id), (writer, reader));
+    assert_eq!((w.run_counter, r.run_counter), (3, 1));
+    assert_eq!((w.local_clock, r.local_clock), (1, 1));
+    assert_eq!(w.write_clock, None);
+    assert!(w.is_writer && !r.is_writer);
+    assert_eq!((w.num_of_objs, w.pending_frees), (1, 1));
+    assert!(w.cur_pos >= 1);
+    assert_eq!((r.num_of_objs, r.pending_frees, r.cur_pos), (0, 0, 0));
+
+    let text = snapshot.to_string();
+    assert!(text.starts_with(
+        "RLU domain (SingleVersion): global clock 1, 2 of 4 threads registered\n"
+    ));
+    assert_eq!(text.lines().count(), 4);
+}
+
+struct Domain(*mut GlobalRlu<Counter>);
+
+unsafe impl Send for Domain {}
+
+#[test]
+fn inspect_multi_version_from_another_thread() {
+    let rlu_ptr: *mut GlobalRlu<Counter> =
+        GlobalRlu::builder().mode(RluMode::MultiVersion).max_threads(2).init_rlu();
+    let writer = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let (first, second) = (new_counter(), new_counter());
+
+    // the locked versions live in the MV write set, and are counted while the section runs
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    let (mut p_first, mut p_second) = (first, second);
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_first) }.unwrap());
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_second) }.unwrap());
+    let domain = Domain(rlu_ptr);
+    let snapshot = std::thread::spawn(move || {
+        let domain = &domain;
+        unsafe { &*domain.0 }.inspect()
+    })
+    .join()
+    .unwrap();
+    assert_eq!(snapshot.mode, RluMode::MultiVersion);
+    let w = &snapshot.threads[writer];
+    assert!(w.is_writer);
+    assert_eq!((w.run_counter, w.num_of_objs), (1, 2));
+
+    unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap();
+    let w = &unsafe { &*rlu_ptr }.inspect().threads[writer];
+    assert!(!w.is_writer);
+    assert_eq!((w.run_counter, w.num_of_objs), (2, 0));
+}
+
+#[test]
+fn inspect_json() {
+    let snapshot = RluSnapshot {
+        global_clock: 7,
+        mode: RluMode::MultiVersion,
+        max_threads: 2,
+        threads: vec![RluThreadSnapshot {
+            id: 1,
+            run_counter: 5,
+            local_clock: 6,
+            write_clock: Some(8),
+            is_writer: true,
+            cur_pos: 2,
+            num_of_objs: 1,
+            pending_frees: 0,
+            pending_callbacks: 3,
+            poisoned: false,
+        }],
+    };
+    assert_eq!(
+        snapshot.to_json(),
+        "{\"global_clock\":7,\"mode\":\"MultiVersion\",\"max_threads\":2,\"threads\":[\
+         {\"id\":1,\"run_counter\":5,\"local_clock\":6,\"write_clock\":8,\"is_writer\":true,\
+         \"cur_pos\":2,\"num_of_objs\":1,\"pending_frees\":0,\"pending_callbacks\":3,\
+         \"poisoned\":false}]}"
+    );
+    let idle = RluThreadSnapshot {
+        write_clock: None,
+        ..snapshot.threads[0].clone()
+    };
+    assert!(RluSnapshot {
+        threads: vec![idle],
+        ..snapshot
+    }
+    .to_json()
+    .contains("\"write_clock\":null"));
+}
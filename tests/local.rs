@@ -0,0 +1,79 @@
+use rlu::{BPlusTree, ConcurrentSet, GlobalRlu, RluCell, RluObj, RluObjHdr, RluSet, RluShared};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[test]
+fn local_handle_per_thread() {
+    let shared: RluShared<Counter> = RluShared::new(GlobalRlu::builder().max_threads(2));
+    let id = shared.handle().id();
+    assert_eq!(shared.handle().id(), id);
+    assert_eq!(shared.clone().handle().id(), id);
+    let other = thread::scope(|s| s.spawn(|| shared.handle().id()).join().unwrap());
+    assert_ne!(other, id);
+}
+
+#[test]
+fn local_shared_as_arc() {
+    let tree: Arc<BPlusTree<u64, u64>> = Arc::new(BPlusTree::new());
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    tree.insert(i * 4 + t, i);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    for key in 0..1000 {
+        assert_eq!(tree.search(&key), Some(key / 4));
+    }
+    tree.validate_tree_structure().unwrap();
+}
+
+#[test]
+fn local_slot_released_at_thread_exit() {
+    // one slot, which every thread in turn takes and gives back when it exits
+    let set: RluSet<u64> = RluSet::with_builder(GlobalRlu::builder().max_threads(1));
+    for i in 0..5 {
+        thread::scope(|s| s.spawn(|| assert!(set.insert(i))).join().unwrap());
+    }
+    assert_eq!(set.len(), 5);
+
+    // commits deferred by a thread are made when it exits
+    let cell = RluCell::with_builder(0, GlobalRlu::builder().defer_commits(8));
+    thread::scope(|s| s.spawn(|| cell.replace(1)).join().unwrap());
+    assert_eq!(cell.read(|value| *value), 1);
+}
+
+#[test]
+fn local_dropped_before_thread_exits() {
+    let set = Arc::new(RluSet::new());
+    let (used_tx, used_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let worker = {
+        let set = set.clone();
+        thread::spawn(move || {
+            assert!(set.insert(1));
+            drop(set);
+            used_tx.send(()).unwrap();
+            done_rx.recv().unwrap(); //still registered on the domain
+        })
+    };
+    used_rx.recv().unwrap();
+    // the last reference gives back the worker's slot, so its exit has nothing left to do
+    assert!(set.contains(1));
+    drop(set);
+    done_tx.send(()).unwrap();
+    worker.join().unwrap();
+}
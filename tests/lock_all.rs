@@ -0,0 +1,304 @@
+use rlu::{
+    rlu_abort, rlu_dereference, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init, rlu_try_lock,
+    rlu_try_lock_all, GlobalRlu, RluError, RluHandle, RluLockAll, RluMode, RluObj, RluObjHdr,
+};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Counters([*mut Counter; 3]);
+
+unsafe impl Send for Counters {}
+
+fn new_counter(value: u64) -> *mut Counter {
+    Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        value,
+    }))
+}
+
+#[test]
+fn lock_all_locked() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let counters = [new_counter(0), new_counter(1), new_counter(2)];
+
+    // the copies come back in the order asked for, whatever the address order is
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    let (mut p_a, mut p_b, mut p_c) = (counters[2], counters[0], counters[1]);
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, id, &mut [&mut p_a, &mut p_b, &mut p_c]) },
+        Ok(RluLockAll::Locked)
+    );
+    for (p_obj, original) in [(p_a, counters[2]), (p_b, counters[0]), (p_c, counters[1])] {
+        assert_ne!(p_obj, original);
+        unsafe {
+            assert_eq!((*p_obj).get_p_original(), original);
+            assert!((*original).is_locked());
+            (*p_obj).value += 10;
+        }
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    for (i, &original) in counters.iter().enumerate() {
+        assert_eq!(unsafe { (*original).value }, i as u64 + 10);
+    }
+    // the same object twice is refused before anything is locked, as original or copy, and
+    // so is a null
+    let (mut p_a, mut p_b) = (counters[0], counters[0]);
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, id, &mut [&mut p_a, &mut p_b]) },
+        Err(RluError::AlreadyLocked)
+    );
+    assert_eq!((p_a, p_b), (counters[0], counters[0]));
+    assert!(!unsafe { (*counters[0]).is_locked() });
+    let mut p_copy = counters[2];
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut p_copy) }.unwrap());
+    let (mut p_a, mut p_b) = (counters[0], counters[2]);
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, id, &mut [&mut p_a, &mut p_copy, &mut p_b]) },
+        Err(RluError::AlreadyLocked)
+    );
+    assert!(!unsafe { (*counters[0]).is_locked() });
+    let (mut p_a, mut p_null) = (counters[1], ptr::null_mut());
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, id, &mut [&mut p_a, &mut p_null]) },
+        Err(RluError::NullObject)
+    );
+    assert_eq!(p_a, counters[1]);
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
+
+fn is_writer(rlu_ptr: *mut GlobalRlu<Counter>, id: usize) -> bool {
+    let snapshot = unsafe { (*rlu_ptr).inspect() };
+    snapshot.threads.iter().find(|thread| thread.id == id).unwrap().is_writer
+}
+
+fn conflict_unlocks_its_own(mode: RluMode) {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().mode(mode).init_rlu();
+    let holder = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let writer = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let (kept, counters) = (
+        new_counter(0),
+        [new_counter(0), new_counter(0), new_counter(0)],
+    );
+
+    unsafe { rlu_reader_lock(rlu_ptr, holder) }.unwrap();
+    let mut p_held = counters[1];
+    assert!(unsafe { rlu_try_lock(rlu_ptr, holder, &mut p_held) }.unwrap());
+
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    let mut p_kept = kept;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut p_kept) }.unwrap());
+    let (mut p_a, mut p_b, mut p_c) = (counters[0], counters[1], counters[2]);
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, writer, &mut [&mut p_a, &mut p_b, &mut p_c]) },
+        Ok(RluLockAll::Conflict { index: 1 })
+    );
+    // the pointers are untouched, and only what the section locked before is still locked
+    assert_eq!([p_a, p_b, p_c], counters);
+    unsafe {
+        assert!((*kept).is_locked());
+        assert!(!(*counters[0]).is_locked() && !(*counters[2]).is_locked());
+        assert!((*counters[1]).is_locked());
+    }
+    assert!(is_writer(rlu_ptr, writer));
+    unsafe { rlu_abort(rlu_ptr, writer) }.unwrap();
+
+    // a section that had locked nothing is a reader again, and has nothing to commit
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, writer, &mut [&mut p_a, &mut p_b, &mut p_c]) },
+        Ok(RluLockAll::Conflict { index: 1 })
+    );
+    assert!(!is_writer(rlu_ptr, writer));
+    unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap();
+    unsafe { rlu_reader_unlock(rlu_ptr, holder) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+    assert_eq!(
+        unsafe { rlu_try_lock_all(rlu_ptr, writer, &mut [&mut p_a, &mut p_b, &mut p_c]) },
+        Ok(RluLockAll::Locked)
+    );
+    unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap();
+}
+
+#[test]
+fn lock_all_conflict_unlocks_its_own() {
+    conflict_unlocks_its_own(RluMode::SingleVersion);
+    conflict_unlocks_its_own(RluMode::MultiVersion);
+}
+
+#[derive(Copy, Clone)]
+struct Shared(*mut GlobalRlu<Counter>, *mut Counter, *mut Counter);
+
+unsafe impl Send for Shared {}
+
+// Two threads ask for the same pair in opposite orders, retrying on conflict. Both lock in
+// address order, so neither holds one of the pair while waiting on the other, and both finish
+// their sections well within the deadline.
+fn opposite_orders_progress(mode: RluMode) {
+    const SECTIONS: u64 = 500;
+    let rlu_ptr: *mut GlobalRlu<Counter> =
+        GlobalRlu::builder().mode(mode).max_threads(3).init_rlu();
+    let shared = Shared(rlu_ptr, new_counter(0), new_counter(0));
+    let start = Instant::now();
+    let workers: Vec<_> = (0..2)
+        .map(|t| {
+            thread::spawn(move || {
+                let shared = shared;
+                let id = unsafe { rlu_thread_init(shared.0) }.unwrap();
+                let mut done = 0;
+                while done < SECTIONS {
+                    assert!(start.elapsed() < Duration::from_secs(60), "thread {} stuck", t);
+                    unsafe { rlu_reader_lock(shared.0, id) }.unwrap();
+                    let mut p_a = unsafe { rlu_dereference(shared.0, id, shared.1) }.unwrap();
+                    let mut p_b = unsafe { rlu_dereference(shared.0, id, shared.2) }.unwrap();
+                    let mut objs = [&mut p_a, &mut p_b];
+                    if t == 1 {
+                        objs.reverse();
+                    }
+                    match unsafe { rlu_try_lock_all(shared.0, id, &mut objs) }.unwrap() {
+                        RluLockAll::Locked => {
+                            unsafe {
+                                (*p_a).value += 1;
+                                (*p_b).value += 1;
+                            }
+                            unsafe { rlu_reader_unlock(shared.0, id) }.unwrap();
+                            done += 1;
+                        }
+                        RluLockAll::Conflict { .. } => unsafe { rlu_abort(shared.0, id) }.unwrap(),
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    for p_obj in [shared.1, shared.2] {
+        let p_obj = unsafe { rlu_dereference(rlu_ptr, id, p_obj) }.unwrap();
+        assert_eq!(unsafe { (*p_obj).value }, 2 * SECTIONS);
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
+
+#[test]
+fn lock_all_opposite_orders_progress() {
+    opposite_orders_progress(RluMode::SingleVersion);
+    opposite_orders_progress(RluMode::MultiVersion);
+}
+
+#[test]
+fn lock_all_txn_overlapping() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let counters = Counters([new_counter(0), new_counter(0), new_counter(0)]);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let workers: Vec<_> = (0..3)
+        .map(|t| {
+            let handle = handle.clone_ref();
+            thread::spawn(move || {
+                let counters = counters;
+                // each thread locks two of the three, listed in a different order
+                let (first, second) = (counters.0[t], counters.0[(t + 1) % 3]);
+                for _ in 0..300 {
+                    handle
+                        .transaction(|txn| {
+                            let objs = [txn.deref(first).unwrap(), txn.deref(second).unwrap()];
+                            for mut locked in txn.lock_all(&objs)? {
+                                locked.value += 1;
+                            }
+                            Ok(())
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let guard = handle.read();
+    for &counter in &counters.0 {
+        assert_eq!(guard.deref(counter).unwrap().value, 600);
+    }
+}
+
+#[test]
+fn lock_all_rejects_duplicates() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let counter = new_counter(0);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    // the same object twice would be two live RluLocked of one copy
+    let result = handle.transaction(|txn| {
+        let obj = txn.deref(counter).unwrap();
+        txn.lock_all(&[obj, obj])?;
+        Ok(())
+    });
+    assert_eq!(result, Err(RluError::AlreadyLocked));
+
+    // also when one of them is already the copy
+    let result = handle.transaction(|txn| {
+        txn.lock(txn.deref(counter).unwrap())?.value = 1;
+        let copy = txn.deref(counter).unwrap();
+        let obj = unsafe { &*counter };
+        txn.lock_all(&[obj, copy])?;
+        Ok(())
+    });
+    assert_eq!(result, Err(RluError::AlreadyLocked));
+
+    // neither attempt committed, and nothing was left locked
+    handle
+        .transaction(|txn| {
+            let obj = txn.deref(counter).unwrap();
+            assert_eq!(obj.value, 0);
+            txn.lock_all(&[obj])?[0].value = 2;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(handle.read().deref(counter).unwrap().value, 2);
+}
+
+#[test]
+fn lock_rejects_held_copies() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let counter = new_counter(0);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    {
+        let guard = handle.write();
+        let mut locked = guard.try_lock(guard.deref(counter).unwrap()).unwrap().unwrap();
+        locked.value = 1;
+        // a second RluLocked, original or copy, would alias the first one
+        let obj = unsafe { &*counter };
+        assert_eq!(guard.try_lock(obj).err(), Some(RluError::AlreadyLocked));
+        assert_eq!(guard.try_lock(&locked).err(), Some(RluError::AlreadyLocked));
+        assert_eq!(guard.try_lock_all(&[obj]).err(), Some(RluError::AlreadyLocked));
+        drop(locked);
+        // once it is gone the copy can be read and locked again
+        let copy = guard.deref(counter).unwrap();
+        assert_eq!(copy.value, 1);
+        guard.try_lock(copy).unwrap().unwrap().value = 2;
+    }
+    assert_eq!(handle.read().deref(counter).unwrap().value, 2);
+
+    // and deref() does not hand out the copy while it is held
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let guard = handle.write();
+        let _locked = guard.try_lock(guard.deref(counter).unwrap()).unwrap().unwrap();
+        guard.deref(counter);
+    }));
+    assert!(result.is_err());
+    assert_eq!(handle.read().deref(counter).unwrap().value, 2);
+}
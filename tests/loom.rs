@@ -0,0 +1,285 @@
+// Model checks the RLU protocol with loom: every interleaving of a few threads, up to a bound
+// on preemptions, and every value the memory model lets each atomic load return. Only built
+// in a loom build, which swaps loom's atomics into the RLU core:
+//
+//     RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//
+// LOOM_MAX_PREEMPTIONS changes the bound of 3 used here.
+#![cfg(loom)]
+
+use loom::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+use rlu::{GlobalRlu, RluHandle, RluLocking, RluMode, RluObj, RluObjHdr};
+
+// Half of a Pair. Its loads and stores are relaxed, so loom only shows a reader what a writer
+// stored if the RLU protocol itself orders the two.
+pub struct Half(AtomicU64);
+
+impl Half {
+    fn new(value: u64) -> Half {
+        Half(AtomicU64::new(value))
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed)
+    }
+}
+
+impl Clone for Half {
+    fn clone(&self) -> Half {
+        Half::new(self.get())
+    }
+
+    // Writeback stores into the original's atomic, rather than replacing it with a new one
+    // that loom would have no older values for
+    fn clone_from(&mut self, source: &Half) {
+        self.set(source.get());
+    }
+}
+
+// Every write sets both halves to the same value, a reader seeing them differ saw a torn write
+#[derive(RluObj)]
+pub struct Pair {
+    hdr: RluObjHdr<Pair>,
+    a: Half,
+    b: Half,
+}
+
+#[derive(Copy, Clone)]
+struct SharedPair(*mut Pair);
+
+unsafe impl Send for SharedPair {}
+
+impl SharedPair {
+    fn new() -> SharedPair {
+        SharedPair(Box::into_raw(Box::new(Pair {
+            hdr: RluObjHdr::new(),
+            a: Half::new(0),
+            b: Half::new(0),
+        })))
+    }
+
+    unsafe fn free(self) {
+        drop(Box::from_raw(self.0));
+    }
+}
+
+fn model<F>(f: F)
+where
+    F: Fn() + Sync + Send + 'static,
+{
+    let mut builder = loom::model::Builder::new();
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+    builder.check(f);
+}
+
+fn domain(mode: RluMode) -> RluHandle<Pair> {
+    RluHandle::new_domain(GlobalRlu::builder().max_threads(3).mode(mode))
+}
+
+// Increments the pair in one section, false if another writer held the lock
+fn increment(handle: &RluHandle<Pair>, pair: SharedPair) -> bool {
+    let guard = handle.write();
+    let obj = guard.deref(pair.0).unwrap();
+    let incremented = guard.try_lock(obj).unwrap().map(|locked| {
+        locked.a.set(locked.a.get() + 1);
+        locked.b.set(locked.b.get() + 1);
+    });
+    if incremented.is_none() {
+        guard.abort();
+    }
+    incremented.is_some()
+}
+
+// Reads the pair twice in one section, which must see the same, untorn values both times
+fn read_twice(handle: &RluHandle<Pair>, pair: SharedPair) -> u64 {
+    let guard = handle.read();
+    let first = guard.deref(pair.0).unwrap();
+    let (a, b) = (first.a.get(), first.b.get());
+    assert_eq!(a, b);
+    let second = guard.deref(pair.0).unwrap();
+    assert_eq!((second.a.get(), second.b.get()), (a, b));
+    a
+}
+
+// Two writers race for the lock: one of them wins, or both do one after the other, and no
+// increment is lost
+fn try_lock_race(mode: RluMode) {
+    model(move || {
+        let pair = SharedPair::new();
+        let handle = domain(mode);
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let handle = handle.clone_ref();
+                thread::spawn(move || increment(&handle, pair))
+            })
+            .collect();
+        let wins = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .filter(|&won| won)
+            .count() as u64;
+        assert!(wins >= 1);
+        assert_eq!(read_twice(&handle, pair), wins);
+        drop(handle);
+        unsafe { pair.free() };
+    });
+}
+
+#[test]
+fn loom_try_lock() {
+    try_lock_race(RluMode::SingleVersion);
+}
+
+#[test]
+fn loom_try_lock_mv() {
+    try_lock_race(RluMode::MultiVersion);
+}
+
+// Coarse writers take turns on the writer lock, so both increments land, and each one's
+// writeback and unlock are visible to the next before it stores its copy pointer
+#[test]
+fn loom_coarse_writers() {
+    model(|| {
+        let pair = SharedPair::new();
+        let handle = RluHandle::new_domain(
+            GlobalRlu::builder()
+                .max_threads(3)
+                .locking(RluLocking::Coarse),
+        );
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let handle = handle.clone_ref();
+                thread::spawn(move || {
+                    let guard = handle.write();
+                    let locked = guard.try_lock(guard.deref(pair.0).unwrap())
+                        .unwrap()
+                        .unwrap();
+                    locked.a.set(locked.a.get() + 1);
+                    locked.b.set(locked.b.get() + 1);
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(read_twice(&handle, pair), 2);
+        drop(handle);
+        unsafe { pair.free() };
+    });
+}
+
+// A reader running alongside a commit sees the pair before or after it, never half of each.
+// Readers that start after the commit's clock moves steal the writer's copy, which must
+// carry the writer's stores, and the writeback must not reach readers still on the original.
+fn read_during_commit(mode: RluMode) {
+    model(move || {
+        let pair = SharedPair::new();
+        let handle = domain(mode);
+        let writer = {
+            let handle = handle.clone_ref();
+            thread::spawn(move || assert!(increment(&handle, pair)))
+        };
+        let reader = {
+            let handle = handle.clone_ref();
+            thread::spawn(move || read_twice(&handle, pair))
+        };
+        writer.join().unwrap();
+        assert!(reader.join().unwrap() <= 1);
+        assert_eq!(read_twice(&handle, pair), 1);
+        drop(handle);
+        unsafe { pair.free() };
+    });
+}
+
+#[test]
+fn loom_dereference_steal() {
+    read_during_commit(RluMode::SingleVersion);
+}
+
+#[test]
+fn loom_dereference_steal_mv() {
+    read_during_commit(RluMode::MultiVersion);
+}
+
+// A callback deferred by a commit only runs once every reader that could still see the old
+// pair has left its section
+#[test]
+fn loom_commit_defer() {
+    model(|| {
+        let pair = SharedPair::new();
+        let retired = Arc::new(AtomicBool::new(false));
+        let handle = domain(RluMode::SingleVersion);
+        let writer = {
+            let handle = handle.clone_ref();
+            let retired = retired.clone();
+            thread::spawn(move || {
+                let guard = handle.write();
+                let obj = guard.deref(pair.0).unwrap();
+                let locked = guard.try_lock(obj).unwrap().unwrap();
+                locked.a.set(1);
+                locked.b.set(1);
+                guard
+                    .defer(move || retired.store(true, Ordering::Relaxed))
+                    .unwrap();
+            })
+        };
+        let reader = {
+            let handle = handle.clone_ref();
+            let retired = retired.clone();
+            thread::spawn(move || {
+                let guard = handle.read();
+                if guard.deref(pair.0).unwrap().a.get() == 0 {
+                    assert!(!retired.load(Ordering::Relaxed));
+                }
+            })
+        };
+        writer.join().unwrap();
+        reader.join().unwrap();
+        assert!(retired.load(Ordering::Relaxed));
+        drop(handle);
+        unsafe { pair.free() };
+    });
+}
+
+// rlu_synchronize outside of any section: a reader either entered before it and is waited
+// for, or entered after and cannot see what was unpublished before the call
+#[test]
+fn loom_synchronize() {
+    model(|| {
+        let published = Arc::new(AtomicBool::new(true));
+        let retired = Arc::new(AtomicBool::new(false));
+        let handle = domain(RluMode::SingleVersion);
+        let retirer = {
+            let handle = handle.clone_ref();
+            let (published, retired) = (published.clone(), retired.clone());
+            thread::spawn(move || {
+                published.store(false, Ordering::Relaxed);
+                handle
+                    .defer(move || retired.store(true, Ordering::Relaxed))
+                    .unwrap();
+                handle.synchronize();
+            })
+        };
+        let reader = {
+            let handle = handle.clone_ref();
+            let (published, retired) = (published.clone(), retired.clone());
+            thread::spawn(move || {
+                let _guard = handle.read();
+                if published.load(Ordering::Relaxed) {
+                    assert!(!retired.load(Ordering::Relaxed));
+                }
+            })
+        };
+        retirer.join().unwrap();
+        reader.join().unwrap();
+        assert!(retired.load(Ordering::Relaxed));
+    });
+}
@@ -0,0 +1,207 @@
+use rlu::{
+    rlu_abort, rlu_reader_lock, rlu_reader_unlock, rlu_try_lock, BPlusTree, ConcurrentSet,
+    ContentionPolicy, GlobalRlu, RluError, RluHandle, RluObj, RluObjHdr, RluSet,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Account {
+    hdr: RluObjHdr<Account>,
+    balance: i64,
+}
+
+fn new_account(balance: i64) -> *mut Account {
+    Box::into_raw(Box::new(Account {
+        hdr: RluObjHdr::new(),
+        balance,
+    }))
+}
+
+fn balance(handle: &RluHandle<Account>, account: *mut Account) -> i64 {
+    handle.read().deref(account).unwrap().balance
+}
+
+#[derive(Default)]
+struct CountingPolicy {
+    conflicts: AtomicU32,
+}
+
+impl ContentionPolicy for CountingPolicy {
+    fn on_conflict(&self, _attempt: u32) {
+        self.conflicts.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn nested_only_outermost_commits() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::init_rlu();
+    let account = new_account(10);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let other = handle.clone_ref();
+
+    let outer = handle.write();
+    {
+        let inner = handle.write();
+        inner.try_lock(inner.deref(account).unwrap()).unwrap().unwrap().balance = 20;
+        // an inner read sees the section's own writes
+        assert_eq!(handle.read().deref(account).unwrap().balance, 20);
+    }
+    assert_eq!(outer.deref(account).unwrap().balance, 20);
+    assert_eq!(balance(&other, account), 10);
+    outer.commit().unwrap();
+    assert_eq!(balance(&other, account), 20);
+
+    // an inner abort undoes the outer section too, which commit reports
+    let outer = handle.write();
+    outer.try_lock(outer.deref(account).unwrap()).unwrap().unwrap().balance = 30;
+    handle.write().abort();
+    assert_eq!(outer.commit(), Err(RluError::Conflict));
+    assert_eq!(balance(&other, account), 20);
+
+    // every lock needs its own unlock
+    unsafe { rlu_reader_lock(rlu_ptr, handle.id()) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, handle.id()) }.unwrap();
+    unsafe { rlu_reader_unlock(rlu_ptr, handle.id()) }.unwrap();
+    unsafe { rlu_reader_unlock(rlu_ptr, handle.id()) }.unwrap();
+    assert_eq!(
+        unsafe { rlu_reader_unlock(rlu_ptr, handle.id()) },
+        Err(RluError::UnbalancedSection)
+    );
+}
+
+#[test]
+fn nested_inner_abort_fails_outer() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::init_rlu();
+    let account = new_account(10);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let id = handle.id();
+
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    let mut p_obj = account;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut p_obj) }.unwrap());
+    unsafe { (*p_obj).balance = 0 };
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    unsafe { rlu_abort(rlu_ptr, id) }.unwrap();
+
+    // the abort undid the outer section's write too, and it cannot lock anything else
+    let mut p_obj = account;
+    assert!(!unsafe { rlu_try_lock(rlu_ptr, id, &mut p_obj) }.unwrap());
+    assert_eq!(unsafe { rlu_reader_unlock(rlu_ptr, id) }, Err(RluError::Conflict));
+    assert_eq!(
+        unsafe { rlu_reader_unlock(rlu_ptr, id) },
+        Err(RluError::UnbalancedSection)
+    );
+    assert_eq!(balance(&handle, account), 10);
+}
+
+#[test]
+fn nested_conflict_retries_outer_transaction() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::init_rlu();
+    let (first, second) = (new_account(10), new_account(10));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let other = handle.clone_ref();
+
+    let mut holder = Some(other.write());
+    let guard = holder.as_ref().unwrap();
+    guard.try_lock(guard.deref(second).unwrap()).unwrap().unwrap();
+
+    let policy = CountingPolicy::default();
+    let (mut runs, mut inner_runs) = (0, 0);
+    handle
+        .transaction_with(&policy, |txn| {
+            runs += 1;
+            txn.lock(txn.deref(first).unwrap())?.balance -= 5;
+            let inner = handle.transaction(|txn| {
+                inner_runs += 1;
+                txn.lock(txn.deref(second).unwrap())?.balance += 5;
+                Ok(())
+            });
+            if inner == Err(RluError::Conflict) {
+                // committing would wait for this section, aborting lets the retry through
+                holder.take().unwrap().abort();
+            }
+            inner
+        })
+        .unwrap();
+    assert_eq!((runs, inner_runs), (2, 2));
+    assert_eq!(policy.conflicts.load(Ordering::SeqCst), 1);
+    assert_eq!(balance(&other, first), 5);
+    assert_eq!(balance(&other, second), 15);
+}
+
+#[test]
+fn nested_bptree_batch() {
+    let tree: BPlusTree<u64, u64> = BPlusTree::new();
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let tree = tree.clone_ref();
+            thread::spawn(move || {
+                for i in 0..200 {
+                    let key = i % 10;
+                    tree.batch(|tree| {
+                        let count = tree.search(&key).unwrap_or(0);
+                        tree.try_insert(key, count + 1)
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    for key in 0..10 {
+        assert_eq!(tree.search(&key), Some(80));
+    }
+}
+
+#[test]
+fn nested_set_batch() {
+    let set: RluSet<u64> = RluSet::new();
+    let moved = set.batch(|set| {
+        set.try_insert(1)?;
+        set.try_insert(2)?;
+        Ok(set.try_delete(1)? && set.contains(2))
+    });
+    assert_eq!(moved, Ok(true));
+    assert!(!set.contains(1));
+    assert_eq!(set.len(), 1);
+}
+
+// An insert in a batch that finds a node held by another writer reports the conflict rather
+// than "already present", and the batch is re-run once the other writer is done
+#[test]
+fn nested_set_batch_conflict() {
+    let set: RluSet<u64> = RluSet::new();
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+    let writer = {
+        let set = set.clone_ref();
+        thread::spawn(move || {
+            set.batch(|set| {
+                let inserted = set.try_insert(2)?;
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok(inserted)
+            })
+        })
+    };
+    locked_rx.recv().unwrap();
+
+    let mut results = Vec::new();
+    let inserted = set.batch(|set| {
+        let result = set.try_insert(1);
+        if results.is_empty() {
+            release_tx.send(()).unwrap();
+        }
+        results.push(result.clone());
+        result
+    });
+    assert_eq!(results[0], Err(RluError::Conflict));
+    assert_eq!(results.last(), Some(&Ok(true)));
+    assert_eq!(inserted, Ok(true));
+    assert_eq!(writer.join().unwrap(), Ok(true));
+    assert_eq!(set.len(), 2);
+}
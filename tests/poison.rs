@@ -0,0 +1,134 @@
+use rlu::{
+    rlu_clear_poison, rlu_destroy, rlu_is_poisoned, rlu_poison, rlu_reader_lock,
+    rlu_reader_unlock, rlu_reclaim, rlu_thread_exit, rlu_thread_init, rlu_try_lock, GlobalRlu,
+    RluError, RluHandle, RluObj, RluObjHdr, RluUnwindGuard,
+};
+use std::panic;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Shared(*mut GlobalRlu<Counter>, *mut Counter);
+
+unsafe impl Send for Shared {}
+
+fn new_counter() -> *mut Counter {
+    Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        value: 0,
+    }))
+}
+
+// Another thread can lock the counter and commit, which waits for every open section
+fn increment(handle: &RluHandle<Counter>, counter: *mut Counter) {
+    let guard = handle.write();
+    guard.try_lock(guard.deref(counter).unwrap()).unwrap().unwrap().value += 1;
+}
+
+#[test]
+fn poison_raw_section_unwinds() {
+    let shared = Shared(GlobalRlu::builder().max_threads(2).init_rlu(), new_counter());
+    let result = thread::spawn(move || {
+        let shared = shared;
+        let id = unsafe { rlu_thread_init(shared.0) }.unwrap();
+        let _unwind = unsafe { RluUnwindGuard::new(shared.0, id) };
+        unsafe { rlu_reader_lock(shared.0, id) }.unwrap();
+        unsafe { rlu_reader_lock(shared.0, id) }.unwrap();
+        let mut p_obj = shared.1;
+        assert!(unsafe { rlu_try_lock(shared.0, id, &mut p_obj) }.unwrap());
+        unsafe { (*p_obj).value = 10 };
+        panic!("writer died mid-section");
+    })
+    .join();
+    assert!(result.is_err());
+
+    // the section was aborted, and the slot is left for someone to reclaim
+    let poisoned = unsafe { (*shared.0).poisoned_threads() };
+    assert_eq!(poisoned.len(), 1);
+    let handle = unsafe { RluHandle::new(shared.0) };
+    increment(&handle, shared.1);
+    assert_eq!(handle.read().deref(shared.1).unwrap().value, 1);
+
+    assert_eq!(unsafe { rlu_reclaim(shared.0, handle.id()) }, Err(RluError::NotPoisoned));
+    // reclaiming from inside a section would wait for that section itself
+    {
+        let _guard = handle.read();
+        assert_eq!(handle.reclaim(poisoned[0]), Err(RluError::UnbalancedSection));
+    }
+    assert_eq!(unsafe { (*shared.0).poisoned_threads() }, poisoned);
+    handle.reclaim(poisoned[0]).unwrap();
+    assert!(unsafe { (*shared.0).poisoned_threads() }.is_empty());
+    assert_eq!(unsafe { rlu_reclaim(shared.0, poisoned[0]) }, Err(RluError::InvalidHandle));
+    drop(handle);
+    unsafe { rlu_destroy(shared.0) }.unwrap();
+}
+
+#[test]
+fn poison_handle_exits_on_unwind() {
+    let shared = Shared(GlobalRlu::builder().max_threads(2).init_rlu(), new_counter());
+    let handle = unsafe { RluHandle::new(shared.0) };
+    let result = thread::spawn(move || {
+        let shared = shared;
+        let handle = unsafe { RluHandle::new(shared.0) };
+        unsafe { rlu_reader_lock(shared.0, handle.id()) }.unwrap();
+        let mut p_obj = shared.1;
+        assert!(unsafe { rlu_try_lock(shared.0, handle.id(), &mut p_obj) }.unwrap());
+        panic!("writer died mid-section");
+    })
+    .join();
+    assert!(result.is_err());
+
+    // dropping the handle aborted the section and gave the slot back
+    assert!(unsafe { (*shared.0).poisoned_threads() }.is_empty());
+    increment(&handle, shared.1);
+    drop(unsafe { RluHandle::new(shared.0) });
+}
+
+#[test]
+fn poison_owner_recovers() {
+    let shared = Shared(GlobalRlu::init_rlu(), new_counter());
+    let id = unsafe { rlu_thread_init(shared.0) }.unwrap();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _unwind = unsafe { RluUnwindGuard::new(shared.0, id) };
+        unsafe { rlu_reader_lock(shared.0, id) }.unwrap();
+        panic!("caught");
+    }));
+    assert!(result.is_err());
+    assert_eq!(unsafe { rlu_is_poisoned(shared.0, id) }, Ok(true));
+    unsafe { rlu_clear_poison(shared.0, id) }.unwrap();
+    assert_eq!(unsafe { rlu_is_poisoned(shared.0, id) }, Ok(false));
+    assert_eq!(unsafe { rlu_clear_poison(shared.0, id) }, Err(RluError::NotPoisoned));
+    unsafe { rlu_reader_lock(shared.0, id) }.unwrap();
+    unsafe { rlu_reader_unlock(shared.0, id) }.unwrap();
+}
+
+// The owner clearing the poison and another thread reclaiming the slot race for it, and
+// exactly one of them gets it
+#[test]
+fn poison_clear_races_reclaim() {
+    let shared = Shared(GlobalRlu::init_rlu(), new_counter());
+    for _ in 0..200 {
+        let id = unsafe { rlu_thread_init(shared.0) }.unwrap();
+        unsafe { rlu_poison(shared.0, id) }.unwrap();
+
+        let reclaimer = thread::spawn(move || {
+            let shared = shared;
+            unsafe { rlu_reclaim(shared.0, id) }
+        });
+        let cleared = unsafe { rlu_clear_poison(shared.0, id) };
+        let reclaimed = reclaimer.join().unwrap();
+        match (cleared, reclaimed) {
+            (Ok(()), Err(RluError::NotPoisoned)) => {
+                unsafe { rlu_thread_exit(shared.0, id) }.unwrap();
+            }
+            (Err(RluError::NotPoisoned), Ok(())) | (Err(RluError::InvalidHandle), Ok(())) => {}
+            results => panic!("both or neither got the slot: {:?}", results),
+        }
+    }
+    unsafe { rlu_destroy(shared.0) }.unwrap();
+}
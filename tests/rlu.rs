@@ -1,10 +1,13 @@
 // Author: Hudson Ayers
 
 use rlu::{
-    rlu_abort, rlu_dereference, rlu_free, rlu_reader_lock, rlu_reader_unlock, rlu_thread_init,
-    rlu_try_lock, GlobalRlu, RluObj, RluObjHdr, WsHdr, PTR_ID_OBJ_COPY,
+    rlu_abort, rlu_dereference, rlu_destroy, rlu_free, rlu_reader_lock, rlu_reader_unlock,
+    rlu_thread_exit, rlu_thread_init, rlu_try_lock, GlobalRlu, RluError, RluHandle, RluMode,
+    RluObj, RluObjHdr, RluThread, RluVersion, PTR_ID_OBJ_COPY, RLU_MAX_THREADS,
 };
 use std::mem;
+use std::ops::Range;
+use std::panic;
 use std::ptr;
 use std::sync::atomic::{AtomicPtr, Ordering};
 use std::thread;
@@ -30,20 +33,23 @@ unsafe impl Sync for RluInt {}
 impl RluObj for RluInt {
     fn copy_back_to_original(&self) {
         assert!(self.has_ws_hdr());
-        self.hdr.ws_hdr.as_ref().map(|hdr| unsafe {
-            (*hdr.p_obj_actual)
-                .hdr
-                .p_obj_copy
-                .store(ptr::null_mut(), Ordering::Relaxed);
-            (*hdr.p_obj_actual).data = self.data;
-        });
+        if let Some(hdr) = self.hdr.ws_hdr.as_ref() {
+            unsafe {
+                (*hdr.p_obj_actual).data = self.data;
+                //unlocking publishes the data written back to readers that find it unlocked
+                (*hdr.p_obj_actual)
+                    .hdr
+                    .p_obj_copy
+                    .store(ptr::null_mut(), Ordering::Release);
+            }
+        }
     }
     fn unlock(&self) {
         assert!(!self.has_ws_hdr());
         assert!(self.is_locked());
         self.hdr
             .p_obj_copy
-            .store(ptr::null_mut(), Ordering::Relaxed);
+            .store(ptr::null_mut(), Ordering::Release);
     }
     fn unlock_original(&self) {
         unsafe {
@@ -51,13 +57,13 @@ impl RluObj for RluInt {
         }
     }
     fn get_p_obj_copy(&self) -> *mut Self {
-        self.hdr.p_obj_copy.load(Ordering::Relaxed)
+        self.hdr.p_obj_copy.load(Ordering::Acquire)
     }
     fn is_locked(&self) -> bool {
         !self.get_p_obj_copy().is_null()
     }
     fn is_copy(&self) -> bool {
-        unsafe { self.get_p_obj_copy() == mem::transmute(PTR_ID_OBJ_COPY) }
+        self.get_p_obj_copy() == PTR_ID_OBJ_COPY as *mut Self
     }
     fn has_ws_hdr(&self) -> bool {
         self.hdr.ws_hdr.is_some()
@@ -79,23 +85,24 @@ impl RluObj for RluInt {
         self.hdr.ws_hdr.as_ref().map(|hdr| hdr.run_counter).unwrap()
     }
     fn get_copy_with_ws_hdr(&self, run_counter: u64, thread_id: usize) -> Self {
+        let p_obj_actual = if self.has_ws_hdr() {
+            self.get_p_original()
+        } else {
+            self as *const Self as *mut Self
+        };
         RluInt {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(unsafe { mem::transmute(PTR_ID_OBJ_COPY) }),
-                ws_hdr: Some(WsHdr {
-                    p_obj_actual: unsafe { mem::transmute(self) },
-                    run_counter: run_counter,
-                    thread_id: thread_id,
-                }),
-            },
-            data: 0,
+            hdr: RluObjHdr::new_copy(p_obj_actual, run_counter, thread_id),
+            data: self.data,
         }
     }
     fn cas(&self, new_obj: *mut Self) -> bool {
         self.hdr
             .p_obj_copy
-            .compare_and_swap(ptr::null_mut(), new_obj, Ordering::Relaxed)
-            .is_null()
+            .compare_exchange(ptr::null_mut(), new_obj, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+    fn get_version_chain(&self) -> &AtomicPtr<RluVersion<Self>> {
+        &self.hdr.p_version
     }
 }
 
@@ -104,65 +111,59 @@ fn rlu_single_thread() {
     let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::init_rlu();
     assert!(!rlu_ptr.is_null());
     let obj = Box::into_raw(Box::new(RluInt {
-        hdr: RluObjHdr {
-            p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-            ws_hdr: None,
-        },
+        hdr: RluObjHdr::new(),
         data: 2,
     }));
-    let thread_id = rlu_thread_init(rlu_ptr);
+    let thread_id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
 
-    rlu_reader_lock(rlu_ptr, thread_id);
-    let mut obj1 = rlu_dereference(rlu_ptr, thread_id, obj);
+    unsafe { rlu_reader_lock(rlu_ptr, thread_id) }.unwrap();
+    let mut obj1 = unsafe { rlu_dereference(rlu_ptr, thread_id, obj) }.unwrap();
     assert!(obj1 == obj); //should return same pointer for unmodified object
     unsafe {
         assert!((*obj1).data == 2);
     }
 
-    assert!(rlu_try_lock(rlu_ptr, thread_id, &mut obj1));
+    assert!(unsafe { rlu_try_lock(rlu_ptr, thread_id, &mut obj1) }.unwrap());
     assert!(obj1 != obj); //locking should return pointer to object in write log
     unsafe {
         (*obj1).data = 5;
     }
     assert!(unsafe { (*obj).data == 2 }); //havent written back yet!
-    rlu_reader_unlock(rlu_ptr, thread_id);
+    unsafe { rlu_reader_unlock(rlu_ptr, thread_id) }.unwrap();
     assert!(unsafe { (*obj).data == 5 }); //have written back!
 
-    rlu_reader_lock(rlu_ptr, thread_id);
-    let obj2 = rlu_dereference(rlu_ptr, thread_id, obj);
+    unsafe { rlu_reader_lock(rlu_ptr, thread_id) }.unwrap();
+    let obj2 = unsafe { rlu_dereference(rlu_ptr, thread_id, obj) }.unwrap();
     assert!(obj2 == obj); //after reader_unlock() writeback should have occurred
     unsafe {
         assert!((*obj2).data == 5);
     }
-    rlu_reader_unlock(rlu_ptr, thread_id);
+    unsafe { rlu_reader_unlock(rlu_ptr, thread_id) }.unwrap();
 
-    rlu_reader_lock(rlu_ptr, thread_id);
-    let mut obj3 = rlu_dereference(rlu_ptr, thread_id, obj);
-    assert!(rlu_try_lock(rlu_ptr, thread_id, &mut obj3));
+    unsafe { rlu_reader_lock(rlu_ptr, thread_id) }.unwrap();
+    let mut obj3 = unsafe { rlu_dereference(rlu_ptr, thread_id, obj) }.unwrap();
+    assert!(unsafe { rlu_try_lock(rlu_ptr, thread_id, &mut obj3) }.unwrap());
     unsafe {
         (*obj3).data = 6;
     }
-    rlu_reader_unlock(rlu_ptr, thread_id);
+    unsafe { rlu_reader_unlock(rlu_ptr, thread_id) }.unwrap();
 
-    rlu_reader_lock(rlu_ptr, thread_id);
-    let obj4 = rlu_dereference(rlu_ptr, thread_id, obj);
+    unsafe { rlu_reader_lock(rlu_ptr, thread_id) }.unwrap();
+    let obj4 = unsafe { rlu_dereference(rlu_ptr, thread_id, obj) }.unwrap();
     unsafe {
         assert!((*obj4).data == 6);
     }
     unsafe {
-        rlu_free(rlu_ptr, thread_id, obj3);
+        rlu_free(rlu_ptr, thread_id, obj3).unwrap();
     }
-    rlu_reader_unlock(rlu_ptr, thread_id);
+    unsafe { rlu_reader_unlock(rlu_ptr, thread_id) }.unwrap();
 }
 
 #[test]
 fn rlu_two_thread() {
     let obj_wrap = RluIntWrapper {
         obj: Box::into_raw(Box::new(RluInt {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                ws_hdr: None,
-            },
+            hdr: RluObjHdr::new(),
             data: 2,
         })),
         rlu: GlobalRlu::init_rlu(),
@@ -171,32 +172,32 @@ fn rlu_two_thread() {
     let reader = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        let id = rlu_thread_init(rlu).unwrap();
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         let before = (*obj1).data;
         thread::sleep(time::Duration::from_millis(100));
         assert!((*obj1).data == before);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
 
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         assert!((*obj1).data == 5);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer = thread::spawn(move || {
         unsafe {
             let obj = obj_wrap.obj;
             let rlu = obj_wrap.rlu;
-            let id = rlu_thread_init(rlu);
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
+            let id = rlu_thread_init(rlu).unwrap();
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
             assert!((*obj2).data == 2);
-            assert!(rlu_try_lock(rlu, id, &mut obj2)); //shouldn't fail with no other writers!
+            assert!(rlu_try_lock(rlu, id, &mut obj2).unwrap()); //shouldn't fail with no other writers!
             (*obj2).data = 5;
             assert!((*obj2).data == 5);
-            rlu_reader_unlock(rlu, id);
+            rlu_reader_unlock(rlu, id).unwrap();
         }
     });
 
@@ -208,10 +209,7 @@ fn rlu_two_thread() {
 fn rlu_lock_contend() {
     let obj_wrap = RluIntWrapper {
         obj: Box::into_raw(Box::new(RluInt {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                ws_hdr: None,
-            },
+            hdr: RluObjHdr::new(),
             data: 2,
         })),
         rlu: GlobalRlu::init_rlu(),
@@ -220,54 +218,54 @@ fn rlu_lock_contend() {
     let reader = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        let id = rlu_thread_init(rlu).unwrap();
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         let before = (*obj1).data;
         thread::sleep(time::Duration::from_millis(100));
         assert!((*obj1).data == before);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
 
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         assert!((*obj1).data == 5 || (*obj1).data == 6);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer1 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 5;
             assert!((*obj2).data == 5);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer2 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 6;
             assert!((*obj2).data == 6);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     reader.join().unwrap();
@@ -279,10 +277,7 @@ fn rlu_lock_contend() {
 fn rlu_thread_contend_more() {
     let obj_wrap = RluIntWrapper {
         obj: Box::into_raw(Box::new(RluInt {
-            hdr: RluObjHdr {
-                p_obj_copy: AtomicPtr::new(ptr::null_mut()),
-                ws_hdr: None,
-            },
+            hdr: RluObjHdr::new(),
             data: 2,
         })),
         rlu: GlobalRlu::init_rlu(),
@@ -291,90 +286,90 @@ fn rlu_thread_contend_more() {
     let reader = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        let id = rlu_thread_init(rlu).unwrap();
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         let before = (*obj1).data;
         thread::sleep(time::Duration::from_millis(100));
         assert!((*obj1).data == before);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
 
-        rlu_reader_lock(rlu, id);
-        let obj1 = rlu_dereference(rlu, id, obj);
+        rlu_reader_lock(rlu, id).unwrap();
+        let obj1 = rlu_dereference(rlu, id, obj).unwrap();
         assert!((*obj1).data == 5 || (*obj1).data == 6 || (*obj1).data == 7 || (*obj1).data == 8);
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer1 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 5;
             assert!((*obj2).data == 5);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer2 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 6;
             assert!((*obj2).data == 6);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer3 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 7;
             assert!((*obj2).data == 7);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     let writer4 = thread::spawn(move || unsafe {
         let obj = obj_wrap.obj;
         let rlu = obj_wrap.rlu;
-        let id = rlu_thread_init(rlu);
+        let id = rlu_thread_init(rlu).unwrap();
         loop {
-            rlu_reader_lock(rlu, id);
-            let mut obj2 = rlu_dereference(rlu, id, obj);
-            if !rlu_try_lock(rlu, id, &mut obj2) {
-                rlu_abort(rlu, id);
+            rlu_reader_lock(rlu, id).unwrap();
+            let mut obj2 = rlu_dereference(rlu, id, obj).unwrap();
+            if !rlu_try_lock(rlu, id, &mut obj2).unwrap() {
+                rlu_abort(rlu, id).unwrap();
                 continue;
             }
             (*obj2).data = 8;
             assert!((*obj2).data == 8);
             break;
         }
-        rlu_reader_unlock(rlu, id);
+        rlu_reader_unlock(rlu, id).unwrap();
     });
 
     reader.join().unwrap();
@@ -383,3 +378,383 @@ fn rlu_thread_contend_more() {
     writer3.join().unwrap();
     writer4.join().unwrap();
 }
+
+#[test]
+fn rlu_mv_snapshot() {
+    // Both ids live on this OS thread: in single-version mode the writer's commit would
+    // spin forever waiting for the open reader section
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::init_rlu_with_mode(RluMode::MultiVersion);
+    let obj = Box::into_raw(Box::new(RluInt {
+        hdr: RluObjHdr::new(),
+        data: 2,
+    }));
+    let reader = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let writer = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, reader) }.unwrap();
+    let old = unsafe { rlu_dereference(rlu_ptr, reader, obj) }.unwrap();
+    unsafe {
+        assert!((*old).data == 2);
+    }
+
+    for value in 5..8 {
+        unsafe { rlu_reader_lock(rlu_ptr, writer) }.unwrap();
+        let mut copy = unsafe { rlu_dereference(rlu_ptr, writer, obj) }.unwrap();
+        assert!(unsafe { rlu_try_lock(rlu_ptr, writer, &mut copy) }.unwrap());
+        unsafe {
+            (*copy).data += value - 4; //builds on the previous version
+        }
+        unsafe { rlu_reader_unlock(rlu_ptr, writer) }.unwrap(); //must not wait for the reader
+    }
+
+    let again = unsafe { rlu_dereference(rlu_ptr, reader, obj) }.unwrap();
+    unsafe {
+        assert!((*old).data == 2);
+        assert!((*again).data == 2); //still in the old snapshot
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, reader) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, reader) }.unwrap();
+    let new = unsafe { rlu_dereference(rlu_ptr, reader, obj) }.unwrap();
+    unsafe {
+        assert!((*new).data == 8);
+        assert!((*obj).data == 2); //originals are never written back in MV mode
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, reader) }.unwrap();
+}
+
+#[test]
+fn rlu_mv_contend() {
+    let obj_wrap = RluIntWrapper {
+        obj: Box::into_raw(Box::new(RluInt {
+            hdr: RluObjHdr::new(),
+            data: 0,
+        })),
+        rlu: GlobalRlu::init_rlu_with_mode(RluMode::MultiVersion),
+    };
+
+    let writer = move || {
+        thread::spawn(move || unsafe {
+            let obj = obj_wrap.obj;
+            let rlu = obj_wrap.rlu;
+            let id = rlu_thread_init(rlu).unwrap();
+            for _ in 0..1000 {
+                loop {
+                    rlu_reader_lock(rlu, id).unwrap();
+                    let mut copy = rlu_dereference(rlu, id, obj).unwrap();
+                    if !rlu_try_lock(rlu, id, &mut copy).unwrap() {
+                        rlu_abort(rlu, id).unwrap();
+                        continue;
+                    }
+                    (*copy).data += 1;
+                    break;
+                }
+                rlu_reader_unlock(rlu, id).unwrap();
+            }
+        })
+    };
+    let reader = move || {
+        thread::spawn(move || unsafe {
+            let obj = obj_wrap.obj;
+            let rlu = obj_wrap.rlu;
+            let id = rlu_thread_init(rlu).unwrap();
+            let mut last = 0;
+            for _ in 0..1000 {
+                rlu_reader_lock(rlu, id).unwrap();
+                let first = (*rlu_dereference(rlu, id, obj).unwrap()).data;
+                let second = (*rlu_dereference(rlu, id, obj).unwrap()).data;
+                assert!(first == second); //snapshot is stable within a section
+                assert!(first >= last); //and never goes back in time
+                last = first;
+                rlu_reader_unlock(rlu, id).unwrap();
+            }
+        })
+    };
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| writer())
+        .chain((0..4).map(|_| reader()))
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let id = unsafe { rlu_thread_init(obj_wrap.rlu) }.unwrap();
+    unsafe { rlu_reader_lock(obj_wrap.rlu, id) }.unwrap();
+    let total = unsafe { (*rlu_dereference(obj_wrap.rlu, id, obj_wrap.obj).unwrap()).data };
+    unsafe { rlu_reader_unlock(obj_wrap.rlu, id) }.unwrap();
+    assert!(total == 4000);
+}
+
+#[test]
+fn rlu_guards() {
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::init_rlu();
+    let obj = Box::into_raw(Box::new(RluInt {
+        hdr: RluObjHdr::new(),
+        data: 2,
+    }));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    {
+        let guard = handle.write();
+        let mut copy = guard.try_lock(guard.deref(obj).unwrap()).unwrap().unwrap();
+        copy.data = 5;
+        assert!(unsafe { (*obj).data == 2 }); //havent written back yet!
+    } //dropping the guard commits
+    assert!(unsafe { (*obj).data == 5 });
+
+    let guard = handle.write();
+    let mut copy = guard.try_lock(guard.deref(obj).unwrap()).unwrap().unwrap();
+    copy.data = 6;
+    drop(copy);
+    guard.abort();
+    assert!(handle.read().deref(obj).unwrap().data == 5);
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let guard = handle.write();
+        let mut copy = guard.try_lock(guard.deref(obj).unwrap()).unwrap().unwrap();
+        copy.data = 7;
+        panic!("writer died mid-section");
+    }));
+    assert!(result.is_err());
+    let guard = handle.read();
+    assert!(guard.deref(obj).unwrap().data == 5); //the panicking section was aborted
+    assert!(!guard.deref(obj).unwrap().is_locked());
+}
+
+#[test]
+fn rlu_thread_recycle() {
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::init_rlu();
+    let obj = Box::into_raw(Box::new(RluInt {
+        hdr: RluObjHdr::new(),
+        data: 0,
+    }));
+    let ids: Vec<_> = (0..RLU_MAX_THREADS)
+        .map(|_| unsafe { rlu_thread_init(rlu_ptr) }.unwrap())
+        .collect();
+
+    unsafe { rlu_reader_lock(rlu_ptr, ids[3]) }.unwrap();
+    let mut copy = unsafe { rlu_dereference(rlu_ptr, ids[3], obj) }.unwrap();
+    assert!(unsafe { rlu_try_lock(rlu_ptr, ids[3], &mut copy) }.unwrap());
+    unsafe {
+        (*copy).data = 1;
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, ids[3]) }.unwrap();
+    unsafe { rlu_thread_exit(rlu_ptr, ids[3]) }.unwrap();
+
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap(); //all other slots are taken
+    assert!(id == ids[3]);
+    for _ in 0..100 {
+        unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+        let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+        assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+        unsafe {
+            (*copy).data += 1;
+        }
+        unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+        unsafe { rlu_thread_exit(rlu_ptr, id) }.unwrap();
+        assert!(unsafe { rlu_thread_init(rlu_ptr) }.unwrap() == id);
+    }
+    assert!(unsafe { (*obj).data } == 101);
+}
+
+#[test]
+fn rlu_builder_capacities() {
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::builder()
+        .max_threads(64)
+        .log_size(512)
+        .max_free_nodes(200)
+        .init_rlu();
+    let objs: Vec<_> = (0..200)
+        .map(|i| {
+            Box::into_raw(Box::new(RluInt {
+                hdr: RluObjHdr::new(),
+                data: i,
+            }))
+        })
+        .collect();
+    let ids: Vec<_> = (0..64).map(|_| unsafe { rlu_thread_init(rlu_ptr) }.unwrap()).collect();
+    assert!(unsafe { (*rlu_ptr).max_threads() } == 64);
+    let id = ids[63];
+
+    //more objects in one section than fit in half of the default write log
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    for &obj in objs.iter() {
+        let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+        assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+        unsafe {
+            (*copy).data += 1000;
+        }
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+    for (i, &obj) in objs.iter().enumerate() {
+        assert!(unsafe { (*obj).data } == i as u64 + 1000);
+    }
+
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    for &obj in objs.iter() {
+        let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+        assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+        unsafe {
+            rlu_free(rlu_ptr, id, copy).unwrap();
+        }
+    }
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
+
+#[test]
+fn rlu_log_growth() {
+    for &mode in [RluMode::SingleVersion, RluMode::MultiVersion].iter() {
+        let rlu_ptr: *mut GlobalRlu<RluInt> =
+            GlobalRlu::builder().mode(mode).log_size(4).init_rlu();
+        let objs: Vec<_> = (0..100)
+            .map(|i| {
+                Box::into_raw(Box::new(RluInt {
+                    hdr: RluObjHdr::new(),
+                    data: i,
+                }))
+            })
+            .collect();
+        let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+
+        for round in 1..4 {
+            unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+            for &obj in objs.iter() {
+                let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+                //grows instead of overflowing
+                assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+                unsafe {
+                    (*copy).data += 1000;
+                }
+            }
+            unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+
+            unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+            for (i, &obj) in objs.iter().enumerate() {
+                let data = unsafe { (*rlu_dereference(rlu_ptr, id, obj).unwrap()).data };
+                assert!(data == i as u64 + round * 1000);
+            }
+            unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+        }
+
+        //a bulk delete frees far more than the initial free list capacity
+        unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+        for &obj in objs.iter() {
+            let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+            assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+            unsafe {
+                rlu_free(rlu_ptr, id, copy).unwrap();
+            }
+        }
+        unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+        unsafe { rlu_thread_exit(rlu_ptr, id) }.unwrap();
+    }
+}
+
+#[test]
+fn rlu_capacity_errors() {
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::builder()
+        .max_log_size(8)
+        .max_free_nodes(2)
+        .init_rlu();
+    let objs: Vec<_> = (0..5)
+        .map(|i| {
+            Box::into_raw(Box::new(RluInt {
+                hdr: RluObjHdr::new(),
+                data: i,
+            }))
+        })
+        .collect();
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    let mut copies = Vec::new();
+    for &obj in objs[..4].iter() {
+        let mut copy = unsafe { rlu_dereference(rlu_ptr, id, obj) }.unwrap();
+        assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) }.unwrap());
+        copies.push(copy);
+    }
+    let mut copy = unsafe { rlu_dereference(rlu_ptr, id, objs[4]) }.unwrap();
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut copy) } == Err(RluError::WriteLogFull));
+    unsafe {
+        rlu_free(rlu_ptr, id, copies[0]).unwrap();
+        rlu_free(rlu_ptr, id, copies[1]).unwrap();
+        assert!(rlu_free(rlu_ptr, id, copies[2]) == Err(RluError::FreeListFull));
+    }
+    unsafe { rlu_abort(rlu_ptr, id) }.unwrap();
+    for &obj in objs.iter() {
+        assert!(unsafe { !(*obj).is_locked() }); //the section can still be aborted cleanly
+    }
+}
+
+#[test]
+fn rlu_errors() {
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::builder().max_threads(2).init_rlu();
+    let obj = Box::into_raw(Box::new(RluInt {
+        hdr: RluObjHdr::new(),
+        data: 2,
+    }));
+    let id = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    let other = unsafe { rlu_thread_init(rlu_ptr) }.unwrap();
+    assert!(unsafe { rlu_thread_init(rlu_ptr) } == Err(RluError::ThreadSlotsExhausted));
+
+    assert!(unsafe { rlu_reader_unlock(rlu_ptr, id) } == Err(RluError::UnbalancedSection));
+    assert!(unsafe { rlu_abort(rlu_ptr, id) } == Err(RluError::UnbalancedSection));
+    let mut p_obj = obj;
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut p_obj) } == Err(RluError::UnbalancedSection));
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap(); //nests, see tests/nested.rs
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+    assert!(unsafe { rlu_thread_exit(rlu_ptr, id) } == Err(RluError::UnbalancedSection));
+    let mut p_null = ptr::null_mut();
+    assert!(unsafe { rlu_try_lock(rlu_ptr, id, &mut p_null) } == Err(RluError::NullObject));
+    assert!(unsafe { rlu_free(rlu_ptr, id, obj) } == Err(RluError::NotLocked));
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+
+    unsafe { rlu_thread_exit(rlu_ptr, other) }.unwrap();
+    assert!(unsafe { rlu_reader_lock(rlu_ptr, other) } == Err(RluError::InvalidHandle));
+    assert!(unsafe { rlu_dereference(rlu_ptr, 7, obj) } == Err(RluError::InvalidHandle));
+
+    //a lock pointing at a copy made by a thread that doesn't exist
+    let bogus = Box::into_raw(Box::new(RluInt {
+        hdr: RluObjHdr::new_copy(obj, 1, 7),
+        data: 3,
+    }));
+    unsafe {
+        (*obj).hdr.p_obj_copy.store(bogus, Ordering::SeqCst);
+    }
+    unsafe { rlu_reader_lock(rlu_ptr, id) }.unwrap();
+    assert!(unsafe { rlu_dereference(rlu_ptr, id, obj) } == Err(RluError::CorruptedHeader));
+    unsafe { rlu_reader_unlock(rlu_ptr, id) }.unwrap();
+}
+
+// Each thread's shared counters start a fresh pair of cache lines (see sharing_bench)
+#[test]
+fn rlu_thread_layout() {
+    assert_eq!(mem::align_of::<RluThread<RluInt>>(), 128);
+    assert_eq!(mem::size_of::<RluThread<RluInt>>() % 128, 0);
+    assert_eq!(mem::align_of::<GlobalRlu<RluInt>>(), 128);
+
+    // checked on real addresses, for threads registered one after the other
+    let rlu_ptr: *mut GlobalRlu<RluInt> = GlobalRlu::builder().max_threads(4).init_rlu();
+    let ids: Vec<usize> = (0..4).map(|_| unsafe { rlu_thread_init(rlu_ptr) }.unwrap()).collect();
+    let lines = |range: Range<usize>| (range.start / 128, (range.end - 1) / 128);
+    let threads = unsafe { &(*rlu_ptr).threads };
+    let thread = |id: usize| threads[id].as_ref().unwrap();
+    for pair in ids.windows(2) {
+        let (first, second) = (
+            lines(thread(pair[0]).shared_counters()),
+            lines(thread(pair[1]).shared_counters()),
+        );
+        assert!(first.1 < second.0 || second.1 < first.0);
+    }
+    for &id in &ids {
+        let (start, end) = lines(thread(id).shared_counters());
+        let wlog = &thread(id).wlog as *const _ as usize / 128;
+        assert!(wlog < start || wlog > end);
+    }
+    for id in ids {
+        unsafe { rlu_thread_exit(rlu_ptr, id) }.unwrap();
+    }
+    unsafe { rlu_destroy(rlu_ptr) }.unwrap();
+}
@@ -5,12 +5,21 @@ mod tests {
     use std::thread;
     use rand::thread_rng;
     use rand::Rng;
-    use rlu::BPlusTree;
+    use rlu::{BPlusTree, RluMode};
 
     #[test]
     fn test_rlu_bplus_tree() {
+        bplus_tree_inserts(RluMode::SingleVersion);
+    }
+
+    #[test]
+    fn test_rlu_mv_bplus_tree() {
+        bplus_tree_inserts(RluMode::MultiVersion);
+    }
+
+    fn bplus_tree_inserts(mode: RluMode) {
         // Create a new BPlusTree
-        let mut bptree = BPlusTree::<i32, char>::new();
+        let bptree = BPlusTree::<i32, char>::with_mode(mode);
 
         // Insert some key-value pairs
         bptree.insert(10, 'a');
@@ -67,6 +76,8 @@ mod tests {
         // assert_eq!(bptree.search(&72), Some('p'));
 
         bptree.debug_print_tree();
+        // every node points back at its parent, see validate_tree_structure
+        assert_eq!(bptree.validate_tree_structure(), Ok(()));
         // Vec::new(bptree.range_search(&5, &60));
         // assert_eq!(Vec::from(bptree.range_search(&5, &60)), vec![(5, 'h'), (10, 'a'), (15, 'c'), (17, 'j'), (18, 'k'), (20, 'b'), (25, 'd'), (32, 'l'), (35, 'e'), (40, 'f'), (45, 'g'), (60, 'i')]);
     }
@@ -76,7 +87,7 @@ mod tests {
         let tree = BPlusTree::new();
 
         let writer = || {
-            let mut tree = tree.clone_ref();
+            let tree = tree.clone_ref();
             thread::spawn(move || {
                 let mut rng = thread_rng();
                 for _ in 0..10 {
@@ -100,4 +111,45 @@ mod tests {
 
     }
 
+    fn concurrent_disjoint_inserts(mode: RluMode) {
+        let tree = BPlusTree::with_mode(mode);
+        let barrier = Arc::new(Barrier::new(4));
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let tree = tree.clone_ref();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    for i in 0..200 {
+                        let key = i * 4 + t;
+                        tree.insert(key, key * 10);
+                    }
+                })
+            })
+            .collect();
+
+        for t in writers {
+            t.join().unwrap();
+        }
+
+        assert_eq!(tree.validate_tree_structure(), Ok(()));
+        for key in 0..800 {
+            assert_eq!(tree.search(&key), Some(key * 10));
+        }
+        let all = tree.range_search(&0, &800);
+        assert_eq!(all.len(), 800);
+        assert!(all.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn bptree_concurrent_splits() {
+        concurrent_disjoint_inserts(RluMode::SingleVersion);
+    }
+
+    #[test]
+    fn bptree_mv_concurrent_splits() {
+        concurrent_disjoint_inserts(RluMode::MultiVersion);
+    }
+
 }
@@ -2,7 +2,7 @@
 
 extern crate rand;
 
-use rlu::{ConcurrentSet, RluSet};
+use rlu::{ConcurrentSet, GlobalRlu, RluMode, RluSet};
 use std::thread;
 
 use rand::{random, thread_rng, Rng};
@@ -22,11 +22,11 @@ fn set_simple() {
     assert!(!set.contains(0));
     assert!(!set.delete(0));
     assert!(set.insert(2));
-    println!("Ins 0: {}", set.to_string());
+    println!("Ins 0: {}", set);
 
     assert!(set.insert(0));
     assert!(set.insert(1));
-    println!("Ins 1: {}", set.to_string());
+    println!("Ins 1: {}", set);
 
     for i in 0..=2 {
         assert!(set.contains(i));
@@ -36,7 +36,7 @@ fn set_simple() {
     println!("Contains");
 
     assert!(set.delete(1));
-    println!("Del 1: {}", set.to_string());
+    println!("Del 1: {}", set);
 
     assert!(!set.contains(1));
 
@@ -44,7 +44,7 @@ fn set_simple() {
     assert!(!set.contains(0));
 
     assert!(set.delete(2));
-    println!("Del 2: {}", set.to_string());
+    println!("Del 2: {}", set);
 }
 
 #[test]
@@ -121,3 +121,125 @@ fn set_mini_thread() {
         t.join().unwrap();
     }
 }
+
+#[test]
+fn set_mv_thread() {
+    let set = RluSet::with_mode(RluMode::MultiVersion);
+
+    for i in 0..1000 {
+        assert!(set.insert(i));
+    }
+
+    let reader = || {
+        let set = set.clone_ref();
+        thread::spawn(move || {
+            let mut rng = thread_rng();
+
+            for _ in 0..10000 {
+                let i = rng.gen_range(0, 500) * 2;
+                assert!(set.contains(i));
+            }
+        })
+    };
+
+    let writer = || {
+        let set = set.clone_ref();
+        thread::spawn(move || {
+            let mut rng = thread_rng();
+
+            for _ in 0..1000 {
+                let i = rng.gen_range(0, 499) * 2 + 1;
+                if random() {
+                    set.insert(i);
+                } else {
+                    set.delete(i);
+                }
+            }
+        })
+    };
+
+    let readers: Vec<_> = (0..8).map(|_| reader()).collect();
+    let writers: Vec<_> = (0..4).map(|_| writer()).collect();
+
+    for t in readers {
+        t.join().unwrap();
+    }
+
+    for t in writers {
+        t.join().unwrap();
+    }
+
+    for i in 0..500 {
+        assert!(set.contains(i * 2));
+    }
+}
+
+#[test]
+fn set_recycled_clones() {
+    let set = RluSet::new();
+
+    // 200 handles over the lifetime of the set, far more than there are thread slots
+    for round in 0..50 {
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let set = set.clone_ref();
+                thread::spawn(move || {
+                    assert!(set.insert(round * 4 + t));
+                    assert!(set.contains(round * 4 + t));
+                })
+            })
+            .collect();
+        for t in writers {
+            t.join().unwrap();
+        }
+    }
+
+    for i in 0..200 {
+        assert!(set.contains(i));
+    }
+}
+
+#[test]
+fn set_many_threads() {
+    let set = RluSet::with_builder(GlobalRlu::builder().max_threads(48));
+
+    let writers: Vec<_> = (0..40)
+        .map(|t| {
+            let set = set.clone_ref();
+            thread::spawn(move || {
+                for i in 0..10 {
+                    assert!(set.insert(i * 40 + t));
+                }
+            })
+        })
+        .collect();
+    for t in writers {
+        t.join().unwrap();
+    }
+
+    for i in 0..(10 * 40) {
+        assert!(set.contains(i));
+    }
+}
+
+#[test]
+fn set_deferred_inserts() {
+    let set = RluSet::with_builder(GlobalRlu::builder().defer_commits(8));
+
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let set = set.clone_ref();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    assert!(set.insert(i * 4 + t));
+                    assert!(set.contains(i * 4 + t)); //a writer sees its own deferred inserts
+                }
+            })
+        })
+        .collect();
+    for t in writers {
+        t.join().unwrap();
+    }
+
+    assert_eq!(set.len(), 1000);
+}
@@ -0,0 +1,125 @@
+// Run with `cargo test --features stats`
+#![cfg(feature = "stats")]
+
+use rlu::{GlobalRlu, RluHandle, RluMode, RluObj, RluObjHdr};
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    count: u64,
+}
+
+#[test]
+fn stats_count_sections() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu();
+    let obj = Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        count: 0,
+    }));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let other = handle.clone_ref();
+
+    drop(handle.read());
+    {
+        let guard = handle.write();
+        let counter = guard.deref(obj).unwrap();
+        guard.try_lock(counter).unwrap().unwrap().count += 1;
+    }
+    {
+        // the other thread runs into the lock of a section that is still open
+        let guard = handle.write();
+        let counter = guard.deref(obj).unwrap();
+        guard.try_lock(counter).unwrap().unwrap();
+        let other_guard = other.write();
+        let counter = other_guard.deref(obj).unwrap();
+        assert!(other_guard.try_lock(counter).unwrap().is_none());
+        other_guard.abort();
+        guard.abort();
+    }
+    {
+        let guard = handle.write();
+        let counter = guard.deref(obj).unwrap();
+        let mut locked = guard.try_lock(counter).unwrap().unwrap();
+        locked.count += 1;
+        guard.free(locked).unwrap();
+    }
+
+    let stats = unsafe { (*rlu_ptr).stats() };
+    assert_eq!(stats.reader_sections, 5);
+    assert_eq!(stats.writer_commits, 2);
+    assert_eq!(stats.try_lock_failures, 1);
+    assert_eq!(stats.aborts, 2);
+    assert_eq!(stats.objects_freed, 1);
+    let mine = unsafe { (*rlu_ptr).thread_stats(handle.id()) }.unwrap();
+    assert_eq!(mine.reader_sections, 4);
+    assert_eq!(mine.aborts, 1);
+    assert!(unsafe { (*rlu_ptr).thread_stats(1000) }.is_none());
+}
+
+#[test]
+fn stats_count_deferred_commits() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::builder().defer_commits(4).init_rlu();
+    let objs = [(); 3].map(|_| {
+        Box::into_raw(Box::new(Counter {
+            hdr: RluObjHdr::new(),
+            count: 0,
+        }))
+    });
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    for obj in objs {
+        let guard = handle.write();
+        let counter = guard.deref(obj).unwrap();
+        guard.try_lock(counter).unwrap().unwrap().count += 1;
+    }
+    // the deferred sections commit once, together
+    assert_eq!(unsafe { (*rlu_ptr).stats() }.writer_commits, 0);
+    handle.flush();
+    let stats = unsafe { (*rlu_ptr).stats() };
+    assert_eq!(stats.reader_sections, 3);
+    assert_eq!(stats.writer_commits, 1);
+}
+
+#[derive(Copy, Clone)]
+struct CounterPtr(*mut Counter);
+
+unsafe impl Send for CounterPtr {}
+
+#[test]
+fn stats_concurrent_increments() {
+    let rlu_ptr: *mut GlobalRlu<Counter> = GlobalRlu::init_rlu_with_mode(RluMode::MultiVersion);
+    let obj = CounterPtr(Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        count: 0,
+    })));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let mut workers = vec![];
+    for _ in 0..4 {
+        let handle = handle.clone_ref();
+        workers.push(thread::spawn(move || {
+            let obj = obj;
+            let mut done = 0;
+            while done < 500 {
+                let guard = handle.write();
+                let counter = guard.deref(obj.0).unwrap();
+                let incremented = guard.try_lock(counter).unwrap().map(|mut locked| {
+                    locked.count += 1;
+                });
+                match incremented {
+                    Some(()) => done += 1,
+                    None => guard.abort(),
+                }
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // every failed try_lock was aborted and retried in a new section
+    let stats = unsafe { (*rlu_ptr).stats() };
+    assert_eq!(stats.writer_commits, 2000);
+    assert_eq!(stats.try_lock_failures, stats.aborts);
+    assert_eq!(stats.reader_sections, 2000 + stats.aborts);
+}
@@ -0,0 +1,140 @@
+// Counts live allocations, so everything runs in a single test: a second test running in
+// parallel would throw the numbers off.
+
+use rlu::{
+    rlu_destroy, BPlusTree, ConcurrentSet, GlobalRlu, RluCell, RluError, RluHandle, RluMode,
+    RluObj, RluObjHdr, RluSet,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::thread;
+
+struct CountingAlloc;
+
+static LIVE_BYTES: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[derive(RluObj)]
+pub struct Item {
+    hdr: RluObjHdr<Item>,
+    name: String,
+}
+
+fn live_bytes() -> isize {
+    LIVE_BYTES.load(Ordering::SeqCst)
+}
+
+fn build_set(mode: RluMode) {
+    let set = RluSet::with_mode(mode);
+    let mut workers = vec![];
+    for t in 0..3 {
+        let set = set.clone_ref();
+        workers.push(thread::spawn(move || {
+            for i in 0..100 {
+                set.insert(t * 100 + i);
+            }
+            for i in 0..50 {
+                set.delete(t * 100 + i * 2);
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(set.len(), 150);
+}
+
+fn build_tree(mode: RluMode) {
+    let tree = BPlusTree::with_mode(mode);
+    let mut workers = vec![];
+    for t in 0..3 {
+        let tree = tree.clone_ref();
+        workers.push(thread::spawn(move || {
+            for i in 0..200 {
+                tree.insert(t * 1000 + i, i);
+            }
+        }));
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    tree.insert(5, 6); //an update, which leaves a version behind in MV mode
+    assert_eq!(tree.search(&5), Some(6));
+}
+
+fn build_cell(mode: RluMode) {
+    let cell = RluCell::with_builder(vec![0u8; 64], GlobalRlu::builder().mode(mode));
+    let other = cell.clone_ref();
+    for i in 0..10 {
+        other.update(|v| v.push(i));
+    }
+    drop(other);
+    assert_eq!(cell.read(|v| v.len()), 74);
+}
+
+#[test]
+fn teardown_frees_everything() {
+    for &mode in &[RluMode::SingleVersion, RluMode::MultiVersion] {
+        // warm up anything lazily allocated once per process, like thread spawning
+        build_set(mode);
+        build_tree(mode);
+        build_cell(mode);
+
+        let before = live_bytes();
+        for _ in 0..5 {
+            build_set(mode);
+            build_tree(mode);
+            build_cell(mode);
+        }
+        assert_eq!(live_bytes(), before, "{:?} structures leaked", mode);
+    }
+
+    // a raw domain is the caller's to destroy, once no thread is registered
+    let before = live_bytes();
+    let rlu_ptr: *mut GlobalRlu<Item> = GlobalRlu::init_rlu();
+    let item = Box::into_raw(Box::new(Item {
+        hdr: RluObjHdr::new(),
+        name: String::from("item"),
+    }));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    {
+        let guard = handle.write();
+        let mut locked = guard.try_lock(guard.deref(item).unwrap())
+            .unwrap()
+            .unwrap();
+        locked.name.push_str(" renamed");
+    }
+    {
+        let guard = handle.write();
+        let locked = guard.try_lock(guard.deref(item).unwrap())
+            .unwrap()
+            .unwrap();
+        guard.free(locked).unwrap();
+    }
+    assert_eq!(unsafe { rlu_destroy(rlu_ptr) }, Err(RluError::DomainInUse));
+    drop(handle);
+    unsafe { rlu_destroy(rlu_ptr) }.unwrap();
+    assert_eq!(live_bytes(), before);
+
+    // so is a domain owned by handles, with the last one
+    let before = live_bytes();
+    let handle = RluHandle::new_domain(GlobalRlu::<Item>::builder());
+    let clones: Vec<_> = (0..4).map(|_| handle.clone_ref()).collect();
+    drop(handle);
+    drop(clones);
+    assert_eq!(live_bytes(), before);
+}
@@ -0,0 +1,179 @@
+use rlu::{
+    Backoff, ContentionPolicy, GlobalRlu, Immediate, RluError, RluHandle, RluMode, RluObj,
+    RluObjHdr, Yield,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(RluObj)]
+pub struct Account {
+    hdr: RluObjHdr<Account>,
+    balance: i64,
+}
+
+#[derive(Copy, Clone)]
+struct Accounts(*mut Account, *mut Account);
+
+unsafe impl Send for Accounts {}
+
+fn new_account(balance: i64) -> *mut Account {
+    Box::into_raw(Box::new(Account {
+        hdr: RluObjHdr::new(),
+        balance,
+    }))
+}
+
+#[derive(Default)]
+struct CountingPolicy {
+    conflicts: AtomicU32,
+}
+
+impl ContentionPolicy for CountingPolicy {
+    fn on_conflict(&self, attempt: u32) {
+        assert_eq!(attempt, self.conflicts.fetch_add(1, Ordering::SeqCst) + 1);
+    }
+}
+
+#[test]
+fn txn_commit_and_errors() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::init_rlu();
+    let account = new_account(10);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    let balance = handle
+        .transaction(|txn| {
+            let mut locked = txn.lock(txn.deref(account).unwrap())?;
+            locked.balance += 5;
+            Ok(locked.balance)
+        })
+        .unwrap();
+    assert_eq!(balance, 15);
+
+    // any error but Conflict aborts the section and is handed back
+    let result: Result<(), RluError> = handle.transaction(|txn| {
+        txn.lock(txn.deref(account).unwrap())?.balance = 0;
+        Err(RluError::NotLocked)
+    });
+    assert_eq!(result, Err(RluError::NotLocked));
+    assert_eq!(handle.read().deref(account).unwrap().balance, 15);
+
+    // the closure can ask to be re-run itself
+    let policy = CountingPolicy::default();
+    let mut runs = 0;
+    handle
+        .transaction_with(&policy, |_txn| {
+            runs += 1;
+            if runs < 4 {
+                Err(RluError::Conflict)
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap();
+    assert_eq!(runs, 4);
+    assert_eq!(policy.conflicts.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn txn_lock_conflict() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::init_rlu();
+    let account = new_account(0);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let other = handle.clone_ref();
+
+    let guard = other.write();
+    guard.try_lock(guard.deref(account).unwrap()).unwrap().unwrap();
+    let mut runs = 0;
+    let result = handle.transaction_with(&Immediate, |txn| {
+        runs += 1;
+        if runs == 3 {
+            return Ok(false);
+        }
+        txn.lock(txn.deref(account).unwrap())?;
+        Ok(true)
+    });
+    assert_eq!(result, Ok(false));
+    guard.abort();
+}
+
+fn transfers(mode: RluMode, policy: Arc<dyn ContentionPolicy>) {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::builder().mode(mode).init_rlu();
+    let accounts = Accounts(new_account(1000), new_account(1000));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    let mut workers = vec![];
+    for t in 0..4 {
+        let handle = handle.clone_ref();
+        let policy = policy.clone();
+        workers.push(thread::spawn(move || {
+            let accounts = accounts;
+            let (from, to) = if t % 2 == 0 {
+                (accounts.0, accounts.1)
+            } else {
+                (accounts.1, accounts.0)
+            };
+            for _ in 0..300 {
+                handle
+                    .transaction_with(&*policy, |txn| {
+                        let mut from = txn.lock(txn.deref(from).unwrap())?;
+                        let mut to = txn.lock(txn.deref(to).unwrap())?;
+                        from.balance -= 1;
+                        to.balance += 1;
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+        }));
+    }
+
+    // readers must never see money in flight
+    for _ in 0..300 {
+        let guard = handle.read();
+        let total = guard.deref(accounts.0).unwrap().balance
+            + guard.deref(accounts.1).unwrap().balance;
+        assert_eq!(total, 2000);
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let guard = handle.read();
+    assert_eq!(guard.deref(accounts.0).unwrap().balance, 1000);
+    assert_eq!(guard.deref(accounts.1).unwrap().balance, 1000);
+}
+
+#[test]
+fn txn_transfers_backoff() {
+    transfers(RluMode::SingleVersion, Arc::new(Backoff::default()));
+}
+
+#[test]
+fn txn_transfers_yield() {
+    transfers(RluMode::SingleVersion, Arc::new(Yield));
+}
+
+#[test]
+fn txn_transfers_mv_immediate() {
+    transfers(RluMode::MultiVersion, Arc::new(Immediate));
+}
+
+#[test]
+fn txn_domain_policy() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::builder()
+        .contention_policy(Backoff::new(1, 8))
+        .init_rlu();
+    let account = new_account(0);
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let mut runs = 0;
+    handle
+        .transaction(|txn| {
+            runs += 1;
+            if runs < 10 {
+                return Err(RluError::Conflict);
+            }
+            txn.lock(txn.deref(account).unwrap())?.balance = runs;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(handle.read().deref(account).unwrap().balance, 10);
+}
@@ -0,0 +1,110 @@
+use rlu::{GlobalRlu, RluHandle, RluObj, RluObjHdr, WaitStrategy};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(RluObj)]
+pub struct Account {
+    hdr: RluObjHdr<Account>,
+    balance: i64,
+}
+
+#[derive(Copy, Clone)]
+struct Accounts(*mut Account, *mut Account);
+
+unsafe impl Send for Accounts {}
+
+fn new_account(balance: i64) -> *mut Account {
+    Box::into_raw(Box::new(Account {
+        hdr: RluObjHdr::new(),
+        balance,
+    }))
+}
+
+// Several threads per core, so that writers regularly wait on readers that are not running
+fn oversubscribed(wait_strategy: WaitStrategy) {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get()) * 3;
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::builder()
+        .max_threads(threads + 1)
+        .wait_strategy(wait_strategy)
+        .init_rlu();
+    assert_eq!(unsafe { (*rlu_ptr).wait_strategy() }, wait_strategy);
+    let accounts = Accounts(new_account(1000), new_account(1000));
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+
+    let workers: Vec<_> = (0..threads)
+        .map(|t| {
+            let handle = handle.clone_ref();
+            thread::spawn(move || {
+                let accounts = accounts;
+                for _ in 0..100 {
+                    if t % 2 == 0 {
+                        handle
+                            .transaction(|txn| {
+                                txn.lock(txn.deref(accounts.0).unwrap())?.balance -= 1;
+                                txn.lock(txn.deref(accounts.1).unwrap())?.balance += 1;
+                                Ok(())
+                            })
+                            .unwrap();
+                    } else {
+                        let guard = handle.read();
+                        let total = guard.deref(accounts.0).unwrap().balance
+                            + guard.deref(accounts.1).unwrap().balance;
+                        assert_eq!(total, 2000);
+                        thread::yield_now(); //give writers someone to wait for
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let moved = threads.div_ceil(2) as i64 * 100;
+    assert_eq!(handle.read().deref(accounts.1).unwrap().balance, 1000 + moved);
+}
+
+#[test]
+fn wait_spin() {
+    oversubscribed(WaitStrategy::Spin);
+}
+
+#[test]
+fn wait_spin_then_yield() {
+    oversubscribed(WaitStrategy::SpinThenYield { spins: 64 });
+}
+
+#[test]
+fn wait_park() {
+    oversubscribed(WaitStrategy::Park { spins: 64 });
+}
+
+#[test]
+fn wait_park_wakes_writer() {
+    let rlu_ptr: *mut GlobalRlu<Account> = GlobalRlu::builder()
+        .wait_strategy(WaitStrategy::Park { spins: 0 })
+        .init_rlu();
+    let account = new_account(0) as usize;
+    let reader = unsafe { RluHandle::new(rlu_ptr) };
+    let writer = reader.clone_ref();
+    let committed = Arc::new(AtomicBool::new(false));
+
+    let guard = reader.read();
+    let worker = {
+        let committed = committed.clone();
+        thread::spawn(move || {
+            let account = account as *mut Account;
+            let guard = writer.write();
+            guard.try_lock(guard.deref(account).unwrap()).unwrap().unwrap().balance = 1;
+            drop(guard); //parks until the reader leaves
+            committed.store(true, Ordering::SeqCst);
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert!(!committed.load(Ordering::SeqCst));
+    drop(guard);
+    worker.join().unwrap();
+    assert!(committed.load(Ordering::SeqCst));
+    assert_eq!(reader.read().deref(account as *mut Account).unwrap().balance, 1);
+}
@@ -0,0 +1,173 @@
+use rlu::{
+    GlobalRlu, RluBlockingThread, RluError, RluHandle, RluObj, RluObjHdr, RluStallReport,
+    RluWaitKind, WaitStrategy,
+};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(RluObj)]
+pub struct Counter {
+    hdr: RluObjHdr<Counter>,
+    value: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Shared(*mut GlobalRlu<Counter>, *mut Counter);
+
+unsafe impl Send for Shared {}
+
+fn new_counter() -> *mut Counter {
+    Box::into_raw(Box::new(Counter {
+        hdr: RluObjHdr::new(),
+        value: 0,
+    }))
+}
+
+// A domain whose stall reports end up in the returned list
+fn watched(
+    wait_strategy: WaitStrategy,
+) -> (*mut GlobalRlu<Counter>, Arc<Mutex<Vec<RluStallReport>>>) {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let rlu_ptr = GlobalRlu::builder()
+        .max_threads(3)
+        .wait_strategy(wait_strategy)
+        .sync_timeout(Duration::from_millis(10))
+        .on_stall(move |report| sink.lock().unwrap().push(report.clone()))
+        .init_rlu();
+    (rlu_ptr, reports)
+}
+
+// Opens a section on another thread and keeps it open until told to leave
+fn stuck_reader(shared: Shared) -> (usize, mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (id_tx, id_rx) = mpsc::channel();
+    let (leave_tx, leave_rx) = mpsc::channel::<()>();
+    let reader = thread::spawn(move || {
+        let shared = shared;
+        let handle = unsafe { RluHandle::new(shared.0) };
+        let guard = handle.read();
+        id_tx.send(handle.id()).unwrap();
+        leave_rx.recv().unwrap();
+        assert_eq!(guard.deref(shared.1).unwrap().value, 0);
+    });
+    (id_rx.recv().unwrap(), leave_tx, reader)
+}
+
+#[test]
+fn watchdog_try_synchronize_times_out() {
+    let (rlu_ptr, reports) = watched(WaitStrategy::Spin);
+    let shared = Shared(rlu_ptr, new_counter());
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let (reader_id, leave, reader) = stuck_reader(shared);
+
+    let err = handle.try_synchronize(Duration::from_millis(20)).unwrap_err();
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(err, RluError::SyncTimeout(reports[0].clone()));
+        assert_eq!(reports[0].waiter, handle.id());
+        assert_eq!(reports[0].waiting_for, RluWaitKind::Readers);
+        assert_eq!(reports[0].write_clock, u64::MAX);
+        assert!(reports[0].waited >= Duration::from_millis(20));
+        assert_eq!(reports[0].blocking.len(), 1);
+        assert_eq!(reports[0].blocking[0].id, reader_id);
+        assert_eq!(reports[0].blocking[0].run_counter % 2, 1);
+    }
+
+    leave.send(()).unwrap();
+    reader.join().unwrap();
+    handle.try_synchronize(Duration::from_millis(20)).unwrap();
+    assert_eq!(reports.lock().unwrap().len(), 1);
+}
+
+// A deferred commit would wait for readers again with no bound, so try_synchronize leaves it
+// for flush
+#[test]
+fn watchdog_try_synchronize_leaves_deferred() {
+    let rlu_ptr = GlobalRlu::builder().defer_commits(10).init_rlu();
+    let counter = new_counter();
+    let writer = unsafe { RluHandle::new(rlu_ptr) };
+    let reader = writer.clone_ref();
+    {
+        let guard = writer.write();
+        guard.try_lock(guard.deref(counter).unwrap()).unwrap().unwrap().value = 1;
+    }
+
+    writer.try_synchronize(Duration::from_millis(20)).unwrap();
+    assert_eq!(reader.read().deref(counter).unwrap().value, 0);
+    writer.flush();
+    assert_eq!(reader.read().deref(counter).unwrap().value, 1);
+}
+
+fn commit_reports_stall(wait_strategy: WaitStrategy) {
+    let (rlu_ptr, reports) = watched(wait_strategy);
+    let shared = Shared(rlu_ptr, new_counter());
+    let handle = unsafe { RluHandle::new(rlu_ptr) };
+    let (reader_id, leave, reader) = stuck_reader(shared);
+
+    let writer = thread::spawn(move || {
+        let shared = shared;
+        let handle = unsafe { RluHandle::new(shared.0) };
+        let guard = handle.write();
+        guard.try_lock(guard.deref(shared.1).unwrap()).unwrap().unwrap().value += 1;
+    });
+    // the commit keeps waiting, and reports again each time the timeout passes
+    while reports.lock().unwrap().len() < 2 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!writer.is_finished());
+    leave.send(()).unwrap();
+    reader.join().unwrap();
+    writer.join().unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert!(reports[1].waited > reports[0].waited);
+    for report in reports.iter() {
+        assert_ne!(report.write_clock, u64::MAX);
+        assert_eq!(report.blocking[0].id, reader_id);
+        assert!(report.blocking[0].local_clock < report.write_clock);
+    }
+    assert_eq!(handle.read().deref(shared.1).unwrap().value, 1);
+}
+
+#[test]
+fn watchdog_commit_reports_stall() {
+    commit_reports_stall(WaitStrategy::Spin);
+    commit_reports_stall(WaitStrategy::SpinThenYield { spins: 16 });
+    commit_reports_stall(WaitStrategy::Park { spins: 16 });
+}
+
+#[test]
+fn watchdog_report_display() {
+    let mut report = RluStallReport {
+        waiter: 0,
+        waiting_for: RluWaitKind::Readers,
+        waited: Duration::from_millis(5),
+        write_clock: 8,
+        blocking: vec![
+            RluBlockingThread {
+                id: 1,
+                run_counter: 3,
+                local_clock: 7,
+            },
+            RluBlockingThread {
+                id: 4,
+                run_counter: 9,
+                local_clock: 6,
+            },
+        ],
+    };
+    assert_eq!(
+        report.to_string(),
+        "RLU thread 0 has waited 5ms for readers: thread 1 (run_counter 3, local_clock 7), \
+         thread 4 (run_counter 9, local_clock 6)"
+    );
+    report.waiting_for = RluWaitKind::WriterLock;
+    report.blocking.truncate(1);
+    assert_eq!(
+        report.to_string(),
+        "RLU thread 0 has waited 5ms for the writer lock: thread 1 (run_counter 3, local_clock 7)"
+    );
+}